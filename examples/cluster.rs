@@ -0,0 +1,48 @@
+use std::env;
+use std::error::Error;
+use std::sync::Arc;
+use std::time::Duration;
+
+use flicbtn::*;
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn Error>> {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let servers: Vec<&str> = if args.is_empty() {
+        vec!["127.0.0.1:5551", "127.0.0.1:5552"]
+    } else {
+        args.iter().map(|s| &s[..]).collect()
+    };
+
+    let cluster = FlicCluster::new(&servers)
+        .await?
+        .register_event_handler(cluster_handler(|server, event| {
+            if let Some(gesture) = event.gesture() {
+                println!(
+                    "{}: conn {} {:?}",
+                    server, gesture.conn_id, gesture.click_type
+                );
+            }
+        }))
+        .await;
+    let cluster1 = Arc::new(cluster);
+    let cluster2 = cluster1.clone();
+
+    let cmd = tokio::spawn(async move {
+        tokio::time::delay_for(Duration::from_secs(1)).await;
+        cluster1
+            .submit(Command::CreateConnectionChannel {
+                conn_id: 1,
+                bd_addr: "80:e4:da:76:fa:55".to_string(),
+                latency_mode: LatencyMode::NormalLatency,
+                auto_disconnect_time: 511,
+            })
+            .await;
+        tokio::time::delay_for(Duration::from_secs(2)).await;
+        cluster1.stop().await;
+    });
+    cluster2.listen().await;
+    cmd.await?;
+
+    Ok(())
+}
@@ -6,6 +6,9 @@ use flicbtn::*;
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn Error>> {
+    // RUST_LOG=flicbtn=debug shows the commands and events
+    tracing_subscriber::fmt::init();
+
     let event = event_handler(|event| {
         println!("ping response: {:?}", event);
     });
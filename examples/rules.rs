@@ -0,0 +1,32 @@
+use std::error::Error;
+use std::sync::Arc;
+use std::time::Duration;
+
+use flicbtn::*;
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn Error>> {
+    let engine = RuleEngine::from_file("rules.toml")?;
+
+    let client = FlicClient::new("127.0.0.1:5551")
+        .await?
+        .register_event_handler(engine.handler())
+        .await;
+    let client = Arc::new(client);
+
+    for command in engine.setup_commands() {
+        client.submit(command).await;
+    }
+
+    let watcher = {
+        let client = client.clone();
+        tokio::spawn(async move {
+            engine.watch(client, Duration::from_secs(2)).await;
+        })
+    };
+
+    client.listen().await;
+    watcher.await?;
+
+    Ok(())
+}
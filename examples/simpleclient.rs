@@ -26,12 +26,12 @@ async fn main() -> Result<()> {
         println!("*** Hello to the Flic2 Button Simple Client ***");
         println!("===============================================");
         client1.submit(Command::GetInfo).await;
-        println!("");
+        println!();
 
         loop {
             show_commands();
 
-            println!("");
+            println!();
             print!("-- Choose: ");
 
             let _ = stdout().flush();
@@ -90,7 +90,7 @@ async fn main() -> Result<()> {
                 }
             }
 
-            println!("");
+            println!();
         }
 
         client1.stop().await;
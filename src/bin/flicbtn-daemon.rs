@@ -0,0 +1,163 @@
+use std::env;
+use std::error::Error;
+use std::fs;
+use std::sync::Arc;
+use std::time::Duration;
+
+use serde::Deserialize;
+use tokio::sync::mpsc;
+use tracing::info;
+use tracing_subscriber::filter::LevelFilter;
+use tracing_subscriber::EnvFilter;
+
+use flicbtn::*;
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+struct DaemonConfig {
+    server: String,
+    max_concurrent: usize,
+    timeout_secs: u64,
+    connect_all: bool,
+    reload_secs: u64,
+    /// Address to serve `/metrics` on.
+    #[cfg(feature = "metrics")]
+    metrics_listen: Option<String>,
+}
+
+impl Default for DaemonConfig {
+    fn default() -> Self {
+        DaemonConfig {
+            server: "127.0.0.1:5551".to_string(),
+            max_concurrent: 4,
+            timeout_secs: 30,
+            connect_all: true,
+            reload_secs: 2,
+            #[cfg(feature = "metrics")]
+            metrics_listen: None,
+        }
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct DaemonFile {
+    #[serde(default)]
+    daemon: DaemonConfig,
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn Error>> {
+    tracing_subscriber::fmt()
+        .with_env_filter(
+            EnvFilter::builder()
+                .with_default_directive(LevelFilter::INFO.into())
+                .from_env_lossy(),
+        )
+        .with_writer(std::io::stderr)
+        .init();
+    let path = env::args()
+        .nth(1)
+        .unwrap_or_else(|| "flicbtn-daemon.toml".to_string());
+    let config: DaemonFile = toml::from_str(&fs::read_to_string(&path)?)?;
+    let config = config.daemon;
+
+    let engine = RuleEngine::from_file(&path)?.with_runner(CommandRunner::new(
+        config.max_concurrent,
+        Duration::from_secs(config.timeout_secs),
+    ));
+
+    let (verified_tx, mut verified_rx) = mpsc::unbounded_channel::<String>();
+    let connect_all = config.connect_all;
+    let monitor = event_handler(move |event| match event {
+        Event::GetInfoResponse(GetInfoResponse {
+            bd_addr_of_verified_buttons,
+            ..
+        }) if connect_all => {
+            for bd_addr in bd_addr_of_verified_buttons {
+                let _ = verified_tx.send(bd_addr.clone());
+            }
+        }
+        Event::NewVerifiedButton(NewVerifiedButton { bd_addr }) if connect_all => {
+            let _ = verified_tx.send(bd_addr.clone());
+        }
+        Event::CreateConnectionChannelResponse(CreateConnectionChannelResponse {
+            conn_id,
+            error,
+            connection_status,
+        }) => info!(conn_id, ?error, ?connection_status, "channel created"),
+        Event::ConnectionStatusChanged(ConnectionStatusChanged {
+            conn_id,
+            connection_status,
+            disconnect_reason,
+        }) => info!(
+            conn_id,
+            ?connection_status,
+            ?disconnect_reason,
+            "channel status changed"
+        ),
+        Event::ConnectionChannelRemoved(ConnectionChannelRemoved {
+            conn_id,
+            removed_reason,
+        }) => info!(conn_id, ?removed_reason, "channel removed"),
+        _ => {}
+    });
+
+    let client = FlicClient::new(&config.server)
+        .await?
+        .register_event_handler(engine.handler())
+        .await
+        .register_event_handler(monitor)
+        .await;
+    #[cfg(feature = "metrics")]
+    let client = match &config.metrics_listen {
+        Some(listen) => {
+            let metrics = Metrics::new()
+                .channels(client.channels())
+                .corrupt_packets(client.corrupt_packets());
+            let addr = listen.parse()?;
+            let server = metrics.clone();
+            tokio::spawn(async move {
+                if let Err(err) = server.serve(addr).await {
+                    tracing::warn!(error = %err, "metrics not served");
+                }
+            });
+            client
+                .register_event_handler(metrics.handler())
+                .await
+                .register_packet_handler(metrics.packet_handler())
+                .await
+        }
+        None => client,
+    };
+    let client = Arc::new(client);
+    info!(server = %config.server, "connected");
+
+    for command in engine.setup_commands() {
+        client.submit(command).await;
+    }
+    client.submit(Command::GetInfo).await;
+
+    {
+        let client = client.clone();
+        let engine = engine.clone();
+        tokio::spawn(async move {
+            while let Some(bd_addr) = verified_rx.recv().await {
+                for command in engine.add_button(&bd_addr) {
+                    client.submit(command).await;
+                }
+            }
+        });
+    }
+    let watcher = {
+        let client = client.clone();
+        let reload = Duration::from_secs(config.reload_secs);
+        tokio::spawn(async move {
+            engine.watch(client, reload).await;
+        })
+    };
+
+    client.listen().await;
+    watcher.await?;
+
+    Ok(())
+}
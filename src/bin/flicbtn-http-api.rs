@@ -0,0 +1,64 @@
+use std::env;
+use std::error::Error;
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use tracing_subscriber::filter::LevelFilter;
+use tracing_subscriber::EnvFilter;
+
+use flicbtn::*;
+
+fn usage() -> ! {
+    eprintln!("usage: flicbtn-http-api [--flicd HOST:PORT] [--listen ADDR:PORT] [--history N]");
+    std::process::exit(2);
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn Error>> {
+    tracing_subscriber::fmt()
+        .with_env_filter(
+            EnvFilter::builder()
+                .with_default_directive(LevelFilter::INFO.into())
+                .from_env_lossy(),
+        )
+        .with_writer(std::io::stderr)
+        .init();
+    let mut flicd = "127.0.0.1:5551".to_string();
+    let mut listen = "127.0.0.1:8080".to_string();
+    let mut history = 100;
+
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let value = args.next().unwrap_or_else(|| usage());
+        match &arg[..] {
+            "--flicd" => flicd = value,
+            "--listen" => listen = value,
+            "--history" => history = value.parse()?,
+            _ => usage(),
+        }
+    }
+    let addr: SocketAddr = listen.parse()?;
+
+    let client = FlicClient::new(&flicd).await?;
+    let feed = EventFeed::new()
+        .history(history)
+        .channels(client.channels());
+    let api = HttpApi::new().feed(feed);
+    let client = client.register_event_handler(api.handler()).await;
+    let client = Arc::new(client);
+
+    let server = {
+        let client = client.clone();
+        tokio::spawn(async move {
+            if let Err(err) = api.serve(client.clone(), addr).await {
+                eprintln!("http server failed: {}", err);
+            }
+            client.stop().await;
+        })
+    };
+
+    client.listen().await;
+    server.await?;
+
+    Ok(())
+}
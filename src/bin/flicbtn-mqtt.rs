@@ -0,0 +1,73 @@
+use std::env;
+use std::error::Error;
+use std::sync::Arc;
+
+use rumqttc::MqttOptions;
+use tracing_subscriber::filter::LevelFilter;
+use tracing_subscriber::EnvFilter;
+
+use flicbtn::*;
+
+fn usage() -> ! {
+    eprintln!("usage: flicbtn-mqtt [--flicd HOST:PORT] [--broker HOST:PORT] [--prefix PREFIX] [--client-id ID] [--homeassistant DISCOVERY_PREFIX]");
+    std::process::exit(2);
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn Error>> {
+    tracing_subscriber::fmt()
+        .with_env_filter(
+            EnvFilter::builder()
+                .with_default_directive(LevelFilter::INFO.into())
+                .from_env_lossy(),
+        )
+        .with_writer(std::io::stderr)
+        .init();
+    let mut flicd = "127.0.0.1:5551".to_string();
+    let mut broker = "127.0.0.1:1883".to_string();
+    let mut prefix = "flic".to_string();
+    let mut client_id = "flicbtn".to_string();
+    let mut discovery_prefix = None;
+
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let value = args.next().unwrap_or_else(|| usage());
+        match &arg[..] {
+            "--flicd" => flicd = value,
+            "--broker" => broker = value,
+            "--prefix" => prefix = value,
+            "--client-id" => client_id = value,
+            "--homeassistant" => discovery_prefix = Some(value),
+            _ => usage(),
+        }
+    }
+    let (host, port) = match broker.rfind(':') {
+        Some(idx) => (&broker[..idx], broker[idx + 1..].parse()?),
+        None => (&broker[..], 1883),
+    };
+
+    let mut bridge = MqttBridge::new(&prefix);
+    if let Some(discovery_prefix) = discovery_prefix {
+        bridge = bridge.home_assistant(&discovery_prefix);
+    }
+    let client = FlicClient::new(&flicd)
+        .await?
+        .register_event_handler(bridge.handler())
+        .await;
+    let client = Arc::new(client);
+
+    let mut options = MqttOptions::new(client_id, host, port);
+    options.set_keep_alive(30);
+
+    let runner = {
+        let client = client.clone();
+        tokio::spawn(async move {
+            bridge.run(client, options).await;
+        })
+    };
+
+    client.listen().await;
+    runner.await?;
+
+    Ok(())
+}
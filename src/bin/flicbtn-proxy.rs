@@ -0,0 +1,40 @@
+use std::env;
+use std::error::Error;
+
+use tracing_subscriber::filter::LevelFilter;
+use tracing_subscriber::EnvFilter;
+
+use flicbtn::*;
+
+fn usage() -> ! {
+    eprintln!("usage: flicbtn-proxy [--flicd HOST:PORT] [--listen ADDR:PORT]");
+    std::process::exit(2);
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn Error>> {
+    tracing_subscriber::fmt()
+        .with_env_filter(
+            EnvFilter::builder()
+                .with_default_directive(LevelFilter::INFO.into())
+                .from_env_lossy(),
+        )
+        .with_writer(std::io::stderr)
+        .init();
+    let mut flicd = "127.0.0.1:5551".to_string();
+    let mut listen = "127.0.0.1:5552".to_string();
+
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let value = args.next().unwrap_or_else(|| usage());
+        match &arg[..] {
+            "--flicd" => flicd = value,
+            "--listen" => listen = value,
+            _ => usage(),
+        }
+    }
+
+    FlicProxy::new(&flicd).run(&listen).await?;
+
+    Ok(())
+}
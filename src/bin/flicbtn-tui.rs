@@ -0,0 +1,481 @@
+use std::collections::{BTreeMap, HashMap};
+use std::convert::TryFrom;
+use std::env;
+use std::error::Error;
+use std::io;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use ratatui::crossterm::event::{self, KeyCode, KeyEventKind};
+use ratatui::layout::{Constraint, Layout};
+use ratatui::style::{Style, Stylize};
+use ratatui::text::Line;
+use ratatui::widgets::{Block, Paragraph, Row, Table, TableState};
+use ratatui::{DefaultTerminal, Frame};
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver};
+use tracing_subscriber::filter::LevelFilter;
+use tracing_subscriber::EnvFilter;
+
+use flicbtn::*;
+
+fn usage() -> ! {
+    eprintln!("usage: flicbtn-tui [--flicd HOST:PORT]");
+    std::process::exit(2);
+}
+
+const SCAN_ID: u32 = 1;
+const WIZARD_ID: u32 = 1;
+
+#[derive(Default)]
+struct Button {
+    name: Option<String>,
+    conn_id: Option<u32>,
+    status: Option<ConnectionStatus>,
+    battery: Option<u8>,
+    last_click: Option<(ClickType, Instant)>,
+    rssi: Option<i8>,
+}
+
+struct Dashboard {
+    server: String,
+    client: Arc<FlicClient>,
+    controller: Option<BluetoothControllerState>,
+    buttons: BTreeMap<String, Button>,
+    conns: HashMap<u32, String>,
+    listeners: HashMap<u32, String>,
+    next_id: u32,
+    table: TableState,
+    scanning: bool,
+    wizard: bool,
+    confirm_delete: Option<String>,
+    message: String,
+}
+
+impl Dashboard {
+    fn new(server: &str, client: Arc<FlicClient>) -> Dashboard {
+        Dashboard {
+            server: server.to_string(),
+            client,
+            controller: None,
+            buttons: BTreeMap::new(),
+            conns: HashMap::new(),
+            listeners: HashMap::new(),
+            next_id: 0,
+            table: TableState::default().with_selected(0),
+            scanning: false,
+            wizard: false,
+            confirm_delete: None,
+            message: String::new(),
+        }
+    }
+
+    fn selected(&self) -> Option<String> {
+        self.table
+            .selected()
+            .and_then(|idx| self.buttons.keys().nth(idx).cloned())
+    }
+
+    fn add(&mut self, bd_addr: &str) -> bool {
+        if self.buttons.contains_key(bd_addr) {
+            return false;
+        }
+        self.buttons.insert(bd_addr.to_string(), Button::default());
+        true
+    }
+
+    async fn connect(&mut self, bd_addr: &str) {
+        if self
+            .buttons
+            .get(bd_addr)
+            .is_none_or(|b| b.conn_id.is_some())
+        {
+            return;
+        }
+        self.next_id += 1;
+        let id = self.next_id;
+        self.conns.insert(id, bd_addr.to_string());
+        if let Some(button) = self.buttons.get_mut(bd_addr) {
+            button.conn_id = Some(id);
+        }
+        self.client
+            .submit(Command::CreateConnectionChannel {
+                conn_id: id,
+                bd_addr: bd_addr.to_string(),
+                latency_mode: LatencyMode::NormalLatency,
+                auto_disconnect_time: 511,
+            })
+            .await;
+        if !self.listeners.values().any(|b| b == bd_addr) {
+            self.listeners.insert(id, bd_addr.to_string());
+            self.client
+                .submit(Command::CreateBatteryStatusListener {
+                    listener_id: id,
+                    bd_addr: bd_addr.to_string(),
+                })
+                .await;
+        }
+    }
+
+    async fn disconnect(&mut self, bd_addr: &str) {
+        let conn_id = match self.buttons.get_mut(bd_addr) {
+            Some(button) => button.conn_id.take(),
+            None => None,
+        };
+        if let Some(conn_id) = conn_id {
+            self.client
+                .submit(Command::RemoveConnectionChannel { conn_id })
+                .await;
+        }
+    }
+
+    fn remove(&mut self, bd_addr: &str) {
+        self.buttons.remove(bd_addr);
+        self.conns.retain(|_, b| b != bd_addr);
+        self.listeners.retain(|_, b| b != bd_addr);
+        let last = self.buttons.len().saturating_sub(1);
+        if self.table.selected().is_some_and(|idx| idx > last) {
+            self.table.select(Some(last));
+        }
+    }
+
+    fn button_of_conn(&mut self, conn_id: u32) -> Option<&mut Button> {
+        let bd_addr = self.conns.get(&conn_id)?;
+        self.buttons.get_mut(bd_addr)
+    }
+
+    async fn on_event(&mut self, event: Event) {
+        if let Some(&ButtonEvent {
+            conn_id,
+            click_type,
+            ..
+        }) = event.gesture()
+        {
+            let pressed = matches!(click_type, ClickType::ButtonDown | ClickType::ButtonUp);
+            if let Some(button) = self.button_of_conn(conn_id).filter(|_| !pressed) {
+                button.last_click = Some((click_type, Instant::now()));
+            }
+            return;
+        }
+        match event {
+            Event::GetInfoResponse(GetInfoResponse {
+                bluetooth_controller_state,
+                bd_addr_of_verified_buttons,
+                ..
+            }) => {
+                self.controller = Some(bluetooth_controller_state);
+                for bd_addr in bd_addr_of_verified_buttons {
+                    self.add(&bd_addr);
+                    self.connect(&bd_addr).await;
+                }
+            }
+            Event::BluetoothControllerStateChange(BluetoothControllerStateChange { state }) => {
+                self.controller = Some(state)
+            }
+            Event::NewVerifiedButton(NewVerifiedButton { bd_addr }) => {
+                if self.add(&bd_addr) {
+                    self.message = format!("new button {}", bd_addr);
+                }
+                self.connect(&bd_addr).await;
+            }
+            Event::CreateConnectionChannelResponse(CreateConnectionChannelResponse {
+                conn_id,
+                error,
+                connection_status,
+            }) => {
+                if let Some(button) = self.button_of_conn(conn_id) {
+                    if error == CreateConnectionChannelError::NoError {
+                        button.status = Some(connection_status);
+                    } else {
+                        button.conn_id = None;
+                        self.message = format!("connecting failed: {:?}", error);
+                    }
+                }
+            }
+            Event::ConnectionStatusChanged(ConnectionStatusChanged {
+                conn_id,
+                connection_status,
+                ..
+            }) => {
+                if let Some(button) = self.button_of_conn(conn_id) {
+                    button.status = Some(connection_status);
+                }
+            }
+            Event::ConnectionChannelRemoved(ConnectionChannelRemoved { conn_id, .. }) => {
+                if let Some(button) = self.button_of_conn(conn_id) {
+                    button.conn_id = None;
+                    button.status = None;
+                }
+                self.conns.remove(&conn_id);
+            }
+            Event::BatteryStatus(BatteryStatus {
+                listener_id,
+                battery_percentage,
+                ..
+            }) => {
+                let bd_addr = self.listeners.get(&listener_id).cloned();
+                if let Some(button) = bd_addr.and_then(|b| self.buttons.get_mut(&b)) {
+                    button.battery = u8::try_from(battery_percentage).ok();
+                }
+            }
+            Event::AdvertisementPacket(AdvertisementPacket {
+                bd_addr,
+                name,
+                rssi,
+                ..
+            }) => {
+                if let Some(button) = self.buttons.get_mut(&bd_addr) {
+                    button.rssi = Some(rssi as i8);
+                    if !name.is_empty() {
+                        button.name = Some(name);
+                    }
+                }
+            }
+            Event::ScanWizardFoundPrivateButton(_) => {
+                self.message = "found a private button, hold it for 7 seconds".to_string()
+            }
+            Event::ScanWizardFoundPublicButton(ScanWizardFoundPublicButton {
+                bd_addr,
+                name,
+                ..
+            }) => self.message = format!("found {} {}, connecting", name, bd_addr),
+            Event::ScanWizardButtonConnected(_) => {
+                self.message = "connected, verifying".to_string()
+            }
+            Event::ScanWizardCompleted(ScanWizardCompleted { result, .. }) => {
+                self.wizard = false;
+                self.message = format!("scan wizard: {:?}", result);
+            }
+            Event::ButtonDeleted(ButtonDeleted { bd_addr, .. }) => {
+                self.remove(&bd_addr);
+                self.message = format!("deleted {}", bd_addr);
+            }
+            _ => {}
+        }
+    }
+
+    /// Handles a key, `false` to quit.
+    async fn on_key(&mut self, key: KeyCode) -> bool {
+        if let Some(bd_addr) = self.confirm_delete.take() {
+            if key == KeyCode::Char('y') {
+                self.client.submit(Command::DeleteButton { bd_addr }).await;
+            } else {
+                self.message.clear();
+            }
+            return true;
+        }
+        match key {
+            KeyCode::Char('q') | KeyCode::Esc => return false,
+            KeyCode::Up => self.table.select_previous(),
+            KeyCode::Down => self.table.select_next(),
+            KeyCode::Char('c') => {
+                if let Some(bd_addr) = self.selected() {
+                    self.connect(&bd_addr).await;
+                }
+            }
+            KeyCode::Char('d') => {
+                if let Some(bd_addr) = self.selected() {
+                    self.disconnect(&bd_addr).await;
+                }
+            }
+            KeyCode::Char('x') | KeyCode::Delete => {
+                if let Some(bd_addr) = self.selected() {
+                    self.message = format!("delete {}? (y/n)", bd_addr);
+                    self.confirm_delete = Some(bd_addr);
+                }
+            }
+            KeyCode::Char('w') => {
+                if self.wizard {
+                    self.client
+                        .submit(Command::CancelScanWizard {
+                            scan_wizard_id: WIZARD_ID,
+                        })
+                        .await;
+                } else {
+                    self.wizard = true;
+                    self.message = "press and hold the new button for 7 seconds".to_string();
+                    self.client
+                        .submit(Command::CreateScanWizard {
+                            scan_wizard_id: WIZARD_ID,
+                        })
+                        .await;
+                }
+            }
+            KeyCode::Char('s') => {
+                self.scanning = !self.scanning;
+                let command = if self.scanning {
+                    Command::CreateScanner { scan_id: SCAN_ID }
+                } else {
+                    Command::RemoveScanner { scan_id: SCAN_ID }
+                };
+                self.client.submit(command).await;
+            }
+            _ => {}
+        }
+        true
+    }
+
+    fn draw(&mut self, frame: &mut Frame) {
+        let [header, table, footer] = Layout::vertical([
+            Constraint::Length(3),
+            Constraint::Min(3),
+            Constraint::Length(4),
+        ])
+        .areas(frame.area());
+
+        let controller = self
+            .controller
+            .map_or("-".to_string(), |state| format!("{:?}", state));
+        let scanning = if self.scanning { "on" } else { "off" };
+        frame.render_widget(
+            Paragraph::new(format!(
+                "Server {}   Controller {}   Scanning {}",
+                self.server, controller, scanning
+            ))
+            .block(Block::bordered().title("flicd")),
+            header,
+        );
+
+        let rows: Vec<Row> = self
+            .buttons
+            .iter()
+            .map(|(bd_addr, button)| {
+                let status = match (button.conn_id, button.status) {
+                    (None, _) => "not connected".to_string(),
+                    (Some(_), None) => "pending".to_string(),
+                    (Some(_), Some(status)) => format!("{:?}", status),
+                };
+                let last_click = button.last_click.map_or("-".to_string(), |(click, at)| {
+                    let click = format!("{:?}", click);
+                    format!(
+                        "{} {}s ago",
+                        click.trim_start_matches("Button"),
+                        at.elapsed().as_secs()
+                    )
+                });
+                Row::new(vec![
+                    bd_addr.clone(),
+                    button.name.clone().unwrap_or_default(),
+                    status,
+                    button
+                        .battery
+                        .map_or("-".to_string(), |b| format!("{}%", b)),
+                    last_click,
+                    button
+                        .rssi
+                        .map_or("-".to_string(), |r| format!("{} dBm", r)),
+                ])
+            })
+            .collect();
+        let widths = [
+            Constraint::Length(17),
+            Constraint::Length(10),
+            Constraint::Length(13),
+            Constraint::Length(7),
+            Constraint::Min(20),
+            Constraint::Length(8),
+        ];
+        let buttons = Table::new(rows, widths)
+            .header(
+                Row::new(vec![
+                    "Button",
+                    "Name",
+                    "Status",
+                    "Battery",
+                    "Last click",
+                    "RSSI",
+                ])
+                .style(Style::new().bold()),
+            )
+            .block(Block::bordered().title("Buttons"))
+            .row_highlight_style(Style::new().reversed());
+        frame.render_stateful_widget(buttons, table, &mut self.table);
+
+        let keys = "↑/↓ select  c connect  d disconnect  x delete  w scan wizard  s scan  q quit";
+        frame.render_widget(
+            Paragraph::new(vec![Line::from(self.message.clone()), Line::from(keys)])
+                .block(Block::bordered()),
+            footer,
+        );
+    }
+
+    async fn run(
+        &mut self,
+        terminal: &mut DefaultTerminal,
+        events: &mut UnboundedReceiver<Event>,
+    ) -> io::Result<()> {
+        let (tx, mut keys) = unbounded_channel();
+        std::thread::spawn(move || loop {
+            match event::read() {
+                Ok(event::Event::Key(key)) if key.kind == KeyEventKind::Press => {
+                    if tx.send(key.code).is_err() {
+                        return;
+                    }
+                }
+                Ok(_) => {}
+                Err(_) => return,
+            }
+        });
+        let mut tick = tokio::time::interval(Duration::from_secs(1));
+
+        self.client.submit(Command::GetInfo).await;
+        loop {
+            terminal.draw(|frame| self.draw(frame))?;
+            tokio::select! {
+                event = events.recv() => match event {
+                    Some(event) => self.on_event(event).await,
+                    None => return Ok(()),
+                },
+                key = keys.recv() => match key {
+                    Some(key) if self.on_key(key).await => {}
+                    _ => return Ok(()),
+                },
+                _ = tick.tick() => {}
+            }
+        }
+    }
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn Error>> {
+    // the dashboard owns the terminal, log only when asked to
+    tracing_subscriber::fmt()
+        .with_env_filter(
+            EnvFilter::builder()
+                .with_default_directive(LevelFilter::OFF.into())
+                .from_env_lossy(),
+        )
+        .with_writer(std::io::stderr)
+        .init();
+    let mut flicd = "127.0.0.1:5551".to_string();
+
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let value = args.next().unwrap_or_else(|| usage());
+        match &arg[..] {
+            "--flicd" => flicd = value,
+            _ => usage(),
+        }
+    }
+
+    let (tx, mut events) = unbounded_channel();
+    let client = FlicClient::new(&flicd)
+        .await?
+        .register_event_handler(event_handler(move |event| {
+            let _ = tx.send(event.clone());
+        }))
+        .await;
+    let client = Arc::new(client);
+    {
+        let client = client.clone();
+        tokio::spawn(async move { client.listen().await });
+    }
+
+    let mut terminal = ratatui::init();
+    let result = Dashboard::new(&flicd, client.clone())
+        .run(&mut terminal, &mut events)
+        .await;
+    ratatui::restore();
+    client.stop().await;
+
+    Ok(result?)
+}
@@ -0,0 +1,419 @@
+use std::env;
+use std::error::Error;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use serde_json::{json, Map, Value};
+use tokio::signal::ctrl_c;
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver};
+use tokio::time::timeout;
+use tracing_subscriber::filter::LevelFilter;
+use tracing_subscriber::EnvFilter;
+
+use flicbtn::json::event_to_json;
+use flicbtn::*;
+
+const USAGE: &str = "usage: flicctl [--server HOST:PORT] [--json] COMMAND
+
+commands:
+    info                        server state and verified buttons
+    ping                        round trip time to the server
+    scan                        print advertisements until interrupted
+    wizard                      add a new button
+    connect ADDR [--profile normal|responsive|battery-saver]
+                 [--latency normal|low|high] [--auto-disconnect SECS]
+                                print the events of a button until interrupted
+    disconnect ADDR             force a button to disconnect
+    button-info ADDR            color, serial number, uuid and versions of a button
+    delete ADDR                 remove a verified button
+    battery ADDR                battery level of a button
+    watch                       print the events of all buttons until interrupted";
+
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+fn usage() -> ! {
+    eprintln!("{}", USAGE);
+    std::process::exit(2);
+}
+
+fn fail(message: &str) -> ! {
+    eprintln!("flicctl: {}", message);
+    std::process::exit(1);
+}
+
+#[derive(PartialEq, Eq, Copy, Clone)]
+enum Output {
+    Table,
+    Json,
+}
+
+fn cell(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.clone(),
+        Value::Array(values) => values.iter().map(cell).collect::<Vec<String>>().join(", "),
+        Value::Null => "-".to_string(),
+        value => value.to_string(),
+    }
+}
+
+fn fields(value: Value) -> Map<String, Value> {
+    match value {
+        Value::Object(map) => map,
+        _ => Map::new(),
+    }
+}
+
+/// Prints the answer of a command, as a field per row in table output.
+fn print_object(output: Output, value: Value) {
+    match output {
+        Output::Json => println!("{}", value),
+        Output::Table => {
+            let mut map = fields(value);
+            let width = map.keys().map(|k| k.len()).max().unwrap_or(0);
+            if let Some(name) = map.remove("event") {
+                println!("{:<width$}  {}", "event", cell(&name), width = width);
+            }
+            for (key, value) in &map {
+                println!("{:<width$}  {}", key, cell(value), width = width);
+            }
+        }
+    }
+}
+
+/// Prints one event of a stream, as a line per event in table output.
+fn print_event(output: Output, bd_addr: Option<String>, event: &Event) {
+    let mut value = event_to_json(event);
+    if let (Some(bd_addr), Value::Object(map)) = (&bd_addr, &mut value) {
+        map.entry("bd_addr").or_insert_with(|| json!(bd_addr));
+    }
+    match output {
+        Output::Json => println!("{}", value),
+        Output::Table => {
+            let mut map = fields(value);
+            let name = map.remove("event").map(|v| cell(&v)).unwrap_or_default();
+            let bd_addr = map.remove("bd_addr").map(|v| cell(&v)).unwrap_or_default();
+            let details: Vec<String> = map
+                .iter()
+                .map(|(key, value)| format!("{}={}", key, cell(value)))
+                .collect();
+            println!("{:<38} {:<17} {}", name, bd_addr, details.join(" "));
+        }
+    }
+}
+
+fn latency_mode(value: &str) -> LatencyMode {
+    match value {
+        "normal" => LatencyMode::NormalLatency,
+        "low" => LatencyMode::LowLatency,
+        "high" => LatencyMode::HighLatency,
+        _ => usage(),
+    }
+}
+
+struct Ctl {
+    client: Arc<FlicClient>,
+    events: UnboundedReceiver<Event>,
+    output: Output,
+}
+
+impl Ctl {
+    async fn connect(server: &str, output: Output) -> Result<Ctl, Box<dyn Error>> {
+        let (tx, events) = unbounded_channel();
+        let client = FlicClient::new(server)
+            .await?
+            .register_event_handler(event_handler(move |event| {
+                let _ = tx.send(event.clone());
+            }))
+            .await;
+        let client = Arc::new(client);
+        {
+            let client = client.clone();
+            tokio::spawn(async move { client.listen().await });
+        }
+        Ok(Ctl {
+            client,
+            events,
+            output,
+        })
+    }
+
+    /// Waits for the first event accepted by `f`.
+    async fn expect<F: Fn(&Event) -> bool>(&mut self, f: F) -> Event {
+        let deadline = Instant::now() + TIMEOUT;
+        loop {
+            let left = deadline.saturating_duration_since(Instant::now());
+            match timeout(left, self.events.recv()).await {
+                Ok(Some(event)) if f(&event) => return event,
+                Ok(Some(_)) => {}
+                Ok(None) => fail("connection to the server lost"),
+                Err(_) => fail("no answer from the server"),
+            }
+        }
+    }
+
+    /// Next event, `None` when interrupted.
+    async fn next(&mut self) -> Option<Event> {
+        tokio::select! {
+            event = self.events.recv() => match event {
+                Some(event) => Some(event),
+                None => fail("connection to the server lost"),
+            },
+            _ = ctrl_c() => None,
+        }
+    }
+
+    fn print(&self, event: &Event) {
+        print_object(self.output, event_to_json(event));
+    }
+
+    fn print_event(&self, event: &Event) {
+        print_event(self.output, self.client.channels().bd_addr_of(event), event);
+    }
+
+    async fn info(&mut self) {
+        self.client.submit(Command::GetInfo).await;
+        let event = self
+            .expect(|e| matches!(e, Event::GetInfoResponse(_)))
+            .await;
+        self.print(&event);
+    }
+
+    async fn ping(&mut self) {
+        let started = Instant::now();
+        self.client.submit(Command::Ping { ping_id: 1 }).await;
+        self.expect(|e| matches!(e, Event::PingResponse(PingResponse { ping_id: 1 })))
+            .await;
+        let ms = started.elapsed().as_secs_f64() * 1000.0;
+        print_object(self.output, json!({ "round_trip_ms": ms }));
+    }
+
+    async fn scan(&mut self) {
+        self.client
+            .submit(Command::CreateScanner { scan_id: 1 })
+            .await;
+        while let Some(event) = self.next().await {
+            if let Event::AdvertisementPacket(_) = event {
+                self.print_event(&event);
+            }
+        }
+        self.client
+            .submit(Command::RemoveScanner { scan_id: 1 })
+            .await;
+    }
+
+    async fn wizard(&mut self) {
+        self.client
+            .submit(Command::CreateScanWizard { scan_wizard_id: 1 })
+            .await;
+        eprintln!("press and hold the button for 7 seconds");
+        while let Some(event) = self.next().await {
+            match event {
+                Event::ScanWizardFoundPrivateButton(_) => {
+                    eprintln!("found a private button, keep holding it")
+                }
+                Event::ScanWizardFoundPublicButton(_) | Event::ScanWizardButtonConnected(_) => {
+                    self.print_event(&event)
+                }
+                Event::ScanWizardCompleted(ScanWizardCompleted { result, .. }) => {
+                    self.print_event(&event);
+                    if result != ScanWizardResult::WizardSuccess {
+                        std::process::exit(1);
+                    }
+                    return;
+                }
+                _ => {}
+            }
+        }
+        self.client
+            .submit(Command::CancelScanWizard { scan_wizard_id: 1 })
+            .await;
+    }
+
+    async fn open(&mut self, conn_id: u32, bd_addr: &str, profile: ConnectionProfile) {
+        self.client
+            .submit(profile.create_channel(conn_id, bd_addr))
+            .await;
+    }
+
+    async fn stream(&mut self) {
+        while let Some(event) = self.next().await {
+            match event {
+                Event::NoOp | Event::GetInfoResponse(_) => {}
+                Event::CreateConnectionChannelResponse(CreateConnectionChannelResponse {
+                    error,
+                    ..
+                }) if error != CreateConnectionChannelError::NoError => {
+                    self.print_event(&event);
+                    std::process::exit(1);
+                }
+                event => self.print_event(&event),
+            }
+        }
+    }
+
+    async fn watch(&mut self) {
+        self.client.submit(Command::GetInfo).await;
+        let buttons = match self
+            .expect(|e| matches!(e, Event::GetInfoResponse(_)))
+            .await
+        {
+            Event::GetInfoResponse(GetInfoResponse {
+                bd_addr_of_verified_buttons,
+                ..
+            }) => bd_addr_of_verified_buttons,
+            _ => vec![],
+        };
+        for (idx, bd_addr) in buttons.iter().enumerate() {
+            self.open(idx as u32 + 1, bd_addr, ConnectionProfile::default())
+                .await;
+        }
+        self.stream().await;
+    }
+
+    async fn button_info(&mut self, bd_addr: &str) {
+        self.client
+            .submit(Command::GetButtonInfo {
+                bd_addr: bd_addr.to_string(),
+            })
+            .await;
+        let event = self
+            .expect(|e| match e {
+                Event::GetButtonInfoResponse(GetButtonInfoResponse { bd_addr: b, .. }) => {
+                    b.eq_ignore_ascii_case(bd_addr)
+                }
+                _ => false,
+            })
+            .await;
+        self.print(&event);
+    }
+
+    async fn delete(&mut self, bd_addr: &str) {
+        self.client
+            .submit(Command::DeleteButton {
+                bd_addr: bd_addr.to_string(),
+            })
+            .await;
+        let event = self
+            .expect(|e| match e {
+                Event::ButtonDeleted(ButtonDeleted { bd_addr: b, .. }) => {
+                    b.eq_ignore_ascii_case(bd_addr)
+                }
+                _ => false,
+            })
+            .await;
+        self.print(&event);
+    }
+
+    async fn battery(&mut self, bd_addr: &str) {
+        self.client
+            .submit(Command::CreateBatteryStatusListener {
+                listener_id: 1,
+                bd_addr: bd_addr.to_string(),
+            })
+            .await;
+        let event = self
+            .expect(|e| {
+                matches!(
+                    e,
+                    Event::BatteryStatus(BatteryStatus { listener_id: 1, .. })
+                )
+            })
+            .await;
+        self.client
+            .submit(Command::RemoveBatteryStatusListener { listener_id: 1 })
+            .await;
+        let mut value = event_to_json(&event);
+        if let Value::Object(map) = &mut value {
+            map.insert("bd_addr".to_string(), json!(bd_addr));
+        }
+        print_object(self.output, value);
+    }
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn Error>> {
+    tracing_subscriber::fmt()
+        .with_env_filter(
+            EnvFilter::builder()
+                .with_default_directive(LevelFilter::INFO.into())
+                .from_env_lossy(),
+        )
+        .with_writer(std::io::stderr)
+        .init();
+    let mut server = "127.0.0.1:5551".to_string();
+    let mut output = Output::Table;
+    let mut profile = ConnectionProfile::default();
+    let mut latency = None;
+    let mut auto_disconnect = None;
+    let mut positional = vec![];
+
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match &arg[..] {
+            "--server" => server = args.next().unwrap_or_else(|| usage()),
+            "--json" => output = Output::Json,
+            "--profile" => {
+                profile = args
+                    .next()
+                    .and_then(|name| ConnectionProfile::named(&name))
+                    .unwrap_or_else(|| usage())
+            }
+            "--latency" => latency = Some(latency_mode(&args.next().unwrap_or_else(|| usage()))),
+            "--auto-disconnect" => {
+                auto_disconnect = args
+                    .next()
+                    .and_then(|secs| secs.parse().ok())
+                    .and_then(|secs| AutoDisconnect::from_wire(secs).ok())
+                    .or_else(|| usage())
+            }
+            "-h" | "--help" => usage(),
+            _ => positional.push(arg),
+        }
+    }
+
+    if let Some(latency) = latency {
+        profile = profile.with_latency_mode(latency);
+    }
+    if let Some(auto_disconnect) = auto_disconnect {
+        profile = profile
+            .with_auto_disconnect(auto_disconnect)
+            .unwrap_or_else(|err| fail(&err.to_string()));
+    }
+
+    let addr = positional.get(1).map(|addr| {
+        if !is_bd_addr(addr) {
+            usage()
+        }
+        addr.to_lowercase()
+    });
+
+    let mut ctl = Ctl::connect(&server, output).await?;
+    match (positional.first().map(|c| &c[..]), addr.as_deref()) {
+        (Some("info"), None) => ctl.info().await,
+        (Some("ping"), None) => ctl.ping().await,
+        (Some("scan"), None) => ctl.scan().await,
+        (Some("wizard"), None) => ctl.wizard().await,
+        (Some("watch"), None) => ctl.watch().await,
+        (Some("connect"), Some(addr)) => {
+            ctl.open(1, addr, profile).await;
+            ctl.stream().await;
+            ctl.client
+                .submit(Command::RemoveConnectionChannel { conn_id: 1 })
+                .await;
+        }
+        (Some("disconnect"), Some(addr)) => {
+            ctl.client
+                .submit(Command::ForceDisconnect {
+                    bd_addr: addr.to_string(),
+                })
+                .await
+        }
+        (Some("button-info"), Some(addr)) => ctl.button_info(addr).await,
+        (Some("delete"), Some(addr)) => ctl.delete(addr).await,
+        (Some("battery"), Some(addr)) => ctl.battery(addr).await,
+        _ => usage(),
+    }
+    ctl.client.stop().await;
+
+    Ok(())
+}
@@ -0,0 +1,187 @@
+use std::env;
+use std::error::Error;
+use std::time::Instant;
+
+use tokio::io::{AsyncRead, AsyncWrite, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tracing_subscriber::filter::LevelFilter;
+use tracing_subscriber::EnvFilter;
+
+use flicbtn::*;
+
+fn usage() -> ! {
+    eprintln!("usage: flicdump [--flicd HOST:PORT] [--listen ADDR:PORT]");
+    eprintln!("       flicdump --recording FILE");
+    std::process::exit(2);
+}
+
+fn hex_dump(packet: &[u8]) -> String {
+    packet
+        .chunks(16)
+        .enumerate()
+        .map(|(row, bytes)| {
+            let hex: Vec<String> = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+            let ascii: String = bytes
+                .iter()
+                .map(|b| match b {
+                    0x20..=0x7e => *b as char,
+                    _ => '.',
+                })
+                .collect();
+            format!("    {:04x}  {:<47}  |{}|", row * 16, hex.join(" "), ascii)
+        })
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+fn decode_event(packet: &[u8]) -> Option<Event> {
+    let mut mapper = ByteToEventMapper::new();
+    let mut result = EventResult::Pending;
+    for b in frame_packet(packet) {
+        result = mapper.map(b);
+    }
+    match result {
+        EventResult::Some(event) => Some(event),
+        _ => None,
+    }
+}
+
+/// Prints a packet without its length prefix.
+fn print_packet(millis: u64, source: &str, dir: PacketDirection, packet: &[u8]) {
+    let decoded = match dir {
+        PacketDirection::Outbound => Command::from_packet(packet).map(|cmd| format!("{:?}", cmd)),
+        PacketDirection::Inbound => decode_event(packet).map(|event| format!("{:?}", event)),
+    };
+    let arrow = match dir {
+        PacketDirection::Outbound => "->",
+        PacketDirection::Inbound => "<-",
+    };
+    let stamp = format!("{:>6}.{:03}", millis / 1000, millis % 1000);
+    match decoded {
+        Some(decoded) => println!("{} {} {} {}", stamp, source, arrow, decoded),
+        None => println!(
+            "{} {} {} corrupt packet ({} bytes)\n{}",
+            stamp,
+            source,
+            arrow,
+            packet.len(),
+            hex_dump(packet)
+        ),
+    }
+}
+
+async fn forward<R, W>(
+    mut reader: R,
+    mut writer: W,
+    started: Instant,
+    source: String,
+    dir: PacketDirection,
+) where
+    R: AsyncRead + Unpin,
+    W: AsyncWrite + Unpin,
+{
+    while let Ok(packet) = read_packet(&mut reader).await {
+        print_packet(started.elapsed().as_millis() as u64, &source, dir, &packet);
+        if writer.write_all(&frame_packet(&packet)).await.is_err() {
+            break;
+        }
+    }
+    let _ = writer.shutdown().await;
+}
+
+async fn intercept(flicd: &str, listen: &str) -> Result<(), Box<dyn Error>> {
+    let started = Instant::now();
+    let mut listener = TcpListener::bind(listen).await?;
+    let mut next_client = 0;
+    loop {
+        let (client, addr) = listener.accept().await?;
+        let upstream = match TcpStream::connect(flicd).await {
+            Ok(upstream) => upstream,
+            Err(err) => {
+                eprintln!("flicd not reachable at {}: {}", flicd, err);
+                continue;
+            }
+        };
+        next_client += 1;
+        let source = format!("client {}", next_client);
+        eprintln!("{} connected from {}", source, addr);
+        let (client_reader, client_writer) = client.into_split();
+        let (upstream_reader, upstream_writer) = upstream.into_split();
+        tokio::spawn(forward(
+            client_reader,
+            upstream_writer,
+            started,
+            source.clone(),
+            PacketDirection::Outbound,
+        ));
+        tokio::spawn(async move {
+            forward(
+                upstream_reader,
+                client_writer,
+                started,
+                source.clone(),
+                PacketDirection::Inbound,
+            )
+            .await;
+            eprintln!("{} disconnected", source);
+        });
+    }
+}
+
+fn dump_recording(path: &str) -> Result<(), Box<dyn Error>> {
+    let recording = Recording::load(path)?;
+    let mut inbound = vec![];
+    for packet in &recording.packets {
+        let bytes = packet.bytes();
+        if packet.dir == PacketDirection::Inbound {
+            // inbound packets may have been recorded as read from the socket
+            inbound.extend_from_slice(&bytes);
+            for framed in split_packets(&mut inbound) {
+                print_packet(packet.t, "recording", packet.dir, &framed[2..]);
+            }
+        } else if bytes.len() >= 2 {
+            print_packet(packet.t, "recording", packet.dir, &bytes[2..]);
+        } else {
+            print_packet(packet.t, "recording", packet.dir, &bytes);
+        }
+    }
+    if !inbound.is_empty() {
+        println!(
+            "truncated packet ({} bytes)\n{}",
+            inbound.len(),
+            hex_dump(&inbound)
+        );
+    }
+    Ok(())
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn Error>> {
+    tracing_subscriber::fmt()
+        .with_env_filter(
+            EnvFilter::builder()
+                .with_default_directive(LevelFilter::INFO.into())
+                .from_env_lossy(),
+        )
+        .with_writer(std::io::stderr)
+        .init();
+    let mut flicd = "127.0.0.1:5551".to_string();
+    let mut listen = "127.0.0.1:5553".to_string();
+    let mut recording = None;
+
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let value = args.next().unwrap_or_else(|| usage());
+        match &arg[..] {
+            "--flicd" => flicd = value,
+            "--listen" => listen = value,
+            "--recording" => recording = Some(value),
+            _ => usage(),
+        }
+    }
+
+    match recording {
+        Some(path) => dump_recording(&path),
+        None => intercept(&flicd, &listen).await,
+    }
+}
@@ -0,0 +1,135 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use super::commands::Command;
+use super::events::*;
+
+#[derive(Default)]
+struct Channels {
+    connections: HashMap<u32, String>,
+    listeners: HashMap<u32, String>,
+}
+
+/// Button addresses of the connection channels and battery listeners a
+/// client has created.
+///
+/// Button events only carry a `conn_id` and battery events a `listener_id`;
+/// this map resolves them to the button they belong to. It is kept up to
+/// date by `FlicClient::submit` and `FlicClient::listen`.
+#[derive(Clone, Default)]
+pub struct ChannelMap {
+    channels: Arc<Mutex<Channels>>,
+}
+
+impl ChannelMap {
+    pub fn new() -> ChannelMap {
+        ChannelMap::default()
+    }
+
+    pub fn bd_addr_of_conn(&self, conn_id: u32) -> Option<String> {
+        self.channels
+            .lock()
+            .unwrap()
+            .connections
+            .get(&conn_id)
+            .cloned()
+    }
+
+    pub fn bd_addr_of_listener(&self, listener_id: u32) -> Option<String> {
+        self.channels
+            .lock()
+            .unwrap()
+            .listeners
+            .get(&listener_id)
+            .cloned()
+    }
+
+    pub fn conn_id_of(&self, bd_addr: &str) -> Option<u32> {
+        self.channels
+            .lock()
+            .unwrap()
+            .connections
+            .iter()
+            .find(|(_, b)| b.eq_ignore_ascii_case(bd_addr))
+            .map(|(conn_id, _)| *conn_id)
+    }
+
+    /// All connection channels with their button.
+    pub fn connections(&self) -> Vec<(u32, String)> {
+        let mut connections: Vec<(u32, String)> = self
+            .channels
+            .lock()
+            .unwrap()
+            .connections
+            .iter()
+            .map(|(conn_id, bd_addr)| (*conn_id, bd_addr.clone()))
+            .collect();
+        connections.sort();
+        connections
+    }
+
+    /// The button an event is about, if known.
+    pub fn bd_addr_of(&self, event: &Event) -> Option<String> {
+        match event {
+            Event::AdvertisementPacket(AdvertisementPacket { bd_addr, .. })
+            | Event::NewVerifiedButton(NewVerifiedButton { bd_addr })
+            | Event::GetButtonInfoResponse(GetButtonInfoResponse { bd_addr, .. })
+            | Event::ScanWizardFoundPublicButton(ScanWizardFoundPublicButton { bd_addr, .. })
+            | Event::ButtonDeleted(ButtonDeleted { bd_addr, .. }) => Some(bd_addr.clone()),
+            Event::CreateConnectionChannelResponse(CreateConnectionChannelResponse {
+                conn_id,
+                ..
+            })
+            | Event::ConnectionStatusChanged(ConnectionStatusChanged { conn_id, .. })
+            | Event::ConnectionChannelRemoved(ConnectionChannelRemoved { conn_id, .. })
+            | Event::Button(ButtonEvent { conn_id, .. }) => self.bd_addr_of_conn(*conn_id),
+            Event::BatteryStatus(BatteryStatus { listener_id, .. }) => {
+                self.bd_addr_of_listener(*listener_id)
+            }
+            _ => None,
+        }
+    }
+
+    pub fn track_command(&self, command: &Command) {
+        let mut channels = self.channels.lock().unwrap();
+        match command {
+            Command::CreateConnectionChannel {
+                conn_id, bd_addr, ..
+            } => {
+                channels
+                    .connections
+                    .insert(*conn_id, bd_addr.to_lowercase());
+            }
+            Command::RemoveConnectionChannel { conn_id } => {
+                channels.connections.remove(conn_id);
+            }
+            Command::CreateBatteryStatusListener {
+                listener_id,
+                bd_addr,
+            } => {
+                channels
+                    .listeners
+                    .insert(*listener_id, bd_addr.to_lowercase());
+            }
+            Command::RemoveBatteryStatusListener { listener_id } => {
+                channels.listeners.remove(listener_id);
+            }
+            _ => {}
+        }
+    }
+
+    pub fn track_event(&self, event: &Event) {
+        let mut channels = self.channels.lock().unwrap();
+        match event {
+            Event::ConnectionChannelRemoved(ConnectionChannelRemoved { conn_id, .. }) => {
+                channels.connections.remove(conn_id);
+            }
+            Event::ButtonDeleted(ButtonDeleted { bd_addr, .. }) => {
+                let bd_addr = bd_addr.to_lowercase();
+                channels.connections.retain(|_, b| *b != bd_addr);
+                channels.listeners.retain(|_, b| *b != bd_addr);
+            }
+            _ => {}
+        }
+    }
+}
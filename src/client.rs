@@ -1,17 +1,28 @@
+use std::sync::atomic::AtomicU64;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
 use futures::future::poll_fn;
 use futures::task::Poll;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
 use tokio::io::*;
 use tokio::net::tcp::{OwnedReadHalf, OwnedWriteHalf};
 use tokio::net::TcpStream;
-use tokio::sync::Mutex;
+use tokio::sync::{watch, Mutex};
+use tracing::field::Empty;
+use tracing::{debug, debug_span, info, info_span, trace, warn, Instrument, Span};
 
+use super::channels::ChannelMap;
 use super::commands::stream_mapper::CommandToByteMapper;
 use super::commands::Command;
 use super::events::stream_mapper::*;
-use super::events::Event;
+use super::events::*;
+use super::filter::EventFilter;
+use super::health::*;
 
-type EventClosure = dyn FnMut(&Event) + Sync + Send + 'static;
-type EventClosureMutex = Box<EventClosure>;
+pub type EventClosure = dyn FnMut(&Event) + Sync + Send + 'static;
+pub type EventClosureMutex = Box<EventClosure>;
 
 pub fn event_handler<F>(f: F) -> EventClosureMutex
 where
@@ -20,35 +31,223 @@ where
     Box::new(f)
 }
 
+/// Whether bytes were received from or sent to flicd.
+#[derive(Debug, PartialEq, Eq, Hash, Copy, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
+pub enum PacketDirection {
+    Inbound,
+    Outbound,
+}
+
+pub type PacketClosure = dyn FnMut(PacketDirection, &[u8]) + Sync + Send + 'static;
+pub type PacketClosureMutex = Box<PacketClosure>;
+
+/// Wraps a closure seeing the raw bytes exchanged with flicd.
+///
+/// Outbound bytes are always one complete packet; inbound bytes are passed
+/// as they are read and may hold partial or several packets.
+pub fn packet_handler<F>(f: F) -> PacketClosureMutex
+where
+    F: FnMut(PacketDirection, &[u8]) + Sync + Send + 'static,
+{
+    Box::new(f)
+}
+
+/// Records the ids and button of a command or event on its span, so logs
+/// can be filtered by them.
+fn record_ids(span: &Span, ids: Vec<(&'static str, u32)>, bd_addr: Option<&str>) {
+    for (name, id) in ids {
+        span.record(name, id);
+    }
+    if let Some(bd_addr) = bd_addr {
+        span.record("bd_addr", bd_addr);
+    }
+}
+
+fn command_ids(cmd: &Command) -> Vec<(&'static str, u32)> {
+    match *cmd {
+        Command::CreateScanner { scan_id } | Command::RemoveScanner { scan_id } => {
+            vec![("scan_id", scan_id)]
+        }
+        Command::CreateConnectionChannel { conn_id, .. }
+        | Command::RemoveConnectionChannel { conn_id }
+        | Command::ChangeModeParameters { conn_id, .. } => vec![("conn_id", conn_id)],
+        Command::Ping { ping_id } => vec![("ping_id", ping_id)],
+        Command::CreateScanWizard { scan_wizard_id }
+        | Command::CancelScanWizard { scan_wizard_id } => {
+            vec![("scan_wizard_id", scan_wizard_id)]
+        }
+        Command::CreateBatteryStatusListener { listener_id, .. }
+        | Command::RemoveBatteryStatusListener { listener_id } => {
+            vec![("listener_id", listener_id)]
+        }
+        _ => vec![],
+    }
+}
+
+fn command_bd_addr(cmd: &Command) -> Option<&str> {
+    match cmd {
+        Command::CreateConnectionChannel { bd_addr, .. }
+        | Command::ForceDisconnect { bd_addr }
+        | Command::GetButtonInfo { bd_addr }
+        | Command::DeleteButton { bd_addr }
+        | Command::CreateBatteryStatusListener { bd_addr, .. } => Some(bd_addr),
+        _ => None,
+    }
+}
+
+fn event_ids(event: &Event) -> Vec<(&'static str, u32)> {
+    match *event {
+        Event::AdvertisementPacket(AdvertisementPacket { scan_id, .. }) => {
+            vec![("scan_id", scan_id)]
+        }
+        Event::CreateConnectionChannelResponse(CreateConnectionChannelResponse {
+            conn_id, ..
+        })
+        | Event::ConnectionStatusChanged(ConnectionStatusChanged { conn_id, .. })
+        | Event::ConnectionChannelRemoved(ConnectionChannelRemoved { conn_id, .. })
+        | Event::Button(ButtonEvent { conn_id, .. }) => vec![("conn_id", conn_id)],
+        Event::PingResponse(PingResponse { ping_id }) => vec![("ping_id", ping_id)],
+        Event::ScanWizardFoundPrivateButton(ScanWizardFoundPrivateButton { scan_wizard_id })
+        | Event::ScanWizardFoundPublicButton(ScanWizardFoundPublicButton {
+            scan_wizard_id, ..
+        })
+        | Event::ScanWizardButtonConnected(ScanWizardButtonConnected { scan_wizard_id })
+        | Event::ScanWizardCompleted(ScanWizardCompleted { scan_wizard_id, .. }) => {
+            vec![("scan_wizard_id", scan_wizard_id)]
+        }
+        Event::BatteryStatus(BatteryStatus { listener_id, .. }) => {
+            vec![("listener_id", listener_id)]
+        }
+        _ => vec![],
+    }
+}
+
+/// Logs with `tracing`: a `connect` span around connecting, a `submit`
+/// span per command and an `event` span per event. Their `conn_id`,
+/// `bd_addr` and other id fields allow filtering by button.
 pub struct FlicClient {
+    server: String,
     reader: Mutex<OwnedReadHalf>,
     writer: Mutex<OwnedWriteHalf>,
     is_running: Mutex<bool>,
+    stop_signal: watch::Sender<bool>,
+    stopped: watch::Receiver<bool>,
     command_mapper: Mutex<CommandToByteMapper>,
     event_mapper: Mutex<ByteToEventMapper>,
+    corrupt_packets: Arc<AtomicU64>,
     map: Mutex<Vec<EventClosureMutex>>,
+    packet_handlers: Mutex<Vec<PacketClosureMutex>>,
+    health_handlers: Mutex<Vec<HealthClosureMutex>>,
+    heartbeat: std::sync::Mutex<Option<Heartbeat>>,
+    channels: ChannelMap,
 }
 
 impl FlicClient {
     pub async fn new(conn: &str) -> Result<FlicClient> {
-        TcpStream::connect(conn)
-            .await
-            .map(|s| s.into_split())
-            .map(|(reader, writer)| FlicClient {
+        let span = info_span!("connect", server = conn);
+        async {
+            match TcpStream::connect(conn).await {
+                Ok(stream) => {
+                    info!("connected");
+                    Ok(stream.into_split())
+                }
+                Err(err) => {
+                    warn!(error = %err, "connecting failed");
+                    Err(err)
+                }
+            }
+        }
+        .instrument(span)
+        .await
+        .map(|(reader, writer)| {
+            let event_mapper = ByteToEventMapper::new();
+            let (stop_signal, stopped) = watch::channel(false);
+            FlicClient {
+                server: conn.to_string(),
                 reader: Mutex::new(reader),
                 writer: Mutex::new(writer),
                 is_running: Mutex::new(true),
+                stop_signal,
+                stopped,
                 command_mapper: Mutex::new(CommandToByteMapper::new()),
-                event_mapper: Mutex::new(ByteToEventMapper::new()),
+                corrupt_packets: event_mapper.corrupt_packets(),
+                event_mapper: Mutex::new(event_mapper),
                 map: Mutex::new(vec![]),
-            })
+                packet_handlers: Mutex::new(vec![]),
+                health_handlers: Mutex::new(vec![]),
+                heartbeat: std::sync::Mutex::new(None),
+                channels: ChannelMap::new(),
+            }
+        })
     }
     pub async fn register_event_handler(self, event: EventClosureMutex) -> Self {
         self.map.lock().await.push(event);
         self
     }
+    /// Registers an event handler seeing only the events passing a filter.
+    pub async fn on(self, filter: EventFilter, handler: EventClosureMutex) -> Self {
+        let handler = filter.handler(self.channels(), handler);
+        self.register_event_handler(handler).await
+    }
+    pub async fn register_packet_handler(self, handler: PacketClosureMutex) -> Self {
+        self.packet_handlers.lock().await.push(handler);
+        self
+    }
+    pub async fn register_health_handler(self, handler: HealthClosureMutex) -> Self {
+        self.health_handlers.lock().await.push(handler);
+        self
+    }
+
+    /// Pings the server every `interval` while listening. After `max_missed`
+    /// pings in a row without a response the server is declared dead: the
+    /// health handlers are called and `listen` returns, so the caller can
+    /// connect again.
+    ///
+    /// Heartbeat pings use ids from `0x8000_0000` on; their responses are
+    /// passed to the event handlers like any other.
+    pub fn heartbeat(self, interval: Duration, max_missed: u32) -> Self {
+        *self.heartbeat.lock().unwrap() = Some(Heartbeat::new(interval, max_missed));
+        self
+    }
+
+    /// Round trip times and missed pings of the heartbeat. Always alive
+    /// without a heartbeat.
+    pub fn health(&self) -> Health {
+        self.heartbeat
+            .lock()
+            .unwrap()
+            .as_ref()
+            .map(Heartbeat::health)
+            .unwrap_or_default()
+    }
+
+    /// Sends a heartbeat ping if one is due, false once the server is dead.
+    async fn beat(&self) -> bool {
+        let (ping_id, died) = match self.heartbeat.lock().unwrap().as_mut() {
+            Some(heartbeat) => heartbeat.due(Instant::now()),
+            None => return true,
+        };
+        if let Some(health) = died {
+            warn!(server = %self.server, missed = health.missed, "server not responding");
+            for f in self.health_handlers.lock().await.iter_mut() {
+                f(&health);
+            }
+            self.stop().await;
+            return false;
+        }
+        if let Some(ping_id) = ping_id {
+            self.submit(Command::Ping { ping_id }).await;
+        }
+        true
+    }
+
     pub async fn listen(&self) {
         while *self.is_running.lock().await {
+            if !self.beat().await {
+                break;
+            }
             let mut reader = self.reader.lock().await;
             if let Ok(size) = poll_fn(|cx| {
                 let mut buf = [0; 1];
@@ -61,32 +260,118 @@ impl FlicClient {
             {
                 if size > 0 {
                     let mut buffer = vec![];
-                    if let Some(_) = reader.read_buf(&mut buffer).await.ok() {
+                    let read = reader.read_buf(&mut buffer).await;
+                    if let Err(err) = &read {
+                        warn!(server = %self.server, error = %err, "reading failed");
+                    }
+                    if read.is_ok() {
+                        for f in self.packet_handlers.lock().await.iter_mut() {
+                            f(PacketDirection::Inbound, &buffer);
+                        }
                         for b in buffer.iter() {
-                            match self.event_mapper.lock().await.map(*b) {
-                                EventResult::Some(Event::NoOp) => {}
-                                EventResult::Some(event) => {
-                                    let mut map = self.map.lock().await;
-                                    for ref mut f in &mut *map {
-                                        f(&event);
-                                    }
-                                }
-                                _ => {}
-                            }
+                            let event = match self.event_mapper.lock().await.map(*b) {
+                                EventResult::Some(Event::NoOp) => continue,
+                                EventResult::Some(event) => event,
+                                _ => continue,
+                            };
+                            self.dispatch(event).await;
                         }
                     }
                 }
             }
         }
     }
+    async fn dispatch(&self, event: Event) {
+        let bd_addr = self.channels.bd_addr_of(&event);
+        let span = debug_span!(
+            "event",
+            server = %self.server,
+            event = event.name(),
+            conn_id = Empty,
+            scan_id = Empty,
+            ping_id = Empty,
+            scan_wizard_id = Empty,
+            listener_id = Empty,
+            bd_addr = Empty,
+        );
+        record_ids(&span, event_ids(&event), bd_addr.as_deref());
+        async {
+            debug!(?event, "received");
+            if let Event::PingResponse(PingResponse { ping_id }) = event {
+                if let Some(heartbeat) = self.heartbeat.lock().unwrap().as_mut() {
+                    heartbeat.response(ping_id, Instant::now());
+                }
+            }
+            self.channels.track_event(&event);
+            let mut map = self.map.lock().await;
+            let started = Instant::now();
+            for ref mut f in &mut *map {
+                f(&event);
+            }
+            trace!(
+                handlers = map.len(),
+                elapsed_us = started.elapsed().as_micros() as u64,
+                "dispatched"
+            );
+        }
+        .instrument(span)
+        .await
+    }
+    pub async fn is_running(&self) -> bool {
+        *self.is_running.lock().await
+    }
     pub async fn stop(&self) {
         *self.is_running.lock().await = false;
+        let _ = self.stop_signal.broadcast(true);
+    }
+
+    /// Resolves once the client is stopped.
+    pub async fn stopped(&self) {
+        let mut stopped = self.stopped.clone();
+        while let Some(is_stopped) = stopped.recv().await {
+            if is_stopped {
+                return;
+            }
+        }
+    }
+
+    /// The number of packets from flicd that could not be decoded.
+    pub fn corrupt_packets(&self) -> Arc<AtomicU64> {
+        self.corrupt_packets.clone()
+    }
+
+    /// Button addresses of the channels created through this client.
+    pub fn channels(&self) -> ChannelMap {
+        self.channels.clone()
     }
 
     pub async fn submit(&self, cmd: Command) {
-        let mut writer = self.writer.lock().await;
-        for b in self.command_mapper.lock().await.map(cmd) {
-            let _ = writer.write_u8(b).await;
+        let span = debug_span!(
+            "submit",
+            server = %self.server,
+            opcode = Empty,
+            conn_id = Empty,
+            scan_id = Empty,
+            ping_id = Empty,
+            scan_wizard_id = Empty,
+            listener_id = Empty,
+            bd_addr = Empty,
+        );
+        record_ids(&span, command_ids(&cmd), command_bd_addr(&cmd));
+        async {
+            self.channels.track_command(&cmd);
+            let mut writer = self.writer.lock().await;
+            Span::current().record("opcode", cmd.opcode());
+            debug!(command = ?cmd, "submitting");
+            let bytes: Vec<u8> = self.command_mapper.lock().await.map(cmd).collect();
+            for f in self.packet_handlers.lock().await.iter_mut() {
+                f(PacketDirection::Outbound, &bytes);
+            }
+            if let Err(err) = writer.write_all(&bytes).await {
+                warn!(error = %err, "writing failed");
+            }
         }
+        .instrument(span)
+        .await
     }
 }
@@ -0,0 +1,274 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use futures::future::join_all;
+use tokio::io::Result;
+
+use super::channels::ChannelMap;
+use super::client::*;
+use super::commands::Command;
+use super::enums::ClickType;
+use super::events::*;
+
+pub type ClusterClosure = dyn FnMut(&str, &Event) + Sync + Send + 'static;
+pub type ClusterClosureMutex = Box<ClusterClosure>;
+
+/// Wraps a closure receiving the address of the originating server and the
+/// event.
+pub fn cluster_handler<F>(f: F) -> ClusterClosureMutex
+where
+    F: FnMut(&str, &Event) + Sync + Send + 'static,
+{
+    Box::new(f)
+}
+
+type ClickKey = (String, ButtonEventKind, ClickType);
+
+struct ClusterState {
+    verified: HashMap<String, HashSet<String>>,
+    conn_ids: HashMap<u32, Vec<String>>,
+    listener_ids: HashMap<u32, Vec<String>>,
+    clicks: HashMap<ClickKey, (String, Instant)>,
+    dedup_window: Duration,
+}
+
+impl ClusterState {
+    fn track(&mut self, server: &str, event: &Event) {
+        match event {
+            Event::GetInfoResponse(GetInfoResponse {
+                bd_addr_of_verified_buttons,
+                ..
+            }) => {
+                self.verified.retain(|_, servers| {
+                    servers.remove(server);
+                    !servers.is_empty()
+                });
+                for bd_addr in bd_addr_of_verified_buttons {
+                    self.verified
+                        .entry(bd_addr.to_lowercase())
+                        .or_default()
+                        .insert(server.to_string());
+                }
+            }
+            Event::NewVerifiedButton(NewVerifiedButton { bd_addr }) => {
+                self.verified
+                    .entry(bd_addr.to_lowercase())
+                    .or_default()
+                    .insert(server.to_string());
+            }
+            Event::ButtonDeleted(ButtonDeleted { bd_addr, .. }) => {
+                let bd_addr = bd_addr.to_lowercase();
+                if let Some(servers) = self.verified.get_mut(&bd_addr) {
+                    servers.remove(server);
+                    if servers.is_empty() {
+                        self.verified.remove(&bd_addr);
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Whether a click was already delivered by another server.
+    fn is_duplicate(&mut self, server: &str, bd_addr: Option<String>, event: &Event) -> bool {
+        let (bd_addr, gesture) = match (bd_addr, event.gesture()) {
+            (Some(bd_addr), Some(gesture)) => (bd_addr, gesture),
+            _ => return false,
+        };
+        let now = Instant::now();
+        let window = self.dedup_window;
+        self.clicks
+            .retain(|_, (_, seen)| now.duration_since(*seen) < window);
+        let key = (bd_addr, gesture.kind, gesture.click_type);
+        match self.clicks.get(&key) {
+            Some((origin, _)) if origin != server => true,
+            _ => {
+                self.clicks.insert(key, (server.to_string(), now));
+                false
+            }
+        }
+    }
+}
+
+/// Several flicd servers behind one client.
+///
+/// Events of all servers are passed to the handlers together with the
+/// address of the server they came from. Commands for a button go to every
+/// server the button is verified on, so it can connect to whichever is in
+/// range, and commands referring to a connection channel or battery listener
+/// go to the servers it was created on. Other commands go to all servers.
+///
+/// A click of a button connected to more than one server is only delivered
+/// once: the same gesture of the same button arriving from another server
+/// within the dedup window (500 ms by default) is dropped.
+pub struct FlicCluster {
+    servers: Vec<(String, Arc<FlicClient>)>,
+    state: Arc<Mutex<ClusterState>>,
+    handlers: Arc<Mutex<Vec<ClusterClosureMutex>>>,
+}
+
+impl FlicCluster {
+    pub async fn new(servers: &[&str]) -> Result<FlicCluster> {
+        let state = Arc::new(Mutex::new(ClusterState {
+            verified: HashMap::new(),
+            conn_ids: HashMap::new(),
+            listener_ids: HashMap::new(),
+            clicks: HashMap::new(),
+            dedup_window: Duration::from_millis(500),
+        }));
+        let handlers: Arc<Mutex<Vec<ClusterClosureMutex>>> = Arc::new(Mutex::new(vec![]));
+        let mut clients = vec![];
+        for server in servers {
+            let client = FlicClient::new(server).await?;
+            let channels = client.channels();
+            let handler = Self::dispatch(server, channels, state.clone(), handlers.clone());
+            let client = client.register_event_handler(handler).await;
+            client.submit(Command::GetInfo).await;
+            clients.push((server.to_string(), Arc::new(client)));
+        }
+        Ok(FlicCluster {
+            servers: clients,
+            state,
+            handlers,
+        })
+    }
+
+    fn dispatch(
+        server: &str,
+        channels: ChannelMap,
+        state: Arc<Mutex<ClusterState>>,
+        handlers: Arc<Mutex<Vec<ClusterClosureMutex>>>,
+    ) -> EventClosureMutex {
+        let server = server.to_string();
+        event_handler(move |event| {
+            {
+                let mut state = state.lock().unwrap();
+                state.track(&server, event);
+                if state.is_duplicate(&server, channels.bd_addr_of(event), event) {
+                    return;
+                }
+            }
+            for handler in handlers.lock().unwrap().iter_mut() {
+                handler(&server, event);
+            }
+        })
+    }
+
+    pub async fn register_event_handler(self, handler: ClusterClosureMutex) -> Self {
+        self.handlers.lock().unwrap().push(handler);
+        self
+    }
+
+    /// How long a click from one server suppresses the same click from
+    /// another.
+    pub fn dedup_window(self, window: Duration) -> Self {
+        self.state.lock().unwrap().dedup_window = window;
+        self
+    }
+
+    /// Addresses of the servers.
+    pub fn servers(&self) -> Vec<String> {
+        self.servers
+            .iter()
+            .map(|(server, _)| server.clone())
+            .collect()
+    }
+
+    /// Servers a button is verified on.
+    pub fn servers_of(&self, bd_addr: &str) -> Vec<String> {
+        let state = self.state.lock().unwrap();
+        let mut servers: Vec<String> = state
+            .verified
+            .get(&bd_addr.to_lowercase())
+            .map(|servers| servers.iter().cloned().collect())
+            .unwrap_or_default();
+        servers.sort();
+        servers
+    }
+
+    pub fn client(&self, server: &str) -> Option<Arc<FlicClient>> {
+        self.servers
+            .iter()
+            .find(|(s, _)| s == server)
+            .map(|(_, client)| client.clone())
+    }
+
+    /// Listens on all servers, each on its own task, until stopped.
+    pub async fn listen(&self) {
+        let listeners = self.servers.iter().map(|(_, client)| {
+            let client = client.clone();
+            tokio::spawn(async move { client.listen().await })
+        });
+        join_all(listeners).await;
+    }
+
+    pub async fn stop(&self) {
+        for (_, client) in &self.servers {
+            client.stop().await;
+        }
+    }
+
+    fn route(&self, cmd: &Command) -> Vec<String> {
+        let all = self.servers();
+        let mut state = self.state.lock().unwrap();
+        let for_button =
+            |state: &ClusterState, bd_addr: &str| match state.verified.get(&bd_addr.to_lowercase())
+            {
+                Some(servers) if !servers.is_empty() => {
+                    let mut servers: Vec<String> = servers.iter().cloned().collect();
+                    servers.sort();
+                    servers
+                }
+                _ => all.clone(),
+            };
+        match cmd {
+            Command::CreateConnectionChannel {
+                conn_id, bd_addr, ..
+            } => {
+                let servers = for_button(&state, bd_addr);
+                state.conn_ids.insert(*conn_id, servers.clone());
+                servers
+            }
+            Command::CreateBatteryStatusListener {
+                listener_id,
+                bd_addr,
+            } => {
+                let servers = for_button(&state, bd_addr);
+                state.listener_ids.insert(*listener_id, servers.clone());
+                servers
+            }
+            Command::ForceDisconnect { bd_addr }
+            | Command::GetButtonInfo { bd_addr }
+            | Command::DeleteButton { bd_addr } => for_button(&state, bd_addr),
+            Command::ChangeModeParameters { conn_id, .. } => state
+                .conn_ids
+                .get(conn_id)
+                .cloned()
+                .unwrap_or_else(|| all.clone()),
+            Command::RemoveConnectionChannel { conn_id } => state
+                .conn_ids
+                .remove(conn_id)
+                .unwrap_or_else(|| all.clone()),
+            Command::RemoveBatteryStatusListener { listener_id } => state
+                .listener_ids
+                .remove(listener_id)
+                .unwrap_or_else(|| all.clone()),
+            _ => all.clone(),
+        }
+    }
+
+    /// Sends a command to the servers it concerns.
+    pub async fn submit(&self, cmd: Command) {
+        for server in self.route(&cmd) {
+            self.submit_to(&server, cmd.clone()).await;
+        }
+    }
+
+    /// Sends a command to one server.
+    pub async fn submit_to(&self, server: &str, cmd: Command) {
+        if let Some(client) = self.client(server) {
+            client.submit(cmd).await;
+        }
+    }
+}
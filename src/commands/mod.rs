@@ -1,9 +1,20 @@
 pub mod stream_mapper;
 
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
 use super::enums::LatencyMode;
 
-/// Commands
+// Commands
 
+/// A command sent to flicd.
+///
+/// With the `serde` feature a command is a JSON object naming the command in
+/// snake case in its `command` field next to the command's fields, e.g.
+/// `{"command": "get_button_info", "bd_addr": "80:e4:da:76:fa:55"}`.
+#[derive(Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(tag = "command", rename_all = "snake_case"))]
 pub enum Command {
     GetInfo,
     CreateScanner {
@@ -73,3 +84,12 @@ impl Command {
         }
     }
 }
+
+/// Whether a string is a Bluetooth address like `80:e4:da:76:fa:55`.
+pub fn is_bd_addr(s: &str) -> bool {
+    let parts: Vec<&str> = s.split(':').collect();
+    parts.len() == 6
+        && parts
+            .iter()
+            .all(|part| part.len() == 2 && part.chars().all(|c| c.is_ascii_hexdigit()))
+}
@@ -2,13 +2,17 @@ use regex::Regex;
 
 use std::collections::vec_deque::Drain;
 use std::collections::VecDeque;
-use std::convert::TryInto;
 
 use super::*;
 
 pub struct CommandToByteMapper {
     buffer: VecDeque<u8>,
 }
+impl Default for CommandToByteMapper {
+    fn default() -> Self {
+        CommandToByteMapper::new()
+    }
+}
 impl CommandToByteMapper {
     pub fn new() -> CommandToByteMapper {
         CommandToByteMapper {
@@ -16,7 +20,7 @@ impl CommandToByteMapper {
         }
     }
 
-    pub fn map(&mut self, command: Command) -> Drain<u8> {
+    pub fn map(&mut self, command: Command) -> Drain<'_, u8> {
         self.clear_buffer();
 
         self.write_u8(command.opcode());
@@ -36,9 +40,7 @@ impl CommandToByteMapper {
             } => {
                 self.write_u32(conn_id);
                 self.write_bdaddr(&bd_addr[..]);
-                if let Ok(latency_mode) = latency_mode.try_into() {
-                    self.write_u8(latency_mode);
-                }
+                self.write_u8(latency_mode.into());
                 self.write_i16(auto_disconnect_time);
             }
             Command::RemoveConnectionChannel { conn_id } => {
@@ -53,9 +55,7 @@ impl CommandToByteMapper {
                 auto_disconnect_time,
             } => {
                 self.write_u32(conn_id);
-                if let Ok(latency_mode) = latency_mode.try_into() {
-                    self.write_u8(latency_mode);
-                }
+                self.write_u8(latency_mode.into());
                 self.write_i16(auto_disconnect_time);
             }
             Command::Ping { ping_id } => {
@@ -100,8 +100,7 @@ impl CommandToByteMapper {
         self.buffer.push_front((len & 255) as u8);
     }
     fn write_u8(&mut self, value: u8) {
-        let mut buf = vec![value].into_iter().collect();
-        self.buffer.append(&mut buf);
+        self.buffer.push_back(value);
     }
     /*
         fn write_bool(&mut self, value: bool) {
@@ -149,7 +148,7 @@ impl CommandToByteMapper {
             if let Some(b) = hex_to_u8(&str[3..]) {
                 self.write_u8(b);
             }
-            if let Some(b) = hex_to_u8(&str[..]) {
+            if let Some(b) = hex_to_u8(str) {
                 self.write_u8(b);
             }
         }
@@ -172,3 +171,93 @@ fn hex_to_u8(buffer: &str) -> Option<u8> {
         _ => None,
     }
 }
+
+struct CommandReader<'a> {
+    packet: &'a [u8],
+    pos: usize,
+}
+impl CommandReader<'_> {
+    fn read_u8(&mut self) -> Option<u8> {
+        let b = *self.packet.get(self.pos)?;
+        self.pos += 1;
+        Some(b)
+    }
+    fn read_u16(&mut self) -> Option<u16> {
+        Some(self.read_u8()? as u16 | (self.read_u8()? as u16) << 8)
+    }
+    fn read_i16(&mut self) -> Option<i16> {
+        self.read_u16().map(|v| v as i16)
+    }
+    fn read_u32(&mut self) -> Option<u32> {
+        Some(self.read_u16()? as u32 | (self.read_u16()? as u32) << 16)
+    }
+    fn read_bdaddr(&mut self) -> Option<String> {
+        let mut bytes = vec![];
+        for _ in 0..6 {
+            bytes.push(self.read_u8()?);
+        }
+        let parts: Vec<String> = bytes.iter().rev().map(|b| format!("{:02x}", b)).collect();
+        Some(parts.join(":"))
+    }
+    fn read_latency_mode(&mut self) -> Option<LatencyMode> {
+        self.read_u8().map(LatencyMode::from)
+    }
+}
+
+impl Command {
+    /// Decodes a command packet without its length prefix, as sent by a
+    /// client to flicd.
+    pub fn from_packet(packet: &[u8]) -> Option<Command> {
+        let mut reader = CommandReader { packet, pos: 1 };
+        let command = match packet.first()? {
+            0 => Command::GetInfo,
+            1 => Command::CreateScanner {
+                scan_id: reader.read_u32()?,
+            },
+            2 => Command::RemoveScanner {
+                scan_id: reader.read_u32()?,
+            },
+            3 => Command::CreateConnectionChannel {
+                conn_id: reader.read_u32()?,
+                bd_addr: reader.read_bdaddr()?,
+                latency_mode: reader.read_latency_mode()?,
+                auto_disconnect_time: reader.read_i16()?,
+            },
+            4 => Command::RemoveConnectionChannel {
+                conn_id: reader.read_u32()?,
+            },
+            5 => Command::ForceDisconnect {
+                bd_addr: reader.read_bdaddr()?,
+            },
+            6 => Command::ChangeModeParameters {
+                conn_id: reader.read_u32()?,
+                latency_mode: reader.read_latency_mode()?,
+                auto_disconnect_time: reader.read_i16()?,
+            },
+            7 => Command::Ping {
+                ping_id: reader.read_u32()?,
+            },
+            8 => Command::GetButtonInfo {
+                bd_addr: reader.read_bdaddr()?,
+            },
+            9 => Command::CreateScanWizard {
+                scan_wizard_id: reader.read_u32()?,
+            },
+            10 => Command::CancelScanWizard {
+                scan_wizard_id: reader.read_u32()?,
+            },
+            11 => Command::DeleteButton {
+                bd_addr: reader.read_bdaddr()?,
+            },
+            12 => Command::CreateBatteryStatusListener {
+                listener_id: reader.read_u32()?,
+                bd_addr: reader.read_bdaddr()?,
+            },
+            13 => Command::RemoveBatteryStatusListener {
+                listener_id: reader.read_u32()?,
+            },
+            _ => return None,
+        };
+        Some(command)
+    }
+}
@@ -1,36 +1,58 @@
 #![allow(dead_code)]
 
+use num_enum::FromPrimitive;
 use num_enum::IntoPrimitive;
-use num_enum::TryFromPrimitive;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
 
 // Enums
+//
+// Values a newer flicd may add decode to `Other` with the raw value, so the
+// enums are non-exhaustive.
+//
+// With the `serde` feature every enum is (de)serialized as its variant name,
+// e.g. `"ButtonSingleClick"`, and `Other` as `{"Other": 7}`.
 
 #[repr(u8)]
-#[derive(Debug, PartialEq, Eq, Hash, Copy, Clone, IntoPrimitive, TryFromPrimitive)]
+#[derive(Debug, PartialEq, Eq, Hash, Copy, Clone, IntoPrimitive, FromPrimitive)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[non_exhaustive]
 pub enum CreateConnectionChannelError {
     NoError,
     MaxPendingConnectionsReached,
+    #[num_enum(catch_all)]
+    Other(u8),
 }
 
 #[repr(u8)]
-#[derive(Debug, PartialEq, Eq, Hash, Copy, Clone, IntoPrimitive, TryFromPrimitive)]
+#[derive(Debug, PartialEq, Eq, Hash, Copy, Clone, IntoPrimitive, FromPrimitive)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[non_exhaustive]
 pub enum ConnectionStatus {
     Disconnected,
     Connected,
     Ready,
+    #[num_enum(catch_all)]
+    Other(u8),
 }
 
 #[repr(u8)]
-#[derive(Debug, PartialEq, Eq, Hash, Copy, Clone, IntoPrimitive, TryFromPrimitive)]
+#[derive(Debug, PartialEq, Eq, Hash, Copy, Clone, IntoPrimitive, FromPrimitive)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[non_exhaustive]
 pub enum DisconnectReason {
     Unspecified,
     ConnectionEstablishmentFailed,
     TimedOut,
     BondingKeysMismatch,
+    #[num_enum(catch_all)]
+    Other(u8),
 }
 
 #[repr(u8)]
-#[derive(Debug, PartialEq, Eq, Hash, Copy, Clone, IntoPrimitive, TryFromPrimitive)]
+#[derive(Debug, PartialEq, Eq, Hash, Copy, Clone, IntoPrimitive, FromPrimitive)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[non_exhaustive]
 pub enum RemovedReason {
     RemovedByThisClient,
     ForceDisconnectedByThisClient,
@@ -47,10 +69,14 @@ pub enum RemovedReason {
     DeletedByOtherClient,
     ButtonBelongsToOtherPartner,
     DeletedFromButton,
+    #[num_enum(catch_all)]
+    Other(u8),
 }
 
 #[repr(u8)]
-#[derive(Debug, PartialEq, Eq, Hash, Copy, Clone, IntoPrimitive, TryFromPrimitive)]
+#[derive(Debug, PartialEq, Eq, Hash, Copy, Clone, IntoPrimitive, FromPrimitive)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[non_exhaustive]
 pub enum ClickType {
     ButtonDown,
     ButtonUp,
@@ -58,25 +84,37 @@ pub enum ClickType {
     ButtonSingleClick,
     ButtonDoubleClick,
     ButtonHold,
+    #[num_enum(catch_all)]
+    Other(u8),
 }
 
 #[repr(u8)]
-#[derive(Debug, PartialEq, Eq, Hash, Copy, Clone, IntoPrimitive, TryFromPrimitive)]
+#[derive(Debug, PartialEq, Eq, Hash, Copy, Clone, IntoPrimitive, FromPrimitive)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[non_exhaustive]
 pub enum BdAddrType {
     PublicBdAddrType,
     RandomBdAddrType,
+    #[num_enum(catch_all)]
+    Other(u8),
 }
 
 #[repr(u8)]
-#[derive(Debug, PartialEq, Eq, Hash, Copy, Clone, IntoPrimitive, TryFromPrimitive)]
+#[derive(Debug, PartialEq, Eq, Hash, Copy, Clone, IntoPrimitive, FromPrimitive)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[non_exhaustive]
 pub enum LatencyMode {
     NormalLatency,
     LowLatency,
     HighLatency,
+    #[num_enum(catch_all)]
+    Other(u8),
 }
 
 #[repr(u8)]
-#[derive(Debug, PartialEq, Eq, Hash, Copy, Clone, IntoPrimitive, TryFromPrimitive)]
+#[derive(Debug, PartialEq, Eq, Hash, Copy, Clone, IntoPrimitive, FromPrimitive)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[non_exhaustive]
 pub enum ScanWizardResult {
     WizardSuccess,
     WizardCancelledByUser,
@@ -87,12 +125,18 @@ pub enum ScanWizardResult {
     WizardInvalidData,
     WizardButtonBelongsToOtherPartner,
     WizardButtonAlreadyConnectedToOtherDevice,
+    #[num_enum(catch_all)]
+    Other(u8),
 }
 
 #[repr(u8)]
-#[derive(Debug, PartialEq, Eq, Hash, Copy, Clone, IntoPrimitive, TryFromPrimitive)]
+#[derive(Debug, PartialEq, Eq, Hash, Copy, Clone, IntoPrimitive, FromPrimitive)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[non_exhaustive]
 pub enum BluetoothControllerState {
     Detached,
     Resetting,
     Attached,
+    #[num_enum(catch_all)]
+    Other(u8),
 }
@@ -1,7 +1,11 @@
+#[cfg(feature = "serde")]
+mod repr;
 pub mod stream_mapper;
 
 use num_enum::IntoPrimitive;
 use num_enum::TryFromPrimitive;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
 
 use super::enums::*;
 
@@ -31,130 +35,355 @@ pub enum OpCode {
     BatteryStatus = 20,
 }
 
-#[allow(dead_code)]
+/// Kind of a button event, after the flicd packet it came in.
+#[derive(Debug, PartialEq, Eq, Hash, Copy, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum ButtonEventKind {
+    UpOrDown,
+    ClickOrHold,
+    SingleOrDoubleClick,
+    SingleOrDoubleClickOrHold,
+}
+
+impl ButtonEventKind {
+    pub fn opcode(self) -> OpCode {
+        match self {
+            ButtonEventKind::UpOrDown => OpCode::ButtonUpOrDown,
+            ButtonEventKind::ClickOrHold => OpCode::ButtonClickOrHold,
+            ButtonEventKind::SingleOrDoubleClick => OpCode::ButtonSingleOrDoubleClick,
+            ButtonEventKind::SingleOrDoubleClickOrHold => OpCode::ButtonSingleOrDoubleClickOrHold,
+        }
+    }
+
+    pub fn of_opcode(opcode: OpCode) -> Option<ButtonEventKind> {
+        match opcode {
+            OpCode::ButtonUpOrDown => Some(ButtonEventKind::UpOrDown),
+            OpCode::ButtonClickOrHold => Some(ButtonEventKind::ClickOrHold),
+            OpCode::ButtonSingleOrDoubleClick => Some(ButtonEventKind::SingleOrDoubleClick),
+            OpCode::ButtonSingleOrDoubleClickOrHold => {
+                Some(ButtonEventKind::SingleOrDoubleClickOrHold)
+            }
+            _ => None,
+        }
+    }
+}
+
+/// A button was pressed, released, clicked or held.
+///
+/// flicd sends one packet per kind for every gesture, so a double click
+/// arrives once as `SingleOrDoubleClick` and once as
+/// `SingleOrDoubleClickOrHold`.
 #[derive(Debug, PartialEq, Eq, Clone)]
-pub enum Event {
-    NoOp,
-    CorruptEvent,
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct ButtonEvent {
+    pub kind: ButtonEventKind,
+    pub conn_id: u32,
+    pub click_type: ClickType,
+    pub was_queued: bool,
+    pub time_diff: i32,
+}
 
-    AdvertisementPacket {
-        scan_id: u32,
-        bd_addr: String,
-        name: String,
-        rssi: u8,
-        is_private: bool,
-        already_verified: bool,
-        already_connected_to_this_device: bool,
-        already_connected_to_other_device: bool,
-    },
+impl ButtonEvent {
+    /// A click, single click or double click, as opposed to a press,
+    /// release or hold.
+    pub fn is_click(&self) -> bool {
+        matches!(
+            self.click_type,
+            ClickType::ButtonClick | ClickType::ButtonSingleClick | ClickType::ButtonDoubleClick
+        )
+    }
+}
 
-    CreateConnectionChannelResponse {
-        conn_id: u32,
-        error: CreateConnectionChannelError,
-        connection_status: ConnectionStatus,
-    },
+#[derive(Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct AdvertisementPacket {
+    pub scan_id: u32,
+    pub bd_addr: String,
+    pub name: String,
+    pub rssi: u8,
+    pub is_private: bool,
+    pub already_verified: bool,
+    pub already_connected_to_this_device: bool,
+    pub already_connected_to_other_device: bool,
+}
 
-    ConnectionStatusChanged {
-        conn_id: u32,
-        connection_status: ConnectionStatus,
-        disconnect_reason: DisconnectReason,
-    },
+#[derive(Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct CreateConnectionChannelResponse {
+    pub conn_id: u32,
+    pub error: CreateConnectionChannelError,
+    pub connection_status: ConnectionStatus,
+}
 
-    ConnectionChannelRemoved {
-        conn_id: u32,
-        removed_reason: RemovedReason,
-    },
+#[derive(Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct ConnectionStatusChanged {
+    pub conn_id: u32,
+    pub connection_status: ConnectionStatus,
+    pub disconnect_reason: DisconnectReason,
+}
 
-    ButtonUpOrDown {
-        conn_id: u32,
-        click_type: ClickType,
-        was_queued: bool,
-        time_diff: i32,
-    },
-    ButtonClickOrHold {
-        conn_id: u32,
-        click_type: ClickType,
-        was_queued: bool,
-        time_diff: i32,
-    },
-    ButtonSingleOrDoubleClick {
-        conn_id: u32,
-        click_type: ClickType,
-        was_queued: bool,
-        time_diff: i32,
-    },
-    ButtonSingleOrDoubleClickOrHold {
-        conn_id: u32,
-        click_type: ClickType,
-        was_queued: bool,
-        time_diff: i32,
-    },
+#[derive(Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct ConnectionChannelRemoved {
+    pub conn_id: u32,
+    pub removed_reason: RemovedReason,
+}
 
-    NewVerifiedButton {
-        bd_addr: String,
-    },
+#[derive(Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct NewVerifiedButton {
+    pub bd_addr: String,
+}
 
-    GetInfoResponse {
-        bluetooth_controller_state: BluetoothControllerState,
-        my_bd_addr: String,
-        my_bd_addr_type: BdAddrType,
-        max_pending_connections: u8,
-        max_concurrently_connected_buttons: i16,
-        current_pending_connections: u8,
-        currently_no_space_for_new_connection: bool,
-        bd_addr_of_verified_buttons: Vec<String>,
-    },
+#[derive(Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct GetInfoResponse {
+    pub bluetooth_controller_state: BluetoothControllerState,
+    pub my_bd_addr: String,
+    pub my_bd_addr_type: BdAddrType,
+    pub max_pending_connections: u8,
+    pub max_concurrently_connected_buttons: i16,
+    pub current_pending_connections: u8,
+    pub currently_no_space_for_new_connection: bool,
+    pub bd_addr_of_verified_buttons: Vec<String>,
+}
 
-    NoSpaceForNewConnection {
-        max_concurrently_connected_buttons: u8,
-    },
+#[derive(Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct NoSpaceForNewConnection {
+    pub max_concurrently_connected_buttons: u8,
+}
 
-    GotSpaceForNewConnection {
-        max_concurrently_connected_buttons: u8,
-    },
+#[derive(Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct GotSpaceForNewConnection {
+    pub max_concurrently_connected_buttons: u8,
+}
 
-    BluetoothControllerStateChange {
-        state: BluetoothControllerState,
-    },
+#[derive(Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct BluetoothControllerStateChange {
+    pub state: BluetoothControllerState,
+}
 
-    PingResponse {
-        ping_id: u32,
-    },
+#[derive(Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct PingResponse {
+    pub ping_id: u32,
+}
 
-    GetButtonInfoResponse {
-        bd_addr: String,
-        uuid: String,
-        color: Option<String>,
-        serial_number: Option<String>,
-    },
+/// Generation of a button.
+#[derive(Debug, PartialEq, Eq, Hash, Copy, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum ButtonModel {
+    Flic1,
+    Flic2,
+}
 
-    ScanWizardFoundPrivateButton {
-        scan_wizard_id: u32,
-    },
+/// Details of a verified button.
+///
+/// Fields after `uuid` were added to flicd over time and are `None` when the
+/// server does not send them.
+#[derive(Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct GetButtonInfoResponse {
+    pub bd_addr: String,
+    pub uuid: String,
+    pub color: Option<String>,
+    pub serial_number: Option<String>,
+    /// 1 for a Flic 1 and 2 for a Flic 2 button.
+    pub flic_version: Option<u8>,
+    pub firmware_version: Option<u32>,
+}
 
-    ScanWizardFoundPublicButton {
-        scan_wizard_id: u32,
-        bd_addr: String,
-        name: String,
-    },
+impl GetButtonInfoResponse {
+    pub fn model(&self) -> Option<ButtonModel> {
+        match self.flic_version {
+            Some(1) => Some(ButtonModel::Flic1),
+            Some(2) => Some(ButtonModel::Flic2),
+            _ => None,
+        }
+    }
+}
 
-    ScanWizardButtonConnected {
-        scan_wizard_id: u32,
-    },
+#[derive(Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct ScanWizardFoundPrivateButton {
+    pub scan_wizard_id: u32,
+}
 
-    ScanWizardCompleted {
-        scan_wizard_id: u32,
-        result: ScanWizardResult,
-    },
+#[derive(Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct ScanWizardFoundPublicButton {
+    pub scan_wizard_id: u32,
+    pub bd_addr: String,
+    pub name: String,
+}
 
-    ButtonDeleted {
-        bd_addr: String,
-        deleted_by_this_client: bool,
-    },
+#[derive(Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct ScanWizardButtonConnected {
+    pub scan_wizard_id: u32,
+}
 
-    BatteryStatus {
-        listener_id: u32,
-        battery_percentage: i8,
-        timestamp: u64,
+#[derive(Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct ScanWizardCompleted {
+    pub scan_wizard_id: u32,
+    pub result: ScanWizardResult,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct ButtonDeleted {
+    pub bd_addr: String,
+    pub deleted_by_this_client: bool,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct BatteryStatus {
+    pub listener_id: u32,
+    pub battery_percentage: i8,
+    pub timestamp: u64,
+}
+
+/// An event received from flicd.
+///
+/// With the `serde` feature an event is a JSON object naming the event in
+/// snake case in its `event` field next to the event's fields, e.g.
+/// `{"event": "ping_response", "ping_id": 1}`. Button events are named
+/// after their kind, e.g. `button_up_or_down`. Bluetooth addresses are
+/// strings like `"80:e4:da:76:fa:55"`.
+///
+/// New kinds of events may be added, and a newer flicd may send events this
+/// version does not know, which arrive as `Unknown`.
+#[allow(dead_code)]
+#[derive(Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(
+    feature = "serde",
+    serde(from = "repr::EventRepr", into = "repr::EventRepr")
+)]
+#[non_exhaustive]
+pub enum Event {
+    NoOp,
+    CorruptEvent,
+    AdvertisementPacket(AdvertisementPacket),
+    CreateConnectionChannelResponse(CreateConnectionChannelResponse),
+    ConnectionStatusChanged(ConnectionStatusChanged),
+    ConnectionChannelRemoved(ConnectionChannelRemoved),
+    /// Any of the four button event packets.
+    Button(ButtonEvent),
+    NewVerifiedButton(NewVerifiedButton),
+    GetInfoResponse(GetInfoResponse),
+    NoSpaceForNewConnection(NoSpaceForNewConnection),
+    GotSpaceForNewConnection(GotSpaceForNewConnection),
+    BluetoothControllerStateChange(BluetoothControllerStateChange),
+    PingResponse(PingResponse),
+    GetButtonInfoResponse(GetButtonInfoResponse),
+    ScanWizardFoundPrivateButton(ScanWizardFoundPrivateButton),
+    ScanWizardFoundPublicButton(ScanWizardFoundPublicButton),
+    ScanWizardButtonConnected(ScanWizardButtonConnected),
+    ScanWizardCompleted(ScanWizardCompleted),
+    ButtonDeleted(ButtonDeleted),
+    BatteryStatus(BatteryStatus),
+    /// An event with an opcode this version does not know, with the bytes
+    /// after the opcode.
+    Unknown {
+        opcode: u8,
+        payload: Vec<u8>,
     },
 }
+
+impl Event {
+    /// Snake case name of the event, e.g. `button_up_or_down`, as used in
+    /// its JSON.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Event::NoOp => "no_op",
+            Event::CorruptEvent => "corrupt_event",
+            Event::AdvertisementPacket(_) => "advertisement_packet",
+            Event::CreateConnectionChannelResponse(_) => "create_connection_channel_response",
+            Event::ConnectionStatusChanged(_) => "connection_status_changed",
+            Event::ConnectionChannelRemoved(_) => "connection_channel_removed",
+            Event::Button(button) => match button.kind {
+                ButtonEventKind::UpOrDown => "button_up_or_down",
+                ButtonEventKind::ClickOrHold => "button_click_or_hold",
+                ButtonEventKind::SingleOrDoubleClick => "button_single_or_double_click",
+                ButtonEventKind::SingleOrDoubleClickOrHold => {
+                    "button_single_or_double_click_or_hold"
+                }
+            },
+            Event::NewVerifiedButton(_) => "new_verified_button",
+            Event::GetInfoResponse(_) => "get_info_response",
+            Event::NoSpaceForNewConnection(_) => "no_space_for_new_connection",
+            Event::GotSpaceForNewConnection(_) => "got_space_for_new_connection",
+            Event::BluetoothControllerStateChange(_) => "bluetooth_controller_state_change",
+            Event::PingResponse(_) => "ping_response",
+            Event::GetButtonInfoResponse(_) => "get_button_info_response",
+            Event::ScanWizardFoundPrivateButton(_) => "scan_wizard_found_private_button",
+            Event::ScanWizardFoundPublicButton(_) => "scan_wizard_found_public_button",
+            Event::ScanWizardButtonConnected(_) => "scan_wizard_button_connected",
+            Event::ScanWizardCompleted(_) => "scan_wizard_completed",
+            Event::ButtonDeleted(_) => "button_deleted",
+            Event::BatteryStatus(_) => "battery_status",
+            Event::Unknown { .. } => "unknown",
+        }
+    }
+
+    pub fn button(&self) -> Option<&ButtonEvent> {
+        match self {
+            Event::Button(button) => Some(button),
+            _ => None,
+        }
+    }
+
+    /// A click, single click or double click of any button event kind.
+    pub fn is_click(&self) -> bool {
+        self.button().is_some_and(ButtonEvent::is_click)
+    }
+
+    /// The connection channel the event is about.
+    pub fn conn_id(&self) -> Option<u32> {
+        match self {
+            Event::CreateConnectionChannelResponse(CreateConnectionChannelResponse {
+                conn_id,
+                ..
+            })
+            | Event::ConnectionStatusChanged(ConnectionStatusChanged { conn_id, .. })
+            | Event::ConnectionChannelRemoved(ConnectionChannelRemoved { conn_id, .. })
+            | Event::Button(ButtonEvent { conn_id, .. }) => Some(*conn_id),
+            _ => None,
+        }
+    }
+
+    /// The button address the event carries. Events of a connection channel
+    /// only carry its `conn_id`, see `ChannelMap::bd_addr_of`.
+    pub fn bd_addr(&self) -> Option<&str> {
+        match self {
+            Event::AdvertisementPacket(AdvertisementPacket { bd_addr, .. })
+            | Event::NewVerifiedButton(NewVerifiedButton { bd_addr })
+            | Event::GetButtonInfoResponse(GetButtonInfoResponse { bd_addr, .. })
+            | Event::ScanWizardFoundPublicButton(ScanWizardFoundPublicButton { bd_addr, .. })
+            | Event::ButtonDeleted(ButtonDeleted { bd_addr, .. }) => Some(bd_addr),
+            _ => None,
+        }
+    }
+
+    /// The button event a gesture is taken from.
+    ///
+    /// flicd reports a gesture on every event kind at once, e.g. a double click
+    /// arrives as `SingleOrDoubleClick` and `SingleOrDoubleClickOrHold`. This
+    /// picks `UpOrDown` and `SingleOrDoubleClickOrHold` events and the clicks
+    /// of `ClickOrHold` events, so every gesture is seen once.
+    pub fn gesture(&self) -> Option<&ButtonEvent> {
+        self.button().filter(|button| match button.kind {
+            ButtonEventKind::UpOrDown | ButtonEventKind::SingleOrDoubleClickOrHold => true,
+            ButtonEventKind::ClickOrHold => button.click_type == ClickType::ButtonClick,
+            ButtonEventKind::SingleOrDoubleClick => false,
+        })
+    }
+}
@@ -0,0 +1,143 @@
+use serde::{Deserialize, Serialize};
+
+use super::*;
+
+/// Fields of a button event; the kind is the tag of the event.
+#[derive(Clone, Serialize, Deserialize)]
+pub(super) struct ButtonFields {
+    conn_id: u32,
+    click_type: ClickType,
+    was_queued: bool,
+    time_diff: i32,
+}
+
+impl ButtonFields {
+    fn event(self, kind: ButtonEventKind) -> Event {
+        Event::Button(ButtonEvent {
+            kind,
+            conn_id: self.conn_id,
+            click_type: self.click_type,
+            was_queued: self.was_queued,
+            time_diff: self.time_diff,
+        })
+    }
+}
+
+/// The JSON shape of an event, with the event name in `event` and a tag per
+/// button event kind.
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub(super) enum EventRepr {
+    NoOp,
+    CorruptEvent,
+    AdvertisementPacket(AdvertisementPacket),
+    CreateConnectionChannelResponse(CreateConnectionChannelResponse),
+    ConnectionStatusChanged(ConnectionStatusChanged),
+    ConnectionChannelRemoved(ConnectionChannelRemoved),
+    ButtonUpOrDown(ButtonFields),
+    ButtonClickOrHold(ButtonFields),
+    ButtonSingleOrDoubleClick(ButtonFields),
+    ButtonSingleOrDoubleClickOrHold(ButtonFields),
+    NewVerifiedButton(NewVerifiedButton),
+    GetInfoResponse(GetInfoResponse),
+    NoSpaceForNewConnection(NoSpaceForNewConnection),
+    GotSpaceForNewConnection(GotSpaceForNewConnection),
+    BluetoothControllerStateChange(BluetoothControllerStateChange),
+    PingResponse(PingResponse),
+    GetButtonInfoResponse(GetButtonInfoResponse),
+    ScanWizardFoundPrivateButton(ScanWizardFoundPrivateButton),
+    ScanWizardFoundPublicButton(ScanWizardFoundPublicButton),
+    ScanWizardButtonConnected(ScanWizardButtonConnected),
+    ScanWizardCompleted(ScanWizardCompleted),
+    ButtonDeleted(ButtonDeleted),
+    BatteryStatus(BatteryStatus),
+    Unknown { opcode: u8, payload: Vec<u8> },
+}
+
+impl From<Event> for EventRepr {
+    fn from(event: Event) -> Self {
+        match event {
+            Event::NoOp => EventRepr::NoOp,
+            Event::CorruptEvent => EventRepr::CorruptEvent,
+            Event::AdvertisementPacket(e) => EventRepr::AdvertisementPacket(e),
+            Event::CreateConnectionChannelResponse(e) => {
+                EventRepr::CreateConnectionChannelResponse(e)
+            }
+            Event::ConnectionStatusChanged(e) => EventRepr::ConnectionStatusChanged(e),
+            Event::ConnectionChannelRemoved(e) => EventRepr::ConnectionChannelRemoved(e),
+            Event::Button(button) => {
+                let fields = ButtonFields {
+                    conn_id: button.conn_id,
+                    click_type: button.click_type,
+                    was_queued: button.was_queued,
+                    time_diff: button.time_diff,
+                };
+                match button.kind {
+                    ButtonEventKind::UpOrDown => EventRepr::ButtonUpOrDown(fields),
+                    ButtonEventKind::ClickOrHold => EventRepr::ButtonClickOrHold(fields),
+                    ButtonEventKind::SingleOrDoubleClick => {
+                        EventRepr::ButtonSingleOrDoubleClick(fields)
+                    }
+                    ButtonEventKind::SingleOrDoubleClickOrHold => {
+                        EventRepr::ButtonSingleOrDoubleClickOrHold(fields)
+                    }
+                }
+            }
+            Event::NewVerifiedButton(e) => EventRepr::NewVerifiedButton(e),
+            Event::GetInfoResponse(e) => EventRepr::GetInfoResponse(e),
+            Event::NoSpaceForNewConnection(e) => EventRepr::NoSpaceForNewConnection(e),
+            Event::GotSpaceForNewConnection(e) => EventRepr::GotSpaceForNewConnection(e),
+            Event::BluetoothControllerStateChange(e) => {
+                EventRepr::BluetoothControllerStateChange(e)
+            }
+            Event::PingResponse(e) => EventRepr::PingResponse(e),
+            Event::GetButtonInfoResponse(e) => EventRepr::GetButtonInfoResponse(e),
+            Event::ScanWizardFoundPrivateButton(e) => EventRepr::ScanWizardFoundPrivateButton(e),
+            Event::ScanWizardFoundPublicButton(e) => EventRepr::ScanWizardFoundPublicButton(e),
+            Event::ScanWizardButtonConnected(e) => EventRepr::ScanWizardButtonConnected(e),
+            Event::ScanWizardCompleted(e) => EventRepr::ScanWizardCompleted(e),
+            Event::ButtonDeleted(e) => EventRepr::ButtonDeleted(e),
+            Event::BatteryStatus(e) => EventRepr::BatteryStatus(e),
+            Event::Unknown { opcode, payload } => EventRepr::Unknown { opcode, payload },
+        }
+    }
+}
+
+impl From<EventRepr> for Event {
+    fn from(repr: EventRepr) -> Self {
+        match repr {
+            EventRepr::NoOp => Event::NoOp,
+            EventRepr::CorruptEvent => Event::CorruptEvent,
+            EventRepr::AdvertisementPacket(e) => Event::AdvertisementPacket(e),
+            EventRepr::CreateConnectionChannelResponse(e) => {
+                Event::CreateConnectionChannelResponse(e)
+            }
+            EventRepr::ConnectionStatusChanged(e) => Event::ConnectionStatusChanged(e),
+            EventRepr::ConnectionChannelRemoved(e) => Event::ConnectionChannelRemoved(e),
+            EventRepr::ButtonUpOrDown(fields) => fields.event(ButtonEventKind::UpOrDown),
+            EventRepr::ButtonClickOrHold(fields) => fields.event(ButtonEventKind::ClickOrHold),
+            EventRepr::ButtonSingleOrDoubleClick(fields) => {
+                fields.event(ButtonEventKind::SingleOrDoubleClick)
+            }
+            EventRepr::ButtonSingleOrDoubleClickOrHold(fields) => {
+                fields.event(ButtonEventKind::SingleOrDoubleClickOrHold)
+            }
+            EventRepr::NewVerifiedButton(e) => Event::NewVerifiedButton(e),
+            EventRepr::GetInfoResponse(e) => Event::GetInfoResponse(e),
+            EventRepr::NoSpaceForNewConnection(e) => Event::NoSpaceForNewConnection(e),
+            EventRepr::GotSpaceForNewConnection(e) => Event::GotSpaceForNewConnection(e),
+            EventRepr::BluetoothControllerStateChange(e) => {
+                Event::BluetoothControllerStateChange(e)
+            }
+            EventRepr::PingResponse(e) => Event::PingResponse(e),
+            EventRepr::GetButtonInfoResponse(e) => Event::GetButtonInfoResponse(e),
+            EventRepr::ScanWizardFoundPrivateButton(e) => Event::ScanWizardFoundPrivateButton(e),
+            EventRepr::ScanWizardFoundPublicButton(e) => Event::ScanWizardFoundPublicButton(e),
+            EventRepr::ScanWizardButtonConnected(e) => Event::ScanWizardButtonConnected(e),
+            EventRepr::ScanWizardCompleted(e) => Event::ScanWizardCompleted(e),
+            EventRepr::ButtonDeleted(e) => Event::ButtonDeleted(e),
+            EventRepr::BatteryStatus(e) => Event::BatteryStatus(e),
+            EventRepr::Unknown { opcode, payload } => Event::Unknown { opcode, payload },
+        }
+    }
+}
@@ -1,8 +1,10 @@
 use super::*;
 use std::collections::VecDeque;
 use std::convert::TryFrom;
-use std::convert::TryInto;
-use std::usize;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+use tracing::{debug, warn};
 
 #[derive(Debug, PartialEq, Eq, Clone)]
 pub enum EventResult {
@@ -14,21 +16,37 @@ pub enum EventResult {
     CorruptPackage,
 }
 enum HasPacketResult {
-    Yes,
+    /// A whole packet of this many bytes, with its length, is buffered.
+    Yes(usize),
     NotYet,
-    Failure(Event),
 }
 
 pub struct ByteToEventMapper {
     fifo: VecDeque<u8>,
+    /// Read position in the packet being decoded.
+    pos: usize,
+    corrupt_packets: Arc<AtomicU64>,
+}
+impl Default for ByteToEventMapper {
+    fn default() -> Self {
+        ByteToEventMapper::new()
+    }
 }
 impl ByteToEventMapper {
     pub fn new() -> ByteToEventMapper {
         ByteToEventMapper {
             fifo: VecDeque::new(),
+            pos: 0,
+            corrupt_packets: Arc::new(AtomicU64::new(0)),
         }
     }
 
+    /// Counts the packets that could not be decoded, including bytes dropped
+    /// for not forming a packet.
+    pub fn corrupt_packets(&self) -> Arc<AtomicU64> {
+        self.corrupt_packets.clone()
+    }
+
     pub fn map(&mut self, value: u8) -> EventResult {
         self.fifo.push_back(value);
 
@@ -36,30 +54,55 @@ impl ByteToEventMapper {
             0..=2 => EventResult::None,
             3..=2047 => match self.has_packet() {
                 HasPacketResult::NotYet => EventResult::Pending,
-                HasPacketResult::Failure(event) => {
-                    self.fifo.drain(..);
-                    EventResult::Failure(event)
+                HasPacketResult::Yes(size) => {
+                    // decode the packet on its own, so fields a newer flicd
+                    // appends are skipped instead of read as the next packet
+                    let rest = self.fifo.split_off(size);
+                    let event = self.read_event();
+                    let result = match event {
+                        Event::Unknown { opcode, .. } => {
+                            debug!(opcode, "unknown opcode");
+                            EventResult::Some(event)
+                        }
+                        Event::CorruptEvent => {
+                            warn!(bytes = %self.hex(), "corrupt packet");
+                            self.corrupt_packets.fetch_add(1, Ordering::Relaxed);
+                            EventResult::CorruptPackage
+                        }
+                        event => EventResult::Some(event),
+                    };
+                    self.fifo = rest;
+                    self.pos = 0;
+                    result
                 }
-                HasPacketResult::Yes => match self.read_event() {
-                    Event::CorruptEvent => EventResult::CorruptPackage,
-                    event => EventResult::Some(event),
-                },
             },
             _ => {
+                warn!(
+                    len = self.fifo.len(),
+                    "no complete packet in 2048 bytes, dropping them"
+                );
                 self.fifo.drain(..);
+                self.corrupt_packets.fetch_add(1, Ordering::Relaxed);
                 EventResult::Drained
             }
         }
     }
 
+    /// The buffered bytes in hex, for logging.
+    fn hex(&self) -> String {
+        self.fifo
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect::<Vec<String>>()
+            .join(" ")
+    }
+
     fn has_packet(&self) -> HasPacketResult {
-        match (self.fifo.get(0), self.fifo.get(1), self.fifo.get(2)) {
-            (Some(&lower), Some(&upper), Some(&opcode)) => {
+        match (self.fifo.front(), self.fifo.get(1), self.fifo.get(2)) {
+            (Some(&lower), Some(&upper), Some(_)) => {
                 let len = ((upper as usize) << 8) + (lower as usize);
-                if OpCode::try_from(opcode).is_err() {
-                    HasPacketResult::Failure(Event::CorruptEvent)
-                } else if self.fifo.len() >= len + 2 {
-                    HasPacketResult::Yes
+                if self.fifo.len() >= len + 2 {
+                    HasPacketResult::Yes(len + 2)
                 } else {
                     HasPacketResult::NotYet
                 }
@@ -68,11 +111,9 @@ impl ByteToEventMapper {
         }
     }
     fn read_u8(&mut self) -> Option<u8> {
-        if self.fifo.is_empty() {
-            return None;
-        }
-        let mut iter = self.fifo.drain(..1);
-        iter.next()
+        let value = self.fifo.get(self.pos).copied()?;
+        self.pos += 1;
+        Some(value)
     }
     fn read_u16(&mut self) -> Option<u16> {
         match (self.read_u8(), self.read_u8()) {
@@ -93,35 +134,23 @@ impl ByteToEventMapper {
         }
     }
     fn read_i8(&mut self) -> Option<i8> {
-        match self.read_u8() {
-            Some(expr) => Some(expr as i8),
-            None => None,
-        }
+        self.read_u8().map(|expr| expr as i8)
     }
     fn read_i16(&mut self) -> Option<i16> {
-        match self.read_u16() {
-            Some(expr) => Some(expr as i16),
-            None => None,
-        }
+        self.read_u16().map(|expr| expr as i16)
     }
     fn read_i32(&mut self) -> Option<i32> {
-        match self.read_u32() {
-            Some(expr) => Some(expr as i32),
-            None => None,
-        }
+        self.read_u32().map(|expr| expr as i32)
     }
     fn read_bool(&mut self) -> Option<bool> {
-        match self.read_u8() {
-            Some(expr) => Some(expr != 0),
-            None => None,
-        }
+        self.read_u8().map(|expr| expr != 0)
     }
-    fn read_enum(&mut self) -> Option<u8> {
-        self.read_u8()
+    fn read_enum<T: From<u8>>(&mut self) -> Option<T> {
+        self.read_u8().map(T::from)
     }
     fn read_bdaddr(&mut self) -> Option<String> {
         let mut out = String::new();
-        let mut buffer = vec![
+        let mut buffer = [
             self.read_u8(),
             self.read_u8(),
             self.read_u8(),
@@ -159,6 +188,16 @@ impl ByteToEventMapper {
             _ => None,
         }
     }
+    /// A length and a string padded to 16 bytes, `None` if empty.
+    fn read_padded_string(&mut self) -> Option<String> {
+        let len = self.read_u8()? as usize;
+        let mut buf = vec![];
+        for _ in 0..16 {
+            buf.push(self.read_u8()?);
+        }
+        buf.truncate(len);
+        String::from_utf8(buf).ok().filter(|s| !s.is_empty())
+    }
     fn read_uuid(&mut self) -> Option<String> {
         let mut out = String::new();
         for _ in 0..16 {
@@ -182,7 +221,7 @@ impl ByteToEventMapper {
         self.read_u8();
         self.read_u8();
         match self.read_u8() {
-            Some(opcode) => match opcode.try_into().ok() {
+            Some(opcode) => match OpCode::try_from(opcode).ok() {
                 Some(OpCode::AdvertisementPacket) => match (
                     self.read_u32(),
                     self.read_bdaddr(),
@@ -202,7 +241,7 @@ impl ByteToEventMapper {
                         Some(already_verified),
                         Some(already_connected_to_this_device),
                         Some(already_connected_to_other_device),
-                    ) => Event::AdvertisementPacket {
+                    ) => Event::AdvertisementPacket(AdvertisementPacket {
                         scan_id,
                         bd_addr,
                         name,
@@ -211,22 +250,19 @@ impl ByteToEventMapper {
                         already_verified,
                         already_connected_to_this_device,
                         already_connected_to_other_device,
-                    },
+                    }),
                     _ => Event::CorruptEvent,
                 },
                 Some(OpCode::CreateConnectionChannelResponse) => {
                     match (self.read_u32(), self.read_enum(), self.read_enum()) {
                         (Some(conn_id), Some(error), Some(connection_status)) => {
-                            match (error.try_into().ok(), connection_status.try_into().ok()) {
-                                (Some(error), Some(connection_status)) => {
-                                    Event::CreateConnectionChannelResponse {
-                                        conn_id,
-                                        error,
-                                        connection_status,
-                                    }
-                                }
-                                _ => Event::CorruptEvent,
-                            }
+                            Event::CreateConnectionChannelResponse(
+                                CreateConnectionChannelResponse {
+                                    conn_id,
+                                    error,
+                                    connection_status,
+                                },
+                            )
                         }
                         _ => Event::CorruptEvent,
                     }
@@ -234,112 +270,54 @@ impl ByteToEventMapper {
                 Some(OpCode::ConnectionStatusChanged) => {
                     match (self.read_u32(), self.read_enum(), self.read_enum()) {
                         (Some(conn_id), Some(connection_status), Some(disconnect_reason)) => {
-                            match (
-                                connection_status.try_into().ok(),
-                                disconnect_reason.try_into().ok(),
-                            ) {
-                                (Some(connection_status), Some(disconnect_reason)) => {
-                                    Event::ConnectionStatusChanged {
-                                        conn_id,
-                                        connection_status,
-                                        disconnect_reason,
-                                    }
-                                }
-                                _ => Event::CorruptEvent,
-                            }
+                            Event::ConnectionStatusChanged(ConnectionStatusChanged {
+                                conn_id,
+                                connection_status,
+                                disconnect_reason,
+                            })
                         }
                         _ => Event::CorruptEvent,
                     }
                 }
                 Some(OpCode::ConnectionChannelRemoved) => match (self.read_u32(), self.read_enum())
                 {
-                    (Some(conn_id), Some(removed_reason)) => match removed_reason.try_into().ok() {
-                        Some(removed_reason) => Event::ConnectionChannelRemoved {
+                    (Some(conn_id), Some(removed_reason)) => {
+                        Event::ConnectionChannelRemoved(ConnectionChannelRemoved {
                             conn_id,
                             removed_reason,
-                        },
-                        _ => Event::CorruptEvent,
-                    },
-                    _ => Event::CorruptEvent,
-                },
-                Some(OpCode::ButtonUpOrDown) => match (
-                    self.read_u32(),
-                    self.read_enum(),
-                    self.read_bool(),
-                    self.read_i32(),
-                ) {
-                    (Some(conn_id), Some(click_type), Some(was_queued), Some(time_diff)) => {
-                        match click_type.try_into().ok() {
-                            Some(click_type) => Event::ButtonUpOrDown {
-                                conn_id,
-                                click_type,
-                                was_queued,
-                                time_diff,
-                            },
-                            _ => Event::CorruptEvent,
-                        }
+                        })
                     }
                     _ => Event::CorruptEvent,
                 },
-                Some(OpCode::ButtonClickOrHold) => match (
+                Some(
+                    opcode @ OpCode::ButtonUpOrDown
+                    | opcode @ OpCode::ButtonClickOrHold
+                    | opcode @ OpCode::ButtonSingleOrDoubleClick
+                    | opcode @ OpCode::ButtonSingleOrDoubleClickOrHold,
+                ) => match (
+                    ButtonEventKind::of_opcode(opcode),
                     self.read_u32(),
                     self.read_enum(),
                     self.read_bool(),
                     self.read_i32(),
                 ) {
-                    (Some(conn_id), Some(click_type), Some(was_queued), Some(time_diff)) => {
-                        match click_type.try_into().ok() {
-                            Some(click_type) => Event::ButtonClickOrHold {
-                                conn_id,
-                                click_type,
-                                was_queued,
-                                time_diff,
-                            },
-                            _ => Event::CorruptEvent,
-                        }
-                    }
-                    _ => Event::CorruptEvent,
-                },
-                Some(OpCode::ButtonSingleOrDoubleClick) => match (
-                    self.read_u32(),
-                    self.read_enum(),
-                    self.read_bool(),
-                    self.read_i32(),
-                ) {
-                    (Some(conn_id), Some(click_type), Some(was_queued), Some(time_diff)) => {
-                        match click_type.try_into().ok() {
-                            Some(click_type) => Event::ButtonSingleOrDoubleClick {
-                                conn_id,
-                                click_type,
-                                was_queued,
-                                time_diff,
-                            },
-                            _ => Event::CorruptEvent,
-                        }
-                    }
-                    _ => Event::CorruptEvent,
-                },
-                Some(OpCode::ButtonSingleOrDoubleClickOrHold) => match (
-                    self.read_u32(),
-                    self.read_enum(),
-                    self.read_bool(),
-                    self.read_i32(),
-                ) {
-                    (Some(conn_id), Some(click_type), Some(was_queued), Some(time_diff)) => {
-                        match click_type.try_into().ok() {
-                            Some(click_type) => Event::ButtonSingleOrDoubleClickOrHold {
-                                conn_id,
-                                click_type,
-                                was_queued,
-                                time_diff,
-                            },
-                            _ => Event::CorruptEvent,
-                        }
-                    }
+                    (
+                        Some(kind),
+                        Some(conn_id),
+                        Some(click_type),
+                        Some(was_queued),
+                        Some(time_diff),
+                    ) => Event::Button(ButtonEvent {
+                        kind,
+                        conn_id,
+                        click_type,
+                        was_queued,
+                        time_diff,
+                    }),
                     _ => Event::CorruptEvent,
                 },
                 Some(OpCode::NewVerifiedButton) => match (self.read_bdaddr(),) {
-                    (Some(bd_addr),) => Event::NewVerifiedButton { bd_addr },
+                    (Some(bd_addr),) => Event::NewVerifiedButton(NewVerifiedButton { bd_addr }),
                     _ => Event::CorruptEvent,
                 },
                 Some(OpCode::GetInfoResponse) => match (
@@ -369,120 +347,125 @@ impl ByteToEventMapper {
                                 None => return Event::CorruptEvent,
                             }
                         }
-                        match (
-                            bluetooth_controller_state.try_into().ok(),
-                            my_bd_addr_type.try_into().ok(),
-                        ) {
-                            (Some(bluetooth_controller_state), Some(my_bd_addr_type)) => {
-                                Event::GetInfoResponse {
-                                    bluetooth_controller_state,
-                                    my_bd_addr,
-                                    my_bd_addr_type,
-                                    max_pending_connections,
-                                    max_concurrently_connected_buttons,
-                                    current_pending_connections,
-                                    currently_no_space_for_new_connection,
-                                    bd_addr_of_verified_buttons,
-                                }
-                            }
-                            _ => Event::CorruptEvent,
-                        }
+                        Event::GetInfoResponse(GetInfoResponse {
+                            bluetooth_controller_state,
+                            my_bd_addr,
+                            my_bd_addr_type,
+                            max_pending_connections,
+                            max_concurrently_connected_buttons,
+                            current_pending_connections,
+                            currently_no_space_for_new_connection,
+                            bd_addr_of_verified_buttons,
+                        })
                     }
                     _ => Event::CorruptEvent,
                 },
                 Some(OpCode::NoSpaceForNewConnection) => match (self.read_u8(),) {
-                    (Some(max_concurrently_connected_buttons),) => Event::NoSpaceForNewConnection {
-                        max_concurrently_connected_buttons,
-                    },
+                    (Some(max_concurrently_connected_buttons),) => {
+                        Event::NoSpaceForNewConnection(NoSpaceForNewConnection {
+                            max_concurrently_connected_buttons,
+                        })
+                    }
                     _ => Event::CorruptEvent,
                 },
                 Some(OpCode::GotSpaceForNewConnection) => match (self.read_u8(),) {
                     (Some(max_concurrently_connected_buttons),) => {
-                        Event::GotSpaceForNewConnection {
+                        Event::GotSpaceForNewConnection(GotSpaceForNewConnection {
                             max_concurrently_connected_buttons,
-                        }
+                        })
                     }
                     _ => Event::CorruptEvent,
                 },
                 Some(OpCode::BluetoothControllerStateChange) => match (self.read_enum(),) {
-                    (Some(state),) => match state.try_into().ok() {
-                        Some(state) => Event::BluetoothControllerStateChange { state },
-                        _ => Event::CorruptEvent,
-                    },
+                    (Some(state),) => {
+                        Event::BluetoothControllerStateChange(BluetoothControllerStateChange {
+                            state,
+                        })
+                    }
                     _ => Event::CorruptEvent,
                 },
                 Some(OpCode::PingResponse) => match (self.read_u32(),) {
-                    (Some(ping_id),) => Event::PingResponse { ping_id },
+                    (Some(ping_id),) => Event::PingResponse(PingResponse { ping_id }),
                     _ => Event::CorruptEvent,
                 },
-                Some(OpCode::GetButtonInfoResponse) => match (
-                    self.read_bdaddr(),
-                    self.read_uuid(),
-                    self.read_string(),
-                    self.read_string(),
-                ) {
-                    (Some(bd_addr), Some(uuid), color, serial_number) => {
-                        Event::GetButtonInfoResponse {
-                            bd_addr,
-                            uuid,
-                            color,
-                            serial_number,
+                Some(OpCode::GetButtonInfoResponse) => {
+                    match (self.read_bdaddr(), self.read_uuid()) {
+                        // the packet ends early if flicd is older than a field
+                        (Some(bd_addr), Some(uuid)) => {
+                            Event::GetButtonInfoResponse(GetButtonInfoResponse {
+                                bd_addr,
+                                uuid,
+                                color: self.read_padded_string(),
+                                serial_number: self.read_padded_string(),
+                                flic_version: self.read_u8(),
+                                firmware_version: self.read_u32(),
+                            })
                         }
+                        _ => Event::CorruptEvent,
                     }
-                    _ => Event::CorruptEvent,
-                },
+                }
                 Some(OpCode::ScanWizardFoundPrivateButton) => match (self.read_u32(),) {
                     (Some(scan_wizard_id),) => {
-                        Event::ScanWizardFoundPrivateButton { scan_wizard_id }
+                        Event::ScanWizardFoundPrivateButton(ScanWizardFoundPrivateButton {
+                            scan_wizard_id,
+                        })
                     }
                     _ => Event::CorruptEvent,
                 },
                 Some(OpCode::ScanWizardButtonConnected) => match (self.read_u32(),) {
-                    (Some(scan_wizard_id),) => Event::ScanWizardButtonConnected { scan_wizard_id },
+                    (Some(scan_wizard_id),) => {
+                        Event::ScanWizardButtonConnected(ScanWizardButtonConnected {
+                            scan_wizard_id,
+                        })
+                    }
                     _ => Event::CorruptEvent,
                 },
                 Some(OpCode::ScanWizardFoundPublicButton) => {
                     match (self.read_u32(), self.read_bdaddr(), self.read_string()) {
                         (Some(scan_wizard_id), Some(bd_addr), Some(name)) => {
-                            Event::ScanWizardFoundPublicButton {
+                            Event::ScanWizardFoundPublicButton(ScanWizardFoundPublicButton {
                                 scan_wizard_id,
                                 bd_addr,
                                 name,
-                            }
+                            })
                         }
                         _ => Event::CorruptEvent,
                     }
                 }
                 Some(OpCode::ScanWizardCompleted) => match (self.read_u32(), self.read_enum()) {
-                    (Some(scan_wizard_id), Some(result)) => match result.try_into().ok() {
-                        Some(result) => Event::ScanWizardCompleted {
+                    (Some(scan_wizard_id), Some(result)) => {
+                        Event::ScanWizardCompleted(ScanWizardCompleted {
                             scan_wizard_id,
                             result,
-                        },
-                        _ => Event::CorruptEvent,
-                    },
+                        })
+                    }
                     _ => Event::CorruptEvent,
                 },
                 Some(OpCode::ButtonDeleted) => match (self.read_bdaddr(), self.read_bool()) {
-                    (Some(bd_addr), Some(deleted_by_this_client)) => Event::ButtonDeleted {
-                        bd_addr,
-                        deleted_by_this_client,
-                    },
+                    (Some(bd_addr), Some(deleted_by_this_client)) => {
+                        Event::ButtonDeleted(ButtonDeleted {
+                            bd_addr,
+                            deleted_by_this_client,
+                        })
+                    }
                     _ => Event::CorruptEvent,
                 },
                 Some(OpCode::BatteryStatus) => {
                     match (self.read_u32(), self.read_i8(), self.read_u64()) {
                         (Some(listener_id), Some(battery_percentage), Some(timestamp)) => {
-                            Event::BatteryStatus {
+                            Event::BatteryStatus(BatteryStatus {
                                 listener_id,
                                 battery_percentage,
                                 timestamp,
-                            }
+                            })
                         }
                         _ => Event::CorruptEvent,
                     }
                 }
-                _ => Event::CorruptEvent,
+                None => Event::Unknown {
+                    opcode,
+                    payload: self.fifo.iter().skip(self.pos).copied().collect(),
+                },
             },
             None => Event::NoOp,
         }
@@ -0,0 +1,60 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use tokio::process::Command as Process;
+use tokio::sync::Semaphore;
+use tracing::{info, warn};
+
+/// Runs shell commands in the background with a limit on how many run at
+/// the same time and how long each one may take.
+///
+/// Commands that exceed the timeout are killed. The exit status of every
+/// command is logged with `tracing`.
+#[derive(Clone)]
+pub struct CommandRunner {
+    semaphore: Arc<Semaphore>,
+    timeout: Duration,
+}
+
+impl Default for CommandRunner {
+    fn default() -> Self {
+        CommandRunner::new(4, Duration::from_secs(30))
+    }
+}
+
+impl CommandRunner {
+    pub fn new(max_concurrent: usize, timeout: Duration) -> CommandRunner {
+        CommandRunner {
+            semaphore: Arc::new(Semaphore::new(max_concurrent.max(1))),
+            timeout,
+        }
+    }
+
+    /// Spawns `sh -c <cmd>` with the given additional environment.
+    ///
+    /// Must be called from within a tokio runtime.
+    pub fn run(&self, cmd: String, env: Vec<(String, String)>) {
+        let semaphore = self.semaphore.clone();
+        let timeout = self.timeout;
+        tokio::spawn(async move {
+            let _permit = semaphore.acquire().await;
+            let child = Process::new("sh")
+                .arg("-c")
+                .arg(&cmd)
+                .envs(env)
+                .kill_on_drop(true)
+                .spawn();
+            match child {
+                Ok(child) => match tokio::time::timeout(timeout, child).await {
+                    Ok(Ok(status)) if status.success() => {
+                        info!(command = %cmd, %status, "command finished")
+                    }
+                    Ok(Ok(status)) => warn!(command = %cmd, %status, "command failed"),
+                    Ok(Err(err)) => warn!(command = %cmd, error = %err, "command failed"),
+                    Err(_) => warn!(command = %cmd, ?timeout, "command killed"),
+                },
+                Err(err) => warn!(command = %cmd, error = %err, "command not started"),
+            }
+        });
+    }
+}
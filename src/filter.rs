@@ -0,0 +1,133 @@
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use super::channels::ChannelMap;
+use super::client::{event_handler, EventClosureMutex};
+use super::enums::ClickType;
+use super::events::*;
+
+/// Selects the events passed to a handler.
+///
+/// Built by chaining, e.g.
+/// `EventFilter::new().for_button(addr).of_type(ClickType::ButtonDoubleClick)`,
+/// and registered with `FlicClient::on`. Without any condition every event
+/// passes. Button conditions look at gestures, so a click reported in
+/// several button events passes once.
+///
+/// Debounce and throttle are kept per connection channel; events of no
+/// channel share one slot.
+#[derive(Debug, Clone, Default)]
+pub struct EventFilter {
+    buttons: Vec<String>,
+    clicks_only: bool,
+    click_types: Vec<ClickType>,
+    exclude_queued: bool,
+    debounce: Option<Duration>,
+    throttle: Option<Duration>,
+    last_seen: HashMap<Option<u32>, Instant>,
+    last_passed: HashMap<Option<u32>, Instant>,
+}
+
+impl EventFilter {
+    pub fn new() -> EventFilter {
+        EventFilter::default()
+    }
+
+    /// Only events of a button. Can be called again to allow several.
+    pub fn for_button(mut self, bd_addr: &str) -> Self {
+        self.buttons.push(bd_addr.to_lowercase());
+        self
+    }
+
+    /// Only clicks, single clicks and double clicks.
+    pub fn clicks_only(mut self) -> Self {
+        self.clicks_only = true;
+        self
+    }
+
+    /// Only gestures of a click type. Can be called again to allow several.
+    pub fn of_type(mut self, click_type: ClickType) -> Self {
+        self.click_types.push(click_type);
+        self
+    }
+
+    /// Drops button events flicd queued while the button was disconnected.
+    pub fn exclude_queued(mut self) -> Self {
+        self.exclude_queued = true;
+        self
+    }
+
+    /// Drops events following a matching event by less than `quiet`, so a
+    /// burst passes only its first event.
+    pub fn debounce(mut self, quiet: Duration) -> Self {
+        self.debounce = Some(quiet);
+        self
+    }
+
+    /// Passes at most one event per `interval`.
+    pub fn throttle(mut self, interval: Duration) -> Self {
+        self.throttle = Some(interval);
+        self
+    }
+
+    /// Whether an event meets the conditions, leaving debounce and throttle
+    /// aside. `bd_addr` is the button of the event, if known.
+    pub fn matches(&self, event: &Event, bd_addr: Option<&str>) -> bool {
+        if !self.buttons.is_empty()
+            && !bd_addr.is_some_and(|b| self.buttons.iter().any(|w| w.eq_ignore_ascii_case(b)))
+        {
+            return false;
+        }
+        if self.exclude_queued && event.button().is_some_and(|button| button.was_queued) {
+            return false;
+        }
+        if self.clicks_only || !self.click_types.is_empty() {
+            let gesture = match event.gesture() {
+                Some(gesture) => gesture,
+                None => return false,
+            };
+            if self.clicks_only && !gesture.is_click() {
+                return false;
+            }
+            if !self.click_types.is_empty() && !self.click_types.contains(&gesture.click_type) {
+                return false;
+            }
+        }
+        true
+    }
+
+    /// Whether an event received at `now` passes, updating debounce and
+    /// throttle.
+    pub fn accept(&mut self, event: &Event, bd_addr: Option<&str>, now: Instant) -> bool {
+        if !self.matches(event, bd_addr) {
+            return false;
+        }
+        let slot = event.conn_id();
+        if let Some(quiet) = self.debounce {
+            let previous = self.last_seen.insert(slot, now);
+            if previous.is_some_and(|seen| now.duration_since(seen) < quiet) {
+                return false;
+            }
+        }
+        if let Some(interval) = self.throttle {
+            if let Some(passed) = self.last_passed.get(&slot) {
+                if now.duration_since(*passed) < interval {
+                    return false;
+                }
+            }
+        }
+        self.last_passed.insert(slot, now);
+        true
+    }
+
+    /// Wraps an event handler so it only sees the events passing this
+    /// filter. `channels` resolves the buttons of connection channels.
+    pub fn handler(mut self, channels: ChannelMap, mut f: EventClosureMutex) -> EventClosureMutex {
+        event_handler(move |event| {
+            let bd_addr = channels.bd_addr_of(event);
+            if self.accept(event, bd_addr.as_deref(), Instant::now()) {
+                f(event);
+            }
+        })
+    }
+}
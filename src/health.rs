@@ -0,0 +1,112 @@
+use std::time::{Duration, Instant};
+
+/// Heartbeat pings use ids from here on, apart from ids chosen by users.
+const FIRST_PING_ID: u32 = 0x8000_0000;
+
+pub type HealthClosure = dyn FnMut(&Health) + Sync + Send + 'static;
+pub type HealthClosureMutex = Box<HealthClosure>;
+
+/// Wraps a closure called when the server is declared dead.
+pub fn health_handler<F>(f: F) -> HealthClosureMutex
+where
+    F: FnMut(&Health) + Sync + Send + 'static,
+{
+    Box::new(f)
+}
+
+/// Snapshot of the heartbeat of a client.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Health {
+    /// False once too many pings in a row went unanswered.
+    pub alive: bool,
+    /// Pings in a row without a response.
+    pub missed: u32,
+    pub pings_sent: u64,
+    pub last_rtt: Option<Duration>,
+    /// Mean round trip time of all answered pings.
+    pub avg_rtt: Option<Duration>,
+    pub last_response: Option<Instant>,
+}
+
+impl Default for Health {
+    fn default() -> Self {
+        Health {
+            alive: true,
+            missed: 0,
+            pings_sent: 0,
+            last_rtt: None,
+            avg_rtt: None,
+            last_response: None,
+        }
+    }
+}
+
+/// Ping schedule and bookkeeping of the heartbeat, independent of any IO.
+pub(crate) struct Heartbeat {
+    interval: Duration,
+    max_missed: u32,
+    next_id: u32,
+    outstanding: Option<(u32, Instant)>,
+    last_sent: Option<Instant>,
+    answered: u32,
+    rtt_total: Duration,
+    health: Health,
+}
+
+impl Heartbeat {
+    pub(crate) fn new(interval: Duration, max_missed: u32) -> Heartbeat {
+        Heartbeat {
+            interval,
+            max_missed: max_missed.max(1),
+            next_id: FIRST_PING_ID,
+            outstanding: None,
+            last_sent: None,
+            answered: 0,
+            rtt_total: Duration::default(),
+            health: Health::default(),
+        }
+    }
+
+    pub(crate) fn health(&self) -> Health {
+        self.health.clone()
+    }
+
+    /// The id of the ping to send now, if one is due, and the health if the
+    /// server was just declared dead.
+    pub(crate) fn due(&mut self, now: Instant) -> (Option<u32>, Option<Health>) {
+        if self
+            .last_sent
+            .is_some_and(|sent| now.duration_since(sent) < self.interval)
+        {
+            return (None, None);
+        }
+        if self.outstanding.take().is_some() {
+            self.health.missed += 1;
+            if self.health.alive && self.health.missed >= self.max_missed {
+                self.health.alive = false;
+                return (None, Some(self.health()));
+            }
+        }
+        let ping_id = self.next_id;
+        self.next_id = self.next_id.wrapping_add(1).max(FIRST_PING_ID);
+        self.outstanding = Some((ping_id, now));
+        self.last_sent = Some(now);
+        self.health.pings_sent += 1;
+        (Some(ping_id), None)
+    }
+
+    pub(crate) fn response(&mut self, ping_id: u32, now: Instant) {
+        let sent = match self.outstanding {
+            Some((id, sent)) if id == ping_id => sent,
+            _ => return,
+        };
+        self.outstanding = None;
+        let rtt = now.duration_since(sent);
+        self.answered += 1;
+        self.rtt_total += rtt;
+        self.health.missed = 0;
+        self.health.last_rtt = Some(rtt);
+        self.health.avg_rtt = Some(self.rtt_total / self.answered);
+        self.health.last_response = Some(now);
+    }
+}
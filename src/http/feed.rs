@@ -0,0 +1,288 @@
+use std::collections::VecDeque;
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+
+use futures::{SinkExt, StreamExt};
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{header, Body, Request, Response, Server, StatusCode};
+use serde_json::{json, Value};
+use sha1::{Digest, Sha1};
+use tokio::sync::broadcast;
+use tokio_tungstenite::tungstenite::protocol::Role;
+use tokio_tungstenite::tungstenite::Message;
+use tokio_tungstenite::WebSocketStream;
+use tracing::warn;
+
+use super::{error, query_param};
+use crate::channels::ChannelMap;
+use crate::client::*;
+use crate::events::*;
+use crate::json::event_to_json;
+
+const WEBSOCKET_GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+
+/// Which events a feed subscriber receives.
+///
+/// Parsed from the query string: `bd_addr` and `event` take comma
+/// separated lists, `replay` the number of past events to send first.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct FeedFilter {
+    pub bd_addrs: Vec<String>,
+    pub events: Vec<String>,
+    pub replay: usize,
+}
+
+impl FeedFilter {
+    pub fn from_query(query: Option<&str>) -> FeedFilter {
+        let list = |name| -> Vec<String> {
+            query_param(query, name)
+                .map(|value| {
+                    value
+                        .split(',')
+                        .filter(|item| !item.is_empty())
+                        .map(|item| item.to_lowercase())
+                        .collect()
+                })
+                .unwrap_or_default()
+        };
+        FeedFilter {
+            bd_addrs: list("bd_addr"),
+            events: list("event"),
+            replay: query_param(query, "replay")
+                .and_then(|replay| replay.parse().ok())
+                .unwrap_or(0),
+        }
+    }
+
+    /// Whether an event, as JSON from the feed, passes the filter.
+    pub fn matches(&self, event: &Value) -> bool {
+        let bd_addr_matches = self.bd_addrs.is_empty()
+            || event["bd_addr"].as_str().is_some_and(|bd_addr| {
+                self.bd_addrs
+                    .iter()
+                    .any(|b| b.eq_ignore_ascii_case(bd_addr))
+            });
+        let event_matches = self.events.is_empty()
+            || event["event"]
+                .as_str()
+                .is_some_and(|name| self.events.iter().any(|e| e == name));
+        bd_addr_matches && event_matches
+    }
+}
+
+struct FeedState {
+    history: VecDeque<Value>,
+    capacity: usize,
+}
+
+/// Live feed of the client's events as JSON over Server-Sent Events
+/// (`GET /events`) and WebSocket (`GET /ws`).
+///
+/// Every event carries its name in `event` and, when known, the button
+/// address in `bd_addr`. Subscribers choose what they get with a
+/// [`FeedFilter`] in the query string, e.g.
+/// `/ws?bd_addr=80:e4:da:76:fa:55&event=button_single_or_double_click_or_hold&replay=10`.
+#[derive(Clone)]
+pub struct EventFeed {
+    state: Arc<Mutex<FeedState>>,
+    channels: ChannelMap,
+    sender: broadcast::Sender<Value>,
+}
+
+impl Default for EventFeed {
+    fn default() -> Self {
+        EventFeed::new()
+    }
+}
+
+impl EventFeed {
+    pub fn new() -> EventFeed {
+        let (sender, _) = broadcast::channel(256);
+        EventFeed {
+            state: Arc::new(Mutex::new(FeedState {
+                history: VecDeque::new(),
+                capacity: 100,
+            })),
+            channels: ChannelMap::new(),
+            sender,
+        }
+    }
+
+    /// How many past events are kept for replay.
+    pub fn history(self, capacity: usize) -> Self {
+        self.state.lock().unwrap().capacity = capacity;
+        self
+    }
+
+    /// Resolves the buttons of click events, usually `client.channels()`.
+    pub fn channels(mut self, channels: ChannelMap) -> Self {
+        self.channels = channels;
+        self
+    }
+
+    /// Records an event and sends it to all subscribers.
+    pub fn publish(&self, event: &Event) {
+        let mut value = event_to_json(event);
+        if let Some(bd_addr) = self.channels.bd_addr_of(event) {
+            value["bd_addr"] = json!(bd_addr);
+        }
+        {
+            let mut state = self.state.lock().unwrap();
+            if state.capacity > 0 {
+                while state.history.len() >= state.capacity {
+                    state.history.pop_front();
+                }
+                state.history.push_back(value.clone());
+            }
+        }
+        let _ = self.sender.send(value);
+    }
+
+    pub fn handler(&self) -> EventClosureMutex {
+        let feed = self.clone();
+        event_handler(move |event| feed.publish(event))
+    }
+
+    /// Subscribes before taking the replay so no event is lost in between.
+    fn subscribe(&self, filter: &FeedFilter) -> (Vec<Value>, broadcast::Receiver<Value>) {
+        let state = self.state.lock().unwrap();
+        let receiver = self.sender.subscribe();
+        let matching: Vec<&Value> = state
+            .history
+            .iter()
+            .filter(|event| filter.matches(event))
+            .collect();
+        let skip = matching.len().saturating_sub(filter.replay);
+        let replay = matching.into_iter().skip(skip).cloned().collect();
+        (replay, receiver)
+    }
+
+    fn sse(&self, filter: FeedFilter) -> Response<Body> {
+        let (replay, mut receiver) = self.subscribe(&filter);
+        let (mut body, stream) = Body::channel();
+        tokio::spawn(async move {
+            for event in replay {
+                if body.send_data(sse_message(&event).into()).await.is_err() {
+                    return;
+                }
+            }
+            loop {
+                match receiver.recv().await {
+                    Ok(event) if filter.matches(&event) => {
+                        if body.send_data(sse_message(&event).into()).await.is_err() {
+                            return;
+                        }
+                    }
+                    Ok(_) | Err(broadcast::RecvError::Lagged(_)) => {}
+                    Err(broadcast::RecvError::Closed) => return,
+                }
+            }
+        });
+        Response::builder()
+            .header(header::CONTENT_TYPE, "text/event-stream")
+            .header(header::CACHE_CONTROL, "no-cache")
+            .body(stream)
+            .unwrap()
+    }
+
+    fn websocket(&self, request: Request<Body>, filter: FeedFilter) -> Response<Body> {
+        let key = match request.headers().get(header::SEC_WEBSOCKET_KEY) {
+            Some(key) => key.as_bytes().to_vec(),
+            None => return error(StatusCode::BAD_REQUEST, "not a WebSocket request"),
+        };
+        let (replay, mut receiver) = self.subscribe(&filter);
+        tokio::spawn(async move {
+            let upgraded = match request.into_body().on_upgrade().await {
+                Ok(upgraded) => upgraded,
+                Err(err) => {
+                    warn!(error = %err, "websocket upgrade failed");
+                    return;
+                }
+            };
+            let socket = WebSocketStream::from_raw_socket(upgraded, Role::Server, None).await;
+            let (mut sink, mut incoming) = socket.split();
+            for event in replay {
+                if sink.send(Message::Text(event.to_string())).await.is_err() {
+                    return;
+                }
+            }
+            loop {
+                tokio::select! {
+                    event = receiver.recv() => match event {
+                        Ok(event) if filter.matches(&event) => {
+                            if sink.send(Message::Text(event.to_string())).await.is_err() {
+                                return;
+                            }
+                        }
+                        Ok(_) | Err(broadcast::RecvError::Lagged(_)) => {}
+                        Err(broadcast::RecvError::Closed) => return,
+                    },
+                    message = incoming.next() => match message {
+                        Some(Ok(Message::Close(_))) | Some(Err(_)) | None => return,
+                        Some(Ok(_)) => {}
+                    },
+                }
+            }
+        });
+        Response::builder()
+            .status(StatusCode::SWITCHING_PROTOCOLS)
+            .header(header::UPGRADE, "websocket")
+            .header(header::CONNECTION, "Upgrade")
+            .header(header::SEC_WEBSOCKET_ACCEPT, accept_key(&key))
+            .body(Body::empty())
+            .unwrap()
+    }
+
+    /// Whether a request is for the feed.
+    pub fn handles(&self, request: &Request<Body>) -> bool {
+        request.method() == hyper::Method::GET
+            && matches!(
+                request.uri().path().trim_end_matches('/'),
+                "/events" | "/ws"
+            )
+    }
+
+    /// Answers `GET /events` and `GET /ws`.
+    pub fn respond(&self, request: Request<Body>) -> Response<Body> {
+        if !self.handles(&request) {
+            return error(StatusCode::NOT_FOUND, "not found");
+        }
+        let filter = FeedFilter::from_query(request.uri().query());
+        if request.uri().path().trim_end_matches('/') == "/ws" {
+            self.websocket(request, filter)
+        } else {
+            self.sse(filter)
+        }
+    }
+
+    /// Serves only the feed on `addr` until the server fails.
+    pub async fn serve(&self, addr: SocketAddr) -> hyper::Result<()> {
+        let feed = self.clone();
+        let service = make_service_fn(move |_| {
+            let feed = feed.clone();
+            async move {
+                Ok::<_, Infallible>(service_fn(move |request| {
+                    let response = feed.respond(request);
+                    async move { Ok::<_, Infallible>(response) }
+                }))
+            }
+        });
+        Server::bind(&addr).serve(service).await
+    }
+}
+
+fn sse_message(event: &Value) -> String {
+    format!(
+        "event: {}\ndata: {}\n\n",
+        event["event"].as_str().unwrap_or("message"),
+        event
+    )
+}
+
+fn accept_key(key: &[u8]) -> String {
+    let mut sha1 = Sha1::new();
+    sha1.update(key);
+    sha1.update(WEBSOCKET_GUID.as_bytes());
+    base64::encode(sha1.finalize())
+}
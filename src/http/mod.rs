@@ -0,0 +1,560 @@
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use futures::future::join_all;
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Method, Request, Response, Server, StatusCode};
+use serde_json::{json, Value};
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender};
+use tokio::sync::oneshot;
+
+use super::client::*;
+use super::commands::{is_bd_addr, Command};
+use super::enums::*;
+use super::events::*;
+use super::json::event_to_json;
+use super::profile::{AutoDisconnect, ConnectionProfile};
+
+mod feed;
+
+pub use feed::*;
+
+#[derive(Default)]
+struct ApiButton {
+    conn_id: Option<u32>,
+    listener_id: Option<u32>,
+    connection_status: Option<ConnectionStatus>,
+    battery_percentage: Option<i8>,
+    info: Option<Value>,
+}
+
+struct Waiter {
+    id: u32,
+    matches: Box<dyn Fn(&Event) -> bool + Send>,
+    tx: oneshot::Sender<Event>,
+}
+
+struct ApiState {
+    buttons: HashMap<String, ApiButton>,
+    waiters: Vec<Waiter>,
+    next_id: u32,
+}
+
+impl ApiState {
+    fn next_id(&mut self) -> u32 {
+        self.next_id += 1;
+        self.next_id
+    }
+
+    fn button_of_conn(&mut self, conn_id: u32) -> Option<&mut ApiButton> {
+        self.buttons
+            .values_mut()
+            .find(|button| button.conn_id == Some(conn_id))
+    }
+
+    fn handle_event(&mut self, event: &Event) {
+        match event {
+            Event::CreateConnectionChannelResponse(CreateConnectionChannelResponse {
+                conn_id,
+                connection_status,
+                ..
+            })
+            | Event::ConnectionStatusChanged(ConnectionStatusChanged {
+                conn_id,
+                connection_status,
+                ..
+            }) => {
+                if let Some(button) = self.button_of_conn(*conn_id) {
+                    button.connection_status = Some(*connection_status);
+                }
+            }
+            Event::ConnectionChannelRemoved(ConnectionChannelRemoved { conn_id, .. }) => {
+                if let Some(button) = self.button_of_conn(*conn_id) {
+                    button.conn_id = None;
+                    button.connection_status = None;
+                }
+            }
+            Event::GetButtonInfoResponse(GetButtonInfoResponse { bd_addr, .. }) => {
+                let mut info = event_to_json(event);
+                if let Some(info) = info.as_object_mut() {
+                    info.remove("event");
+                    info.remove("bd_addr");
+                }
+                self.buttons.entry(bd_addr.to_lowercase()).or_default().info = Some(info);
+            }
+            Event::BatteryStatus(BatteryStatus {
+                listener_id,
+                battery_percentage,
+                ..
+            }) => {
+                if let Some(button) = self
+                    .buttons
+                    .values_mut()
+                    .find(|button| button.listener_id == Some(*listener_id))
+                {
+                    button.battery_percentage = Some(*battery_percentage);
+                }
+            }
+            Event::ButtonDeleted(ButtonDeleted { bd_addr, .. }) => {
+                self.buttons.remove(&bd_addr.to_lowercase());
+            }
+            _ => {}
+        }
+
+        let mut i = 0;
+        while i < self.waiters.len() {
+            if (self.waiters[i].matches)(event) {
+                let waiter = self.waiters.remove(i);
+                let _ = waiter.tx.send(event.clone());
+            } else {
+                i += 1;
+            }
+        }
+    }
+}
+
+fn button_json(bd_addr: &str, button: Option<&ApiButton>) -> Value {
+    let mut value = json!({ "bd_addr": bd_addr });
+    if let Some(button) = button {
+        if let Some(Value::Object(info)) = &button.info {
+            for (key, field) in info {
+                value[key] = field.clone();
+            }
+        }
+        value["conn_id"] = json!(button.conn_id);
+        value["connection_status"] = json!(button
+            .connection_status
+            .map(|status| format!("{:?}", status)));
+        value["battery_percentage"] = json!(button.battery_percentage);
+    }
+    value
+}
+
+fn query_param<'a>(query: Option<&'a str>, name: &str) -> Option<&'a str> {
+    query?
+        .split('&')
+        .filter_map(|pair| {
+            let mut parts = pair.splitn(2, '=');
+            Some((parts.next()?, parts.next().unwrap_or("")))
+        })
+        .find(|(key, _)| *key == name)
+        .map(|(_, value)| value)
+}
+
+fn response(status: StatusCode, body: Value) -> Response<Body> {
+    Response::builder()
+        .status(status)
+        .header("Content-Type", "application/json")
+        .body(Body::from(body.to_string()))
+        .unwrap()
+}
+
+fn error(status: StatusCode, message: &str) -> Response<Body> {
+    response(status, json!({ "error": message }))
+}
+
+fn without_event_name(event: &Event) -> Value {
+    let mut value = event_to_json(event);
+    if let Some(object) = value.as_object_mut() {
+        object.remove("event");
+    }
+    value
+}
+
+/// REST API in front of a flicd connection.
+///
+/// | Route | |
+/// |-------|-|
+/// | `GET /info` | the server's `GetInfoResponse` |
+/// | `GET /buttons` | all verified buttons |
+/// | `GET /buttons/{bd_addr}` | button info and battery level |
+/// | `POST /buttons/{bd_addr}/connect` | opens a connection channel, `?profile=normal\|responsive\|battery-saver&latency=low\|normal\|high&auto_disconnect_time=<secs>` |
+/// | `DELETE /buttons/{bd_addr}` | deletes a verified button |
+/// | `POST /scan-wizard` | runs the scan wizard and returns its result |
+///
+/// With a [`EventFeed`] the live events are served as well.
+///
+/// Requests waiting for flicd answer `504 Gateway Timeout` when it does not
+/// respond in time.
+#[derive(Clone)]
+pub struct HttpApi {
+    state: Arc<Mutex<ApiState>>,
+    commands: UnboundedSender<Command>,
+    receiver: Arc<Mutex<Option<UnboundedReceiver<Command>>>>,
+    timeout: Duration,
+    wizard_timeout: Duration,
+    feed: Option<EventFeed>,
+}
+
+impl Default for HttpApi {
+    fn default() -> Self {
+        HttpApi::new()
+    }
+}
+
+impl HttpApi {
+    pub fn new() -> HttpApi {
+        let (commands, receiver) = unbounded_channel();
+        HttpApi {
+            state: Arc::new(Mutex::new(ApiState {
+                buttons: HashMap::new(),
+                waiters: vec![],
+                next_id: 0,
+            })),
+            commands,
+            receiver: Arc::new(Mutex::new(Some(receiver))),
+            timeout: Duration::from_secs(5),
+            wizard_timeout: Duration::from_secs(60),
+            feed: None,
+        }
+    }
+
+    /// How long to wait for flicd to answer a request.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Also serves the live event feed at `/events` and `/ws`.
+    pub fn feed(mut self, feed: EventFeed) -> Self {
+        self.feed = Some(feed);
+        self
+    }
+
+    pub fn handler(&self) -> EventClosureMutex {
+        let state = self.state.clone();
+        let feed = self.feed.clone();
+        event_handler(move |event| {
+            state.lock().unwrap().handle_event(event);
+            if let Some(feed) = &feed {
+                feed.publish(event);
+            }
+        })
+    }
+
+    /// Submits commands and waits for the first event accepted by `matches`.
+    async fn request<F>(
+        &self,
+        commands: Vec<Command>,
+        matches: F,
+        timeout: Duration,
+    ) -> Option<Event>
+    where
+        F: Fn(&Event) -> bool + Send + 'static,
+    {
+        let (tx, rx) = oneshot::channel();
+        let id = {
+            let mut state = self.state.lock().unwrap();
+            let id = state.next_id();
+            state.waiters.push(Waiter {
+                id,
+                matches: Box::new(matches),
+                tx,
+            });
+            id
+        };
+        for command in commands {
+            let _ = self.commands.send(command);
+        }
+        match tokio::time::timeout(timeout, rx).await {
+            Ok(Ok(event)) => Some(event),
+            _ => {
+                self.state
+                    .lock()
+                    .unwrap()
+                    .waiters
+                    .retain(|waiter| waiter.id != id);
+                None
+            }
+        }
+    }
+
+    async fn info(&self) -> Option<Event> {
+        self.request(
+            vec![Command::GetInfo],
+            |event| matches!(event, Event::GetInfoResponse(_)),
+            self.timeout,
+        )
+        .await
+    }
+
+    async fn button_info(&self, bd_addr: &str) -> Option<Event> {
+        let wanted = bd_addr.to_string();
+        self.request(
+            vec![Command::GetButtonInfo {
+                bd_addr: bd_addr.to_string(),
+            }],
+            move |event| {
+                matches!(event, Event::GetButtonInfoResponse(GetButtonInfoResponse { bd_addr, .. }) if bd_addr.eq_ignore_ascii_case(&wanted))
+            },
+            self.timeout,
+        )
+        .await
+    }
+
+    fn button(&self, bd_addr: &str) -> Value {
+        let state = self.state.lock().unwrap();
+        button_json(bd_addr, state.buttons.get(bd_addr))
+    }
+
+    async fn get_info(&self) -> Response<Body> {
+        match self.info().await {
+            Some(event) => response(StatusCode::OK, without_event_name(&event)),
+            None => error(StatusCode::GATEWAY_TIMEOUT, "flicd did not respond"),
+        }
+    }
+
+    async fn get_buttons(&self) -> Response<Body> {
+        let verified = match self.info().await {
+            Some(Event::GetInfoResponse(GetInfoResponse {
+                bd_addr_of_verified_buttons,
+                ..
+            })) => bd_addr_of_verified_buttons,
+            _ => return error(StatusCode::GATEWAY_TIMEOUT, "flicd did not respond"),
+        };
+        let missing: Vec<&String> = {
+            let state = self.state.lock().unwrap();
+            verified
+                .iter()
+                .filter(|bd_addr| {
+                    state
+                        .buttons
+                        .get(*bd_addr)
+                        .is_none_or(|button| button.info.is_none())
+                })
+                .collect()
+        };
+        join_all(missing.into_iter().map(|bd_addr| self.button_info(bd_addr))).await;
+        let buttons: Vec<Value> = verified
+            .iter()
+            .map(|bd_addr| self.button(bd_addr))
+            .collect();
+        response(StatusCode::OK, json!(buttons))
+    }
+
+    async fn get_button(&self, bd_addr: &str) -> Response<Body> {
+        if self.button_info(bd_addr).await.is_none() {
+            return error(StatusCode::GATEWAY_TIMEOUT, "flicd did not respond");
+        }
+        let listener_id = {
+            let mut state = self.state.lock().unwrap();
+            let listener_id = state.next_id();
+            let button = state.buttons.entry(bd_addr.to_string()).or_default();
+            match button.listener_id {
+                Some(_) => None,
+                None => {
+                    button.listener_id = Some(listener_id);
+                    Some(listener_id)
+                }
+            }
+        };
+        if let Some(listener_id) = listener_id {
+            self.request(
+                vec![Command::CreateBatteryStatusListener {
+                    listener_id,
+                    bd_addr: bd_addr.to_string(),
+                }],
+                move |event| matches!(event, Event::BatteryStatus(BatteryStatus { listener_id: id, .. }) if *id == listener_id),
+                self.timeout,
+            )
+            .await;
+        }
+        response(StatusCode::OK, self.button(bd_addr))
+    }
+
+    async fn connect(&self, bd_addr: &str, query: Option<&str>) -> Response<Body> {
+        let profile = match query_param(query, "profile") {
+            None => ConnectionProfile::default(),
+            Some(name) => match ConnectionProfile::named(name) {
+                Some(profile) => profile,
+                None => {
+                    return error(
+                        StatusCode::BAD_REQUEST,
+                        "profile must be normal, responsive or battery-saver",
+                    )
+                }
+            },
+        };
+        let profile = match query_param(query, "latency") {
+            None => profile,
+            Some("normal") => profile.with_latency_mode(LatencyMode::NormalLatency),
+            Some("low") => profile.with_latency_mode(LatencyMode::LowLatency),
+            Some("high") => profile.with_latency_mode(LatencyMode::HighLatency),
+            Some(_) => {
+                return error(
+                    StatusCode::BAD_REQUEST,
+                    "latency must be low, normal or high",
+                )
+            }
+        };
+        let profile = match query_param(query, "auto_disconnect_time") {
+            None => profile,
+            Some(secs) => match secs
+                .parse::<i16>()
+                .ok()
+                .and_then(|secs| AutoDisconnect::from_wire(secs).ok())
+                .and_then(|auto_disconnect| profile.with_auto_disconnect(auto_disconnect).ok())
+            {
+                Some(profile) => profile,
+                None => {
+                    return error(
+                        StatusCode::BAD_REQUEST,
+                        "auto_disconnect_time must be between 0 and 511",
+                    )
+                }
+            },
+        };
+        let existing = {
+            let mut state = self.state.lock().unwrap();
+            let conn_id = state.next_id();
+            let button = state.buttons.entry(bd_addr.to_string()).or_default();
+            let existing = button.conn_id;
+            button.conn_id = existing.or(Some(conn_id));
+            existing.ok_or(conn_id)
+        };
+        let conn_id = match existing {
+            Ok(conn_id) => {
+                let _ = self.commands.send(profile.change_mode(conn_id));
+                return response(StatusCode::OK, self.button(bd_addr));
+            }
+            Err(conn_id) => conn_id,
+        };
+        let answer = self
+            .request(
+                vec![profile.create_channel(conn_id, bd_addr)],
+                move |event| matches!(event, Event::CreateConnectionChannelResponse(CreateConnectionChannelResponse { conn_id: id, .. }) if *id == conn_id),
+                self.timeout,
+            )
+            .await;
+        match answer {
+            Some(Event::CreateConnectionChannelResponse(CreateConnectionChannelResponse {
+                error: err,
+                ..
+            })) if err != CreateConnectionChannelError::NoError => {
+                if let Some(button) = self.state.lock().unwrap().buttons.get_mut(bd_addr) {
+                    button.conn_id = None;
+                }
+                error(StatusCode::CONFLICT, &format!("{:?}", err))
+            }
+            Some(_) => response(StatusCode::OK, self.button(bd_addr)),
+            None => error(StatusCode::GATEWAY_TIMEOUT, "flicd did not respond"),
+        }
+    }
+
+    async fn delete(&self, bd_addr: &str) -> Response<Body> {
+        let wanted = bd_addr.to_string();
+        let answer = self
+            .request(
+                vec![Command::DeleteButton {
+                    bd_addr: bd_addr.to_string(),
+                }],
+                move |event| {
+                    matches!(event, Event::ButtonDeleted(ButtonDeleted { bd_addr, .. }) if bd_addr.eq_ignore_ascii_case(&wanted))
+                },
+                self.timeout,
+            )
+            .await;
+        match answer {
+            Some(_) => Response::builder()
+                .status(StatusCode::NO_CONTENT)
+                .body(Body::empty())
+                .unwrap(),
+            None => error(StatusCode::GATEWAY_TIMEOUT, "flicd did not respond"),
+        }
+    }
+
+    async fn scan_wizard(&self) -> Response<Body> {
+        let scan_wizard_id = self.state.lock().unwrap().next_id();
+        let found = Arc::new(Mutex::new(None));
+        let seen = found.clone();
+        let answer = self
+            .request(
+                vec![Command::CreateScanWizard { scan_wizard_id }],
+                move |event| match event {
+                    Event::ScanWizardFoundPublicButton(ScanWizardFoundPublicButton {
+                        scan_wizard_id: id,
+                        bd_addr,
+                        name,
+                    }) if *id == scan_wizard_id => {
+                        *seen.lock().unwrap() = Some((bd_addr.clone(), name.clone()));
+                        false
+                    }
+                    Event::ScanWizardCompleted(ScanWizardCompleted {
+                        scan_wizard_id: id, ..
+                    }) => *id == scan_wizard_id,
+                    _ => false,
+                },
+                self.wizard_timeout,
+            )
+            .await;
+        match answer {
+            Some(Event::ScanWizardCompleted(ScanWizardCompleted { result, .. })) => {
+                let mut value = json!({ "result": format!("{:?}", result) });
+                if let Some((bd_addr, name)) = found.lock().unwrap().take() {
+                    value["bd_addr"] = json!(bd_addr);
+                    value["name"] = json!(name);
+                }
+                response(StatusCode::OK, value)
+            }
+            _ => {
+                let _ = self
+                    .commands
+                    .send(Command::CancelScanWizard { scan_wizard_id });
+                error(StatusCode::GATEWAY_TIMEOUT, "scan wizard did not complete")
+            }
+        }
+    }
+
+    /// Answers a single HTTP request.
+    pub async fn respond(&self, request: Request<Body>) -> Response<Body> {
+        if let Some(feed) = &self.feed {
+            if feed.handles(&request) {
+                return feed.respond(request);
+            }
+        }
+        let path = request.uri().path().to_lowercase();
+        let query = request.uri().query();
+        let segments: Vec<&str> = path.trim_matches('/').split('/').collect();
+        match (request.method(), &segments[..]) {
+            (&Method::GET, ["info"]) => self.get_info().await,
+            (&Method::GET, ["buttons"]) => self.get_buttons().await,
+            (&Method::POST, ["scan-wizard"]) => self.scan_wizard().await,
+            (_, ["buttons", bd_addr, ..]) if !is_bd_addr(bd_addr) => {
+                error(StatusCode::BAD_REQUEST, "invalid Bluetooth address")
+            }
+            (&Method::GET, ["buttons", bd_addr]) => self.get_button(bd_addr).await,
+            (&Method::DELETE, ["buttons", bd_addr]) => self.delete(bd_addr).await,
+            (&Method::POST, ["buttons", bd_addr, "connect"]) => self.connect(bd_addr, query).await,
+            _ => error(StatusCode::NOT_FOUND, "not found"),
+        }
+    }
+
+    /// Serves the API on `addr` until the server fails.
+    ///
+    /// Can only be run once per API.
+    pub async fn serve(&self, client: Arc<FlicClient>, addr: SocketAddr) -> hyper::Result<()> {
+        let mut receiver = match self.receiver.lock().unwrap().take() {
+            Some(receiver) => receiver,
+            None => return Ok(()),
+        };
+        tokio::spawn(async move {
+            while let Some(command) = receiver.recv().await {
+                client.submit(command).await;
+            }
+        });
+        let api = self.clone();
+        let service = make_service_fn(move |_| {
+            let api = api.clone();
+            async move {
+                Ok::<_, Infallible>(service_fn(move |request| {
+                    let api = api.clone();
+                    async move { Ok::<_, Infallible>(api.respond(request).await) }
+                }))
+            }
+        });
+        Server::bind(&addr).serve(service).await
+    }
+}
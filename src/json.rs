@@ -0,0 +1,16 @@
+use serde_json::Value;
+
+use super::events::*;
+
+/// Snake case name of an event, e.g. `button_up_or_down`.
+pub fn event_name(event: &Event) -> &'static str {
+    event.name()
+}
+
+/// JSON object of an event with its name in the `event` field.
+///
+/// This is the `serde` representation of [`Event`]; enum values are written
+/// with their Rust variant names.
+pub fn event_to_json(event: &Event) -> Value {
+    serde_json::to_value(event).unwrap_or(Value::Null)
+}
@@ -1,9 +1,70 @@
+mod channels;
 mod client;
+mod cluster;
 mod commands;
 mod enums;
 mod events;
+#[cfg(feature = "rules")]
+mod exec;
+mod filter;
+mod health;
+#[cfg(feature = "http")]
+mod http;
+#[cfg(feature = "json")]
+pub mod json;
+#[cfg(feature = "metrics")]
+mod metrics;
+#[cfg(feature = "mqtt")]
+mod mqtt;
+mod outage;
+mod policy;
+mod profile;
+mod proxy;
+#[cfg(feature = "record")]
+mod record;
+#[cfg(feature = "rules")]
+mod rules;
+#[cfg(feature = "schedule")]
+mod schedule;
+mod state;
+#[cfg(feature = "webhook")]
+mod webhook;
 
+pub use channels::*;
 pub use client::*;
-pub use commands::Command;
+pub use cluster::*;
+pub use commands::stream_mapper::CommandToByteMapper;
+pub use commands::{is_bd_addr, Command};
 pub use enums::*;
-pub use events::Event;
+pub use events::stream_mapper::{ByteToEventMapper, EventResult};
+pub use events::{
+    AdvertisementPacket, BatteryStatus, BluetoothControllerStateChange, ButtonDeleted, ButtonEvent,
+    ButtonEventKind, ButtonModel, ConnectionChannelRemoved, ConnectionStatusChanged,
+    CreateConnectionChannelResponse, Event, GetButtonInfoResponse, GetInfoResponse,
+    GotSpaceForNewConnection, NewVerifiedButton, NoSpaceForNewConnection, PingResponse,
+    ScanWizardButtonConnected, ScanWizardCompleted, ScanWizardFoundPrivateButton,
+    ScanWizardFoundPublicButton,
+};
+#[cfg(feature = "rules")]
+pub use exec::*;
+pub use filter::*;
+pub use health::{health_handler, Health, HealthClosure, HealthClosureMutex};
+#[cfg(feature = "http")]
+pub use http::*;
+#[cfg(feature = "metrics")]
+pub use metrics::*;
+#[cfg(feature = "mqtt")]
+pub use mqtt::*;
+pub use outage::*;
+pub use policy::*;
+pub use profile::*;
+pub use proxy::*;
+#[cfg(feature = "record")]
+pub use record::*;
+#[cfg(feature = "rules")]
+pub use rules::*;
+#[cfg(feature = "schedule")]
+pub use schedule::*;
+pub use state::*;
+#[cfg(feature = "webhook")]
+pub use webhook::*;
@@ -0,0 +1,332 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::convert::Infallible;
+use std::fmt::Write;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Method, Request, Response, Server, StatusCode};
+
+use super::channels::ChannelMap;
+use super::client::*;
+use super::commands::Command;
+use super::enums::ConnectionStatus;
+use super::events::*;
+
+/// Upper bounds of the ping latency histogram buckets in seconds.
+const PING_BUCKETS: [f64; 10] = [0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0];
+
+const STATUSES: [ConnectionStatus; 3] = [
+    ConnectionStatus::Disconnected,
+    ConnectionStatus::Connected,
+    ConnectionStatus::Ready,
+];
+
+#[derive(Default)]
+struct MetricsState {
+    clicks: BTreeMap<(String, String), u64>,
+    status: BTreeMap<String, ConnectionStatus>,
+    battery: BTreeMap<String, i8>,
+    reconnects: BTreeMap<String, u64>,
+    lost: HashSet<String>,
+    conns: HashMap<u32, String>,
+    pings: HashMap<u32, Instant>,
+    ping_buckets: [u64; PING_BUCKETS.len()],
+    ping_sum: f64,
+    ping_count: u64,
+    pending_connections: Option<(u8, u8)>,
+}
+
+impl MetricsState {
+    fn set_status(&mut self, bd_addr: String, status: ConnectionStatus) {
+        let up = status != ConnectionStatus::Disconnected;
+        let was_up = self
+            .status
+            .get(&bd_addr)
+            .is_some_and(|s| *s != ConnectionStatus::Disconnected);
+        if up && self.lost.remove(&bd_addr) {
+            *self.reconnects.entry(bd_addr.clone()).or_default() += 1;
+        }
+        if !up && was_up {
+            self.lost.insert(bd_addr.clone());
+        }
+        self.status.insert(bd_addr, status);
+    }
+
+    fn observe_ping(&mut self, seconds: f64) {
+        for (bucket, bound) in self.ping_buckets.iter_mut().zip(PING_BUCKETS.iter()) {
+            if seconds <= *bound {
+                *bucket += 1;
+            }
+        }
+        self.ping_sum += seconds;
+        self.ping_count += 1;
+    }
+
+    fn event(&mut self, bd_addr: Option<String>, event: &Event) {
+        if let (Some(bd_addr), Some(gesture)) = (&bd_addr, event.gesture()) {
+            let key = (bd_addr.clone(), format!("{:?}", gesture.click_type));
+            *self.clicks.entry(key).or_default() += 1;
+            return;
+        }
+        match event {
+            Event::CreateConnectionChannelResponse(CreateConnectionChannelResponse {
+                conn_id,
+                connection_status,
+                ..
+            })
+            | Event::ConnectionStatusChanged(ConnectionStatusChanged {
+                conn_id,
+                connection_status,
+                ..
+            }) => {
+                if let Some(bd_addr) = bd_addr {
+                    self.conns.insert(*conn_id, bd_addr.clone());
+                    self.set_status(bd_addr, *connection_status);
+                }
+            }
+            Event::ConnectionChannelRemoved(ConnectionChannelRemoved { conn_id, .. }) => {
+                // the channel map has already forgotten the channel
+                if let Some(bd_addr) = self.conns.remove(conn_id) {
+                    self.status.remove(&bd_addr);
+                    self.lost.remove(&bd_addr);
+                }
+            }
+            Event::BatteryStatus(BatteryStatus {
+                battery_percentage, ..
+            }) => {
+                match bd_addr {
+                    // -1 means the level is unknown
+                    Some(bd_addr) if *battery_percentage < 0 => {
+                        self.battery.remove(&bd_addr);
+                    }
+                    Some(bd_addr) => {
+                        self.battery.insert(bd_addr, *battery_percentage);
+                    }
+                    None => {}
+                }
+            }
+            Event::ButtonDeleted(ButtonDeleted { bd_addr, .. }) => {
+                let bd_addr = bd_addr.to_lowercase();
+                self.status.remove(&bd_addr);
+                self.battery.remove(&bd_addr);
+            }
+            Event::PingResponse(PingResponse { ping_id }) => {
+                if let Some(sent) = self.pings.remove(ping_id) {
+                    self.observe_ping(sent.elapsed().as_secs_f64());
+                }
+            }
+            Event::GetInfoResponse(GetInfoResponse {
+                max_pending_connections,
+                current_pending_connections,
+                ..
+            }) => {
+                self.pending_connections =
+                    Some((*current_pending_connections, *max_pending_connections))
+            }
+            _ => {}
+        }
+    }
+
+    fn render(&self, corrupt_packets: u64) -> String {
+        fn header(out: &mut String, name: &str, kind: &str, help: &str) {
+            let _ = writeln!(out, "# HELP {} {}", name, help);
+            let _ = writeln!(out, "# TYPE {} {}", name, kind);
+        }
+        let mut out = String::new();
+
+        header(
+            &mut out,
+            "flic_clicks_total",
+            "counter",
+            "Gestures by button and click type.",
+        );
+        for ((bd_addr, click_type), count) in &self.clicks {
+            let _ = writeln!(
+                out,
+                "flic_clicks_total{{bd_addr=\"{}\",click_type=\"{}\"}} {}",
+                bd_addr, click_type, count
+            );
+        }
+
+        header(
+            &mut out,
+            "flic_connection_status",
+            "gauge",
+            "Connection status of a button, 1 for the current one.",
+        );
+        for (bd_addr, current) in &self.status {
+            for status in STATUSES.iter() {
+                let _ = writeln!(
+                    out,
+                    "flic_connection_status{{bd_addr=\"{}\",status=\"{:?}\"}} {}",
+                    bd_addr,
+                    status,
+                    (status == current) as u8
+                );
+            }
+        }
+
+        header(
+            &mut out,
+            "flic_battery_percent",
+            "gauge",
+            "Last reported battery level of a button.",
+        );
+        for (bd_addr, percentage) in &self.battery {
+            let _ = writeln!(
+                out,
+                "flic_battery_percent{{bd_addr=\"{}\"}} {}",
+                bd_addr, percentage
+            );
+        }
+
+        header(
+            &mut out,
+            "flic_reconnects_total",
+            "counter",
+            "Times a button connected again after losing its connection.",
+        );
+        for (bd_addr, count) in &self.reconnects {
+            let _ = writeln!(
+                out,
+                "flic_reconnects_total{{bd_addr=\"{}\"}} {}",
+                bd_addr, count
+            );
+        }
+
+        header(
+            &mut out,
+            "flic_corrupt_packets_total",
+            "counter",
+            "Packets from flicd that could not be decoded.",
+        );
+        let _ = writeln!(out, "flic_corrupt_packets_total {}", corrupt_packets);
+
+        header(
+            &mut out,
+            "flic_ping_seconds",
+            "histogram",
+            "Round trip time of pings to flicd.",
+        );
+        for (count, bound) in self.ping_buckets.iter().zip(PING_BUCKETS.iter()) {
+            let _ = writeln!(
+                out,
+                "flic_ping_seconds_bucket{{le=\"{}\"}} {}",
+                bound, count
+            );
+        }
+        let _ = writeln!(
+            out,
+            "flic_ping_seconds_bucket{{le=\"+Inf\"}} {}",
+            self.ping_count
+        );
+        let _ = writeln!(out, "flic_ping_seconds_sum {}", self.ping_sum);
+        let _ = writeln!(out, "flic_ping_seconds_count {}", self.ping_count);
+
+        if let Some((current, max)) = self.pending_connections {
+            header(
+                &mut out,
+                "flic_pending_connections",
+                "gauge",
+                "Pending connections at the last GetInfo.",
+            );
+            let _ = writeln!(out, "flic_pending_connections {}", current);
+            header(
+                &mut out,
+                "flic_max_pending_connections",
+                "gauge",
+                "Maximum pending connections at the last GetInfo.",
+            );
+            let _ = writeln!(out, "flic_max_pending_connections {}", max);
+        }
+        out
+    }
+}
+
+/// Prometheus metrics of the events a client receives.
+///
+/// Register `handler()` for the events and `packet_handler()` to time pings,
+/// and pass the client's channels so events can be attributed to buttons and
+/// its `corrupt_packets()` counter.
+#[derive(Clone, Default)]
+pub struct Metrics {
+    state: Arc<Mutex<MetricsState>>,
+    channels: ChannelMap,
+    corrupt_packets: Arc<AtomicU64>,
+}
+
+impl Metrics {
+    pub fn new() -> Metrics {
+        Metrics::default()
+    }
+
+    /// Resolves connection channels and battery listeners to buttons.
+    pub fn channels(mut self, channels: ChannelMap) -> Self {
+        self.channels = channels;
+        self
+    }
+
+    /// Reports the client's count of corrupt packets.
+    pub fn corrupt_packets(mut self, corrupt_packets: Arc<AtomicU64>) -> Self {
+        self.corrupt_packets = corrupt_packets;
+        self
+    }
+
+    pub fn handler(&self) -> EventClosureMutex {
+        let metrics = self.clone();
+        event_handler(move |event| {
+            let bd_addr = metrics.channels.bd_addr_of(event);
+            metrics.state.lock().unwrap().event(bd_addr, event);
+        })
+    }
+
+    /// Notes when pings are sent.
+    pub fn packet_handler(&self) -> PacketClosureMutex {
+        let state = self.state.clone();
+        packet_handler(move |dir, bytes| {
+            if dir != PacketDirection::Outbound || bytes.len() < 2 {
+                return;
+            }
+            if let Some(Command::Ping { ping_id }) = Command::from_packet(&bytes[2..]) {
+                state.lock().unwrap().pings.insert(ping_id, Instant::now());
+            }
+        })
+    }
+
+    /// The metrics in the Prometheus text format.
+    pub fn render(&self) -> String {
+        let corrupt_packets = self.corrupt_packets.load(Ordering::Relaxed);
+        self.state.lock().unwrap().render(corrupt_packets)
+    }
+
+    pub fn respond(&self, request: Request<Body>) -> Response<Body> {
+        let mut response = Response::new(Body::empty());
+        if request.method() != Method::GET || request.uri().path() != "/metrics" {
+            *response.status_mut() = StatusCode::NOT_FOUND;
+            return response;
+        }
+        *response.body_mut() = Body::from(self.render());
+        response
+            .headers_mut()
+            .insert("content-type", "text/plain; version=0.0.4".parse().unwrap());
+        response
+    }
+
+    /// Serves `GET /metrics` on `addr`.
+    pub async fn serve(&self, addr: SocketAddr) -> hyper::Result<()> {
+        let metrics = self.clone();
+        let service = make_service_fn(move |_| {
+            let metrics = metrics.clone();
+            async move {
+                Ok::<_, Infallible>(service_fn(move |request| {
+                    let response = metrics.respond(request);
+                    async move { Ok::<_, Infallible>(response) }
+                }))
+            }
+        });
+        Server::bind(&addr).serve(service).await
+    }
+}
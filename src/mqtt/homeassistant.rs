@@ -0,0 +1,179 @@
+use serde_json::{json, Value};
+
+use super::Publication;
+use crate::enums::ClickType;
+use crate::events::{ButtonModel, GetButtonInfoResponse};
+
+const CLICK_TYPES: [ClickType; 6] = [
+    ClickType::ButtonDown,
+    ClickType::ButtonUp,
+    ClickType::ButtonClick,
+    ClickType::ButtonSingleClick,
+    ClickType::ButtonDoubleClick,
+    ClickType::ButtonHold,
+];
+
+/// Payload published to `<prefix>/<bd_addr>/action` for a gesture.
+pub fn action_name(click_type: ClickType) -> &'static str {
+    match click_type {
+        ClickType::ButtonDown => "down",
+        ClickType::ButtonUp => "up",
+        ClickType::ButtonClick => "click",
+        ClickType::ButtonSingleClick => "single",
+        ClickType::ButtonDoubleClick => "double",
+        ClickType::ButtonHold => "hold",
+        ClickType::Other(_) => "other",
+    }
+}
+
+fn trigger_type(click_type: ClickType) -> &'static str {
+    match click_type {
+        ClickType::ButtonDown => "button_down",
+        ClickType::ButtonUp => "button_up",
+        ClickType::ButtonClick => "button_click",
+        ClickType::ButtonSingleClick => "button_short_press",
+        ClickType::ButtonDoubleClick => "button_double_press",
+        ClickType::ButtonHold => "button_long_press",
+        ClickType::Other(_) => "other",
+    }
+}
+
+fn node_id(bd_addr: &str) -> String {
+    format!("flic_{}", bd_addr.replace(':', ""))
+}
+
+fn config_topics(discovery_prefix: &str, bd_addr: &str) -> Vec<String> {
+    let node_id = node_id(bd_addr);
+    let mut topics: Vec<String> = CLICK_TYPES
+        .iter()
+        .map(|click_type| {
+            format!(
+                "{}/device_automation/{}/{}/config",
+                discovery_prefix,
+                node_id,
+                action_name(*click_type)
+            )
+        })
+        .collect();
+    topics.push(format!(
+        "{}/sensor/{}/battery/config",
+        discovery_prefix, node_id
+    ));
+    topics.push(format!(
+        "{}/binary_sensor/{}/connectivity/config",
+        discovery_prefix, node_id
+    ));
+    topics
+}
+
+/// Discovery configuration of a button for Home Assistant.
+///
+/// Announces a device trigger per click type, a battery sensor and a
+/// connectivity binary sensor, all tied to one device carrying the serial
+/// number, model, color and firmware version of the button.
+pub fn discovery(
+    discovery_prefix: &str,
+    state_prefix: &str,
+    info: &GetButtonInfoResponse,
+) -> Vec<Publication> {
+    let bd_addr = &info.bd_addr[..];
+    let serial_number = info.serial_number.as_deref();
+    let node_id = node_id(bd_addr);
+    let name = match serial_number {
+        Some(serial_number) => format!("Flic {}", serial_number),
+        None => format!("Flic {}", bd_addr),
+    };
+    let mut device = json!({
+        "identifiers": [node_id],
+        "connections": [["bluetooth", bd_addr]],
+        "name": name,
+        "manufacturer": "Shortcut Labs",
+    });
+    let model = match info.model() {
+        Some(ButtonModel::Flic1) => "Flic 1",
+        Some(ButtonModel::Flic2) => "Flic 2",
+        None => "Flic",
+    };
+    device["model"] = match &info.color {
+        Some(color) => json!(format!("{} ({})", model, color)),
+        None => json!(model),
+    };
+    if let Some(firmware_version) = info.firmware_version {
+        device["sw_version"] = json!(firmware_version.to_string());
+    }
+    if let Some(serial_number) = serial_number {
+        device["serial_number"] = json!(serial_number);
+    }
+    let availability = json!([{
+        "topic": format!("{}/server/status", state_prefix),
+        "payload_available": "online",
+        "payload_not_available": "offline",
+    }]);
+    let mut battery_availability = availability.clone();
+    battery_availability.as_array_mut().unwrap().push(json!({
+        "topic": format!("{}/{}/battery", state_prefix, bd_addr),
+        "value_template":
+            "{{ 'online' if value_json.battery_percentage >= 0 else 'offline' }}",
+        "payload_available": "online",
+        "payload_not_available": "offline",
+    }));
+
+    let topics = config_topics(discovery_prefix, bd_addr);
+    let mut configs: Vec<Value> = CLICK_TYPES
+        .iter()
+        .map(|click_type| {
+            json!({
+                "automation_type": "trigger",
+                "topic": format!("{}/{}/action", state_prefix, bd_addr),
+                "payload": action_name(*click_type),
+                "type": trigger_type(*click_type),
+                "subtype": "button_1",
+                "device": device,
+            })
+        })
+        .collect();
+    configs.push(json!({
+        "name": format!("{} Battery", name),
+        "unique_id": format!("{}_battery", node_id),
+        "device_class": "battery",
+        "state_class": "measurement",
+        "unit_of_measurement": "%",
+        "state_topic": format!("{}/{}/battery", state_prefix, bd_addr),
+        "value_template": "{{ value_json.battery_percentage }}",
+        // flicd reports -1 while the level is unknown
+        "availability": battery_availability,
+        "availability_mode": "all",
+        "device": device,
+    }));
+    configs.push(json!({
+        "name": format!("{} Connectivity", name),
+        "unique_id": format!("{}_connectivity", node_id),
+        "device_class": "connectivity",
+        "state_topic": format!("{}/{}/connection", state_prefix, bd_addr),
+        "value_template": "{{ 'OFF' if value_json.connection_status == 'Disconnected' else 'ON' }}",
+        "availability": availability,
+        "device": device,
+    }));
+
+    topics
+        .into_iter()
+        .zip(configs)
+        .map(|(topic, config)| Publication {
+            topic,
+            payload: config.to_string().into_bytes(),
+            retain: true,
+        })
+        .collect()
+}
+
+/// Removes a button from Home Assistant by clearing its discovery topics.
+pub fn removal(discovery_prefix: &str, bd_addr: &str) -> Vec<Publication> {
+    config_topics(discovery_prefix, bd_addr)
+        .into_iter()
+        .map(|topic| Publication {
+            topic,
+            payload: vec![],
+            retain: true,
+        })
+        .collect()
+}
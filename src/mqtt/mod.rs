@@ -0,0 +1,442 @@
+pub mod homeassistant;
+
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use rumqttc::{AsyncClient, Event as MqttEvent, LastWill, MqttOptions, Packet, QoS};
+use serde_json::{json, Value};
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender};
+use tracing::warn;
+
+use super::client::{event_handler, EventClosureMutex, FlicClient};
+use super::commands::{is_bd_addr, Command};
+use super::enums::*;
+use super::events::*;
+use super::json::{event_name, event_to_json};
+
+/// A message to be published to the broker.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Publication {
+    pub topic: String,
+    pub payload: Vec<u8>,
+    pub retain: bool,
+}
+
+impl Publication {
+    fn json(topic: String, value: &Value, retain: bool) -> Publication {
+        Publication {
+            topic,
+            payload: value.to_string().into_bytes(),
+            retain,
+        }
+    }
+    fn clear(topic: String) -> Publication {
+        Publication {
+            topic,
+            payload: vec![],
+            retain: true,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct BridgeButton {
+    bd_addr: String,
+    conn_id: u32,
+    listener_id: u32,
+}
+
+struct BridgeState {
+    prefix: String,
+    connect_all: bool,
+    discovery_prefix: Option<String>,
+    buttons: Vec<BridgeButton>,
+    next_id: u32,
+    scan_wizard_id: u32,
+}
+
+impl BridgeState {
+    fn topic(&self, bd_addr: &str, kind: &str) -> String {
+        format!("{}/{}/{}", self.prefix, bd_addr, kind)
+    }
+    fn server_topic(&self, kind: &str) -> String {
+        format!("{}/server/{}", self.prefix, kind)
+    }
+    fn by_conn_id(&self, conn_id: u32) -> Option<&BridgeButton> {
+        self.buttons.iter().find(|b| b.conn_id == conn_id)
+    }
+
+    fn connect(&mut self, bd_addr: &str) -> Vec<Command> {
+        let bd_addr = bd_addr.to_lowercase();
+        if self.buttons.iter().any(|b| b.bd_addr == bd_addr) {
+            return vec![];
+        }
+        self.next_id += 1;
+        let button = BridgeButton {
+            bd_addr,
+            conn_id: self.next_id,
+            listener_id: self.next_id,
+        };
+        let commands = vec![
+            Command::CreateConnectionChannel {
+                conn_id: button.conn_id,
+                bd_addr: button.bd_addr.clone(),
+                latency_mode: LatencyMode::NormalLatency,
+                auto_disconnect_time: 511,
+            },
+            Command::CreateBatteryStatusListener {
+                listener_id: button.listener_id,
+                bd_addr: button.bd_addr.clone(),
+            },
+            Command::GetButtonInfo {
+                bd_addr: button.bd_addr.clone(),
+            },
+        ];
+        self.buttons.push(button);
+        commands
+    }
+
+    fn disconnect(&mut self, bd_addr: &str) -> Vec<Command> {
+        let bd_addr = bd_addr.to_lowercase();
+        let mut commands = vec![];
+        for button in self.buttons.iter().filter(|b| b.bd_addr == bd_addr) {
+            commands.push(Command::RemoveBatteryStatusListener {
+                listener_id: button.listener_id,
+            });
+            commands.push(Command::RemoveConnectionChannel {
+                conn_id: button.conn_id,
+            });
+        }
+        self.buttons.retain(|b| b.bd_addr != bd_addr);
+        commands
+    }
+
+    fn handle_event(&mut self, event: &Event) -> (Vec<Publication>, Vec<Command>) {
+        let mut value = event_to_json(event);
+        let mut commands = vec![];
+        let publications = match event {
+            Event::NoOp | Event::CorruptEvent => vec![],
+            Event::Button(ButtonEvent { conn_id, .. }) => match self.by_conn_id(*conn_id) {
+                Some(button) => {
+                    value["bd_addr"] = json!(button.bd_addr);
+                    let mut publications = vec![Publication::json(
+                        self.topic(&button.bd_addr, "click"),
+                        &value,
+                        false,
+                    )];
+                    if let Some(gesture) = event.gesture() {
+                        publications.push(Publication {
+                            topic: self.topic(&button.bd_addr, "action"),
+                            payload: homeassistant::action_name(gesture.click_type).into(),
+                            retain: false,
+                        });
+                    }
+                    publications
+                }
+                None => vec![],
+            },
+            Event::CreateConnectionChannelResponse(CreateConnectionChannelResponse {
+                conn_id,
+                ..
+            })
+            | Event::ConnectionStatusChanged(ConnectionStatusChanged { conn_id, .. }) => {
+                match self.by_conn_id(*conn_id) {
+                    Some(button) => {
+                        value["bd_addr"] = json!(button.bd_addr);
+                        vec![Publication::json(
+                            self.topic(&button.bd_addr, "connection"),
+                            &value,
+                            true,
+                        )]
+                    }
+                    None => vec![],
+                }
+            }
+            Event::ConnectionChannelRemoved(ConnectionChannelRemoved { conn_id, .. }) => {
+                match self.by_conn_id(*conn_id) {
+                    Some(button) => {
+                        let bd_addr = button.bd_addr.clone();
+                        value["bd_addr"] = json!(bd_addr);
+                        value["connection_status"] =
+                            json!(format!("{:?}", ConnectionStatus::Disconnected));
+                        let topic = self.topic(&bd_addr, "connection");
+                        self.buttons.retain(|b| b.bd_addr != bd_addr);
+                        vec![Publication::json(topic, &value, true)]
+                    }
+                    None => vec![],
+                }
+            }
+            Event::BatteryStatus(BatteryStatus { listener_id, .. }) => {
+                match self.buttons.iter().find(|b| b.listener_id == *listener_id) {
+                    Some(button) => {
+                        value["bd_addr"] = json!(button.bd_addr);
+                        vec![Publication::json(
+                            self.topic(&button.bd_addr, "battery"),
+                            &value,
+                            true,
+                        )]
+                    }
+                    None => vec![],
+                }
+            }
+            Event::GetButtonInfoResponse(info) => {
+                let mut publications = vec![Publication::json(
+                    self.topic(&info.bd_addr, "info"),
+                    &value,
+                    true,
+                )];
+                if let Some(ref discovery_prefix) = self.discovery_prefix {
+                    publications.extend(homeassistant::discovery(
+                        discovery_prefix,
+                        &self.prefix,
+                        info,
+                    ));
+                }
+                publications
+            }
+            Event::NewVerifiedButton(NewVerifiedButton { bd_addr }) => {
+                if self.connect_all {
+                    commands.extend(self.connect(bd_addr));
+                }
+                vec![Publication::json(
+                    self.topic(bd_addr, "verified"),
+                    &value,
+                    false,
+                )]
+            }
+            Event::ButtonDeleted(ButtonDeleted { bd_addr, .. }) => {
+                self.buttons.retain(|b| b.bd_addr != *bd_addr);
+                let mut publications = vec![
+                    Publication::json(self.topic(bd_addr, "deleted"), &value, false),
+                    Publication::clear(self.topic(bd_addr, "connection")),
+                    Publication::clear(self.topic(bd_addr, "battery")),
+                    Publication::clear(self.topic(bd_addr, "info")),
+                ];
+                if let Some(ref discovery_prefix) = self.discovery_prefix {
+                    publications.extend(homeassistant::removal(discovery_prefix, bd_addr));
+                }
+                publications
+            }
+            Event::AdvertisementPacket(AdvertisementPacket { bd_addr, .. }) => {
+                vec![Publication::json(
+                    self.topic(bd_addr, "advertisement"),
+                    &value,
+                    false,
+                )]
+            }
+            Event::GetInfoResponse(GetInfoResponse {
+                bd_addr_of_verified_buttons,
+                ..
+            }) => {
+                if self.connect_all {
+                    for bd_addr in bd_addr_of_verified_buttons {
+                        commands.extend(self.connect(bd_addr));
+                    }
+                }
+                vec![Publication::json(self.server_topic("info"), &value, true)]
+            }
+            Event::BluetoothControllerStateChange(_) => vec![Publication::json(
+                self.server_topic("bluetooth"),
+                &value,
+                true,
+            )],
+            _ => vec![Publication::json(
+                self.server_topic(event_name(event)),
+                &value,
+                false,
+            )],
+        };
+        (publications, commands)
+    }
+
+    fn handle_message(&mut self, topic: &str, payload: &[u8]) -> Vec<Command> {
+        let prefix = format!("{}/cmd/", self.prefix);
+        let cmd = match topic.strip_prefix(&prefix) {
+            Some(cmd) => cmd,
+            None => return vec![],
+        };
+        let payload = String::from_utf8_lossy(payload).trim().to_string();
+        let arg = match serde_json::from_str::<Value>(&payload) {
+            Ok(Value::Object(map)) => map
+                .get("bd_addr")
+                .or_else(|| map.get("action"))
+                .and_then(|v| v.as_str())
+                .unwrap_or_default()
+                .to_string(),
+            _ => payload,
+        };
+        match (cmd, &arg[..]) {
+            ("info", _) => vec![Command::GetInfo],
+            ("ping", _) => vec![Command::Ping { ping_id: 0 }],
+            ("scan", "stop") => vec![Command::RemoveScanner { scan_id: 0 }],
+            ("scan", _) => vec![Command::CreateScanner { scan_id: 0 }],
+            ("scan-wizard", "cancel") => vec![Command::CancelScanWizard {
+                scan_wizard_id: self.scan_wizard_id,
+            }],
+            ("scan-wizard", _) => {
+                self.scan_wizard_id += 1;
+                vec![Command::CreateScanWizard {
+                    scan_wizard_id: self.scan_wizard_id,
+                }]
+            }
+            // the remaining commands take a button address
+            (_, bd_addr) if !is_bd_addr(bd_addr) => vec![],
+            ("connect", bd_addr) => self.connect(bd_addr),
+            ("disconnect", bd_addr) => self.disconnect(bd_addr),
+            ("button-info", bd_addr) => vec![Command::GetButtonInfo {
+                bd_addr: bd_addr.to_lowercase(),
+            }],
+            ("delete", bd_addr) => vec![Command::DeleteButton {
+                bd_addr: bd_addr.to_lowercase(),
+            }],
+            _ => vec![],
+        }
+    }
+}
+
+type BridgeReceivers = (UnboundedReceiver<Publication>, UnboundedReceiver<Command>);
+
+/// Bridges a flicd connection to an MQTT broker.
+///
+/// Every event is published as JSON below `<prefix>/<bd_addr>/` for button
+/// related events (`click`, `connection`, `battery`, `info`, ...) and below
+/// `<prefix>/server/` otherwise. Each gesture is additionally published once
+/// as plain text (`single`, `double`, `hold`, ...) to
+/// `<prefix>/<bd_addr>/action`. Connection, battery and server state are
+/// retained. Messages to `<prefix>/cmd/<command>` are translated into
+/// commands: `info`, `ping`, `scan` (`start`/`stop`), `scan-wizard`
+/// (`start`/`cancel`), `connect`, `disconnect`, `button-info` and `delete`,
+/// the latter taking the button address as payload. Payloads that are no
+/// button address are ignored.
+#[derive(Clone)]
+pub struct MqttBridge {
+    state: Arc<Mutex<BridgeState>>,
+    publications: UnboundedSender<Publication>,
+    commands: UnboundedSender<Command>,
+    receivers: Arc<Mutex<Option<BridgeReceivers>>>,
+}
+
+impl MqttBridge {
+    pub fn new(prefix: &str) -> MqttBridge {
+        let (publications, publication_rx) = unbounded_channel();
+        let (commands, command_rx) = unbounded_channel();
+        MqttBridge {
+            state: Arc::new(Mutex::new(BridgeState {
+                prefix: prefix.trim_end_matches('/').to_string(),
+                connect_all: true,
+                discovery_prefix: None,
+                buttons: vec![],
+                next_id: 0,
+                scan_wizard_id: 0,
+            })),
+            publications,
+            commands,
+            receivers: Arc::new(Mutex::new(Some((publication_rx, command_rx)))),
+        }
+    }
+
+    /// Whether all verified buttons are connected automatically.
+    pub fn connect_all(self, connect_all: bool) -> Self {
+        self.state.lock().unwrap().connect_all = connect_all;
+        self
+    }
+
+    /// Announces every connected button to Home Assistant using MQTT
+    /// discovery below `discovery_prefix`, usually `homeassistant`.
+    pub fn home_assistant(self, discovery_prefix: &str) -> Self {
+        self.state.lock().unwrap().discovery_prefix =
+            Some(discovery_prefix.trim_end_matches('/').to_string());
+        self
+    }
+
+    pub fn prefix(&self) -> String {
+        self.state.lock().unwrap().prefix.clone()
+    }
+
+    /// Returns what to publish for an event and the commands it requires.
+    pub fn handle_event(&self, event: &Event) -> (Vec<Publication>, Vec<Command>) {
+        self.state.lock().unwrap().handle_event(event)
+    }
+
+    /// Translates a message received on the command topics.
+    pub fn handle_message(&self, topic: &str, payload: &[u8]) -> Vec<Command> {
+        self.state.lock().unwrap().handle_message(topic, payload)
+    }
+
+    pub fn handler(&self) -> EventClosureMutex {
+        let bridge = self.clone();
+        event_handler(move |event| {
+            let (publications, commands) = bridge.handle_event(event);
+            for publication in publications {
+                let _ = bridge.publications.send(publication);
+            }
+            for command in commands {
+                let _ = bridge.commands.send(command);
+            }
+        })
+    }
+
+    /// Connects to the broker and forwards events and commands until the
+    /// client is stopped. Lost broker connections are retried.
+    ///
+    /// Can only be run once per bridge.
+    pub async fn run(&self, client: Arc<FlicClient>, mut options: MqttOptions) {
+        let (mut publication_rx, mut command_rx) = match self.receivers.lock().unwrap().take() {
+            Some(receivers) => receivers,
+            None => return,
+        };
+        let prefix = self.prefix();
+        let status_topic = format!("{}/server/status", prefix);
+        let mut will = LastWill::new(&status_topic, QoS::AtLeastOnce, "offline");
+        will.retain = true;
+        options.set_last_will(will);
+
+        let (mqtt, mut eventloop) = AsyncClient::new(options, 64);
+
+        let publisher = mqtt.clone();
+        tokio::spawn(async move {
+            while let Some(publication) = publication_rx.recv().await {
+                let _ = publisher
+                    .publish(
+                        publication.topic,
+                        QoS::AtLeastOnce,
+                        publication.retain,
+                        publication.payload,
+                    )
+                    .await;
+            }
+        });
+        let submitter = client.clone();
+        tokio::spawn(async move {
+            while let Some(command) = command_rx.recv().await {
+                submitter.submit(command).await;
+            }
+        });
+        let _ = self.commands.send(Command::GetInfo);
+
+        while client.is_running().await {
+            match eventloop.poll().await {
+                Ok(MqttEvent::Incoming(Packet::ConnAck(_))) => {
+                    let _ = mqtt
+                        .subscribe(format!("{}/cmd/#", prefix), QoS::AtLeastOnce)
+                        .await;
+                    let _ = self.publications.send(Publication {
+                        topic: status_topic.clone(),
+                        payload: b"online".to_vec(),
+                        retain: true,
+                    });
+                }
+                Ok(MqttEvent::Incoming(Packet::Publish(publish))) => {
+                    for command in self.handle_message(&publish.topic, &publish.payload) {
+                        let _ = self.commands.send(command);
+                    }
+                }
+                Ok(_) => {}
+                Err(err) => {
+                    warn!(error = ?err, "mqtt connection failed");
+                    tokio::time::delay_for(Duration::from_secs(1)).await;
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,250 @@
+use std::collections::BTreeSet;
+use std::sync::{Arc, Mutex};
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender};
+
+use super::client::*;
+use super::commands::Command;
+use super::enums::*;
+use super::events::*;
+
+/// Start or end of a Bluetooth controller outage, as passed to the outage
+/// handlers.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(tag = "outage", rename_all = "snake_case"))]
+pub enum Outage {
+    /// The controller is detached or resetting. The listed scanners and scan
+    /// wizards were paused.
+    Started {
+        state: BluetoothControllerState,
+        scanners: Vec<u32>,
+        scan_wizards: Vec<u32>,
+    },
+    /// The controller is attached again. The listed scanners and scan
+    /// wizards were created again with their old ids.
+    Ended {
+        scanners: Vec<u32>,
+        scan_wizards: Vec<u32>,
+    },
+}
+
+pub type OutageClosure = dyn FnMut(&Outage) + Sync + Send + 'static;
+pub type OutageClosureMutex = Box<OutageClosure>;
+
+pub fn outage_handler<F>(f: F) -> OutageClosureMutex
+where
+    F: FnMut(&Outage) + Sync + Send + 'static,
+{
+    Box::new(f)
+}
+
+#[derive(Default)]
+struct OutageState {
+    outage: Option<BluetoothControllerState>,
+    scanners: BTreeSet<u32>,
+    scan_wizards: BTreeSet<u32>,
+    paused_scanners: BTreeSet<u32>,
+    paused_scan_wizards: BTreeSet<u32>,
+    /// Removals sent by the guard itself, which keep their ids paused.
+    own: Vec<Command>,
+}
+
+impl OutageState {
+    fn command(&mut self, command: Command) {
+        if let Some(pos) = self.own.iter().position(|c| *c == command) {
+            self.own.remove(pos);
+            return;
+        }
+        match command {
+            Command::CreateScanner { scan_id } => {
+                self.scanners.insert(scan_id);
+            }
+            Command::RemoveScanner { scan_id } => {
+                self.scanners.remove(&scan_id);
+                self.paused_scanners.remove(&scan_id);
+            }
+            Command::CreateScanWizard { scan_wizard_id } => {
+                self.scan_wizards.insert(scan_wizard_id);
+            }
+            Command::CancelScanWizard { scan_wizard_id } => {
+                self.scan_wizards.remove(&scan_wizard_id);
+                self.paused_scan_wizards.remove(&scan_wizard_id);
+            }
+            _ => {}
+        }
+    }
+
+    fn controller(&mut self, state: BluetoothControllerState) -> (Option<Outage>, Vec<Command>) {
+        match (self.outage, state) {
+            (None, BluetoothControllerState::Attached) => (None, vec![]),
+            (Some(_), BluetoothControllerState::Attached) => {
+                self.outage = None;
+                let scanners = std::mem::take(&mut self.paused_scanners);
+                let scan_wizards = std::mem::take(&mut self.paused_scan_wizards);
+                let commands =
+                    scanners
+                        .iter()
+                        .map(|scan_id| Command::CreateScanner { scan_id: *scan_id })
+                        .chain(scan_wizards.iter().map(|scan_wizard_id| {
+                            Command::CreateScanWizard {
+                                scan_wizard_id: *scan_wizard_id,
+                            }
+                        }))
+                        .collect();
+                let outage = Outage::Ended {
+                    scanners: scanners.into_iter().collect(),
+                    scan_wizards: scan_wizards.into_iter().collect(),
+                };
+                (Some(outage), commands)
+            }
+            (Some(_), state) => {
+                self.outage = Some(state);
+                (None, vec![])
+            }
+            (None, state) => {
+                self.outage = Some(state);
+                let scanners = std::mem::take(&mut self.scanners);
+                let scan_wizards = std::mem::take(&mut self.scan_wizards);
+                let commands: Vec<Command> =
+                    scanners
+                        .iter()
+                        .map(|scan_id| Command::RemoveScanner { scan_id: *scan_id })
+                        .chain(scan_wizards.iter().map(|scan_wizard_id| {
+                            Command::CancelScanWizard {
+                                scan_wizard_id: *scan_wizard_id,
+                            }
+                        }))
+                        .collect();
+                self.own.extend(commands.iter().cloned());
+                self.paused_scanners.extend(scanners.iter());
+                self.paused_scan_wizards.extend(scan_wizards.iter());
+                let outage = Outage::Started {
+                    state,
+                    scanners: scanners.into_iter().collect(),
+                    scan_wizards: scan_wizards.into_iter().collect(),
+                };
+                (Some(outage), commands)
+            }
+        }
+    }
+
+    fn event(&mut self, event: &Event) -> (Option<Outage>, Vec<Command>) {
+        match event {
+            Event::BluetoothControllerStateChange(BluetoothControllerStateChange { state })
+            | Event::GetInfoResponse(GetInfoResponse {
+                bluetooth_controller_state: state,
+                ..
+            }) => self.controller(*state),
+            Event::ScanWizardCompleted(ScanWizardCompleted { scan_wizard_id, .. }) => {
+                // a wizard failing for the outage stays paused
+                self.scan_wizards.remove(scan_wizard_id);
+                (None, vec![])
+            }
+            _ => (None, vec![]),
+        }
+    }
+}
+
+/// Pauses scanners and scan wizards while the Bluetooth controller of flicd
+/// is detached or resetting, and creates them again once it is attached.
+///
+/// Register `handler()` for the events and `packet_handler()` so the guard
+/// sees which scanners and wizards the client creates, then `run` it to
+/// submit its commands. Connection channels are kept by flicd and need no
+/// help; `ServerState` marks their buttons disconnected during an outage.
+#[derive(Clone)]
+pub struct OutageGuard {
+    state: Arc<Mutex<OutageState>>,
+    handlers: Arc<Mutex<Vec<OutageClosureMutex>>>,
+    commands: UnboundedSender<Command>,
+    receiver: Arc<Mutex<Option<UnboundedReceiver<Command>>>>,
+}
+
+impl Default for OutageGuard {
+    fn default() -> Self {
+        OutageGuard::new()
+    }
+}
+
+impl OutageGuard {
+    pub fn new() -> OutageGuard {
+        let (commands, receiver) = unbounded_channel();
+        OutageGuard {
+            state: Arc::new(Mutex::new(OutageState::default())),
+            handlers: Arc::new(Mutex::new(vec![])),
+            commands,
+            receiver: Arc::new(Mutex::new(Some(receiver))),
+        }
+    }
+
+    pub fn register_outage_handler(self, handler: OutageClosureMutex) -> Self {
+        self.handlers.lock().unwrap().push(handler);
+        self
+    }
+
+    /// The state of the controller while it is out, `None` when attached.
+    pub fn outage(&self) -> Option<BluetoothControllerState> {
+        self.state.lock().unwrap().outage
+    }
+
+    /// Returns the commands an event requires and notifies the outage
+    /// handlers.
+    pub fn handle_event(&self, event: &Event) -> Vec<Command> {
+        let (outage, commands) = self.state.lock().unwrap().event(event);
+        if let Some(outage) = outage {
+            for handler in self.handlers.lock().unwrap().iter_mut() {
+                handler(&outage);
+            }
+        }
+        commands
+    }
+
+    /// Notes the commands sent to flicd.
+    pub fn handle_command(&self, command: Command) {
+        self.state.lock().unwrap().command(command);
+    }
+
+    pub fn handler(&self) -> EventClosureMutex {
+        let guard = self.clone();
+        event_handler(move |event| {
+            for command in guard.handle_event(event) {
+                let _ = guard.commands.send(command);
+            }
+        })
+    }
+
+    pub fn packet_handler(&self) -> PacketClosureMutex {
+        let guard = self.clone();
+        packet_handler(move |dir, bytes| {
+            if dir != PacketDirection::Outbound || bytes.len() < 2 {
+                return;
+            }
+            if let Some(command) = Command::from_packet(&bytes[2..]) {
+                guard.handle_command(command);
+            }
+        })
+    }
+
+    /// Submits the commands of the guard until the client is stopped.
+    ///
+    /// Can only be run once per guard.
+    pub async fn run(&self, client: Arc<FlicClient>) {
+        let mut receiver = match self.receiver.lock().unwrap().take() {
+            Some(receiver) => receiver,
+            None => return,
+        };
+        loop {
+            let command = tokio::select! {
+                command = receiver.recv() => command,
+                _ = client.stopped() => None,
+            };
+            match command {
+                Some(command) => client.submit(command).await,
+                None => break,
+            }
+        }
+    }
+}
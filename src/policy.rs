@@ -0,0 +1,167 @@
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+
+use super::client::{event_handler, EventClosureMutex};
+use super::enums::ClickType;
+use super::events::*;
+
+/// What to do with a queued click that is older than the policy threshold.
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum QueuedClickAction {
+    Deliver,
+    Flag,
+    Drop,
+    Delay(Duration),
+}
+
+/// A button event with its `time_diff` resolved to an absolute point in time.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Click {
+    pub event: Event,
+    pub conn_id: u32,
+    pub click_type: ClickType,
+    pub was_queued: bool,
+    pub stale: bool,
+    pub occurred_at: SystemTime,
+}
+
+impl Click {
+    pub fn from_event(event: &Event, received_at: SystemTime) -> Option<Click> {
+        match *event {
+            Event::Button(ButtonEvent {
+                conn_id,
+                click_type,
+                was_queued,
+                time_diff,
+                ..
+            }) => {
+                let age = if was_queued {
+                    Duration::from_secs(time_diff.max(0) as u64)
+                } else {
+                    Duration::from_secs(0)
+                };
+                Some(Click {
+                    event: event.clone(),
+                    conn_id,
+                    click_type,
+                    was_queued,
+                    stale: false,
+                    occurred_at: received_at.checked_sub(age).unwrap_or(received_at),
+                })
+            }
+            _ => None,
+        }
+    }
+
+    pub fn age(&self) -> Duration {
+        SystemTime::now()
+            .duration_since(self.occurred_at)
+            .unwrap_or_default()
+    }
+
+    fn same_slot(&self, other: &Click) -> bool {
+        self.conn_id == other.conn_id
+            && self.click_type == other.click_type
+            && self.event.button().map(|b| b.kind) == other.event.button().map(|b| b.kind)
+    }
+}
+
+/// Decides how queued clicks are delivered to a click handler.
+///
+/// Clicks that were queued for at least `max_age` are considered stale and
+/// are handled according to the configured `QueuedClickAction`. With
+/// coalescing enabled, delayed clicks are collapsed to the most recent one
+/// per connection, event kind and click type, so a burst replayed after a
+/// reconnect triggers the handler at most once per gesture of a button.
+#[derive(Debug, Clone)]
+pub struct ClickPolicy {
+    max_age: Duration,
+    action: QueuedClickAction,
+    coalesce: bool,
+}
+
+impl ClickPolicy {
+    pub fn new(max_age: Duration, action: QueuedClickAction) -> ClickPolicy {
+        ClickPolicy {
+            max_age,
+            action,
+            coalesce: false,
+        }
+    }
+    pub fn coalesce(mut self, coalesce: bool) -> Self {
+        self.coalesce = coalesce;
+        self
+    }
+
+    pub fn is_stale(&self, click: &Click, received_at: SystemTime) -> bool {
+        click.was_queued
+            && received_at
+                .duration_since(click.occurred_at)
+                .map(|age| age >= self.max_age)
+                .unwrap_or(false)
+    }
+
+    /// Wraps a click handler into an event handler applying this policy.
+    ///
+    /// Only gestures are handled, so a click reported in several button
+    /// events is handled once. Other events are ignored.
+    pub fn handler<F>(self, f: F) -> EventClosureMutex
+    where
+        F: FnMut(&Click) + Sync + Send + 'static,
+    {
+        let state = Arc::new(Mutex::new(PolicyState {
+            f,
+            pending: vec![],
+            flush_scheduled: false,
+        }));
+        event_handler(move |event| {
+            if event.gesture().is_none() {
+                return;
+            }
+            let received_at = SystemTime::now();
+            let mut click = match Click::from_event(event, received_at) {
+                Some(click) => click,
+                None => return,
+            };
+            let mut guard = state.lock().unwrap();
+            if !self.is_stale(&click, received_at) {
+                (guard.f)(&click);
+                return;
+            }
+            match self.action {
+                QueuedClickAction::Deliver => (guard.f)(&click),
+                QueuedClickAction::Flag => {
+                    click.stale = true;
+                    (guard.f)(&click);
+                }
+                QueuedClickAction::Drop => {}
+                QueuedClickAction::Delay(delay) => {
+                    click.stale = true;
+                    if self.coalesce {
+                        guard.pending.retain(|pending| !pending.same_slot(&click));
+                    }
+                    guard.pending.push(click);
+                    if !guard.flush_scheduled {
+                        guard.flush_scheduled = true;
+                        let state = state.clone();
+                        tokio::spawn(async move {
+                            tokio::time::delay_for(delay).await;
+                            let mut guard = state.lock().unwrap();
+                            guard.flush_scheduled = false;
+                            let pending: Vec<Click> = guard.pending.drain(..).collect();
+                            for click in pending.iter() {
+                                (guard.f)(click);
+                            }
+                        });
+                    }
+                }
+            }
+        })
+    }
+}
+
+struct PolicyState<F> {
+    f: F,
+    pending: Vec<Click>,
+    flush_scheduled: bool,
+}
@@ -0,0 +1,202 @@
+use std::fmt;
+use std::str::FromStr;
+use std::time::Duration;
+
+use super::commands::Command;
+use super::enums::LatencyMode;
+
+/// `auto_disconnect_time` value flicd reads as "never disconnect".
+const NEVER: i16 = 511;
+
+// Errors
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ProfileError {
+    /// flicd disconnects after at most 510 seconds.
+    InvalidAutoDisconnect(Duration),
+    InvalidAutoDisconnectTime(i16),
+    UnknownProfile(String),
+}
+
+impl fmt::Display for ProfileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidAutoDisconnect(after) => write!(
+                f,
+                "auto disconnect after {}s, must be at most {}s",
+                after.as_secs(),
+                NEVER - 1
+            ),
+            Self::InvalidAutoDisconnectTime(secs) => write!(
+                f,
+                "auto_disconnect_time must be between 0 and {}: {}",
+                NEVER, secs
+            ),
+            Self::UnknownProfile(name) => write!(f, "unknown connection profile: {}", name),
+        }
+    }
+}
+
+impl std::error::Error for ProfileError {}
+
+/// When flicd disconnects a button that has not been pressed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum AutoDisconnect {
+    Never,
+    /// Whole seconds of idle time, at most 510.
+    After(Duration),
+}
+
+impl AutoDisconnect {
+    /// Decodes the `auto_disconnect_time` of a command, 511 meaning never.
+    pub fn from_wire(secs: i16) -> Result<AutoDisconnect, ProfileError> {
+        match secs {
+            NEVER => Ok(AutoDisconnect::Never),
+            0..=510 => Ok(AutoDisconnect::After(Duration::from_secs(secs as u64))),
+            _ => Err(ProfileError::InvalidAutoDisconnectTime(secs)),
+        }
+    }
+
+    /// The `auto_disconnect_time` of a command.
+    pub fn to_wire(self) -> Result<i16, ProfileError> {
+        match self {
+            AutoDisconnect::Never => Ok(NEVER),
+            AutoDisconnect::After(after) if after.as_secs() < NEVER as u64 => {
+                Ok(after.as_secs() as i16)
+            }
+            AutoDisconnect::After(after) => Err(ProfileError::InvalidAutoDisconnect(after)),
+        }
+    }
+}
+
+/// Latency mode and auto disconnect of a connection channel.
+///
+/// A profile is validated when it is made, so the commands it builds are
+/// always accepted by flicd.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ConnectionProfile {
+    latency_mode: LatencyMode,
+    auto_disconnect: AutoDisconnect,
+}
+
+impl Default for ConnectionProfile {
+    /// Normal latency, never disconnected.
+    fn default() -> Self {
+        ConnectionProfile {
+            latency_mode: LatencyMode::NormalLatency,
+            auto_disconnect: AutoDisconnect::Never,
+        }
+    }
+}
+
+impl ConnectionProfile {
+    pub const NAMES: [&'static str; 3] = ["normal", "responsive", "battery-saver"];
+
+    pub fn new(
+        latency_mode: LatencyMode,
+        auto_disconnect: AutoDisconnect,
+    ) -> Result<ConnectionProfile, ProfileError> {
+        auto_disconnect.to_wire()?;
+        Ok(ConnectionProfile {
+            latency_mode,
+            auto_disconnect,
+        })
+    }
+
+    /// Low latency, never disconnected.
+    pub fn responsive() -> ConnectionProfile {
+        ConnectionProfile {
+            latency_mode: LatencyMode::LowLatency,
+            auto_disconnect: AutoDisconnect::Never,
+        }
+    }
+
+    /// High latency, disconnected after five idle minutes.
+    pub fn battery_saver() -> ConnectionProfile {
+        ConnectionProfile {
+            latency_mode: LatencyMode::HighLatency,
+            auto_disconnect: AutoDisconnect::After(Duration::from_secs(300)),
+        }
+    }
+
+    /// One of the profiles in `NAMES`.
+    pub fn named(name: &str) -> Option<ConnectionProfile> {
+        match name {
+            "normal" => Some(ConnectionProfile::default()),
+            "responsive" => Some(ConnectionProfile::responsive()),
+            "battery-saver" => Some(ConnectionProfile::battery_saver()),
+            _ => None,
+        }
+    }
+
+    pub fn latency_mode(&self) -> LatencyMode {
+        self.latency_mode
+    }
+
+    pub fn auto_disconnect(&self) -> AutoDisconnect {
+        self.auto_disconnect
+    }
+
+    pub fn with_latency_mode(mut self, latency_mode: LatencyMode) -> Self {
+        self.latency_mode = latency_mode;
+        self
+    }
+
+    pub fn with_auto_disconnect(
+        self,
+        auto_disconnect: AutoDisconnect,
+    ) -> Result<Self, ProfileError> {
+        ConnectionProfile::new(self.latency_mode, auto_disconnect)
+    }
+
+    fn auto_disconnect_time(&self) -> i16 {
+        self.auto_disconnect.to_wire().unwrap_or(NEVER)
+    }
+
+    /// Opens a connection channel with this profile.
+    pub fn create_channel(&self, conn_id: u32, bd_addr: &str) -> Command {
+        Command::CreateConnectionChannel {
+            conn_id,
+            bd_addr: bd_addr.to_string(),
+            latency_mode: self.latency_mode,
+            auto_disconnect_time: self.auto_disconnect_time(),
+        }
+    }
+
+    /// Switches a live connection channel to this profile.
+    pub fn change_mode(&self, conn_id: u32) -> Command {
+        Command::ChangeModeParameters {
+            conn_id,
+            latency_mode: self.latency_mode,
+            auto_disconnect_time: self.auto_disconnect_time(),
+        }
+    }
+
+    /// The profile a `CreateConnectionChannel` or `ChangeModeParameters`
+    /// command sets.
+    pub fn of_command(command: &Command) -> Option<Result<ConnectionProfile, ProfileError>> {
+        match command {
+            Command::CreateConnectionChannel {
+                latency_mode,
+                auto_disconnect_time,
+                ..
+            }
+            | Command::ChangeModeParameters {
+                latency_mode,
+                auto_disconnect_time,
+                ..
+            } => Some(AutoDisconnect::from_wire(*auto_disconnect_time).and_then(
+                |auto_disconnect| ConnectionProfile::new(*latency_mode, auto_disconnect),
+            )),
+            _ => None,
+        }
+    }
+}
+
+impl FromStr for ConnectionProfile {
+    type Err = ProfileError;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        ConnectionProfile::named(name).ok_or_else(|| ProfileError::UnknownProfile(name.to_string()))
+    }
+}
@@ -0,0 +1,350 @@
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+
+use tokio::io::{self, AsyncRead, AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::mpsc::{unbounded_channel, UnboundedSender};
+use tracing::info;
+
+/// Reads one packet, i.e. the opcode and the body following the two byte
+/// length prefix.
+pub async fn read_packet<R: AsyncRead + Unpin>(reader: &mut R) -> io::Result<Vec<u8>> {
+    let len = reader.read_u16_le().await? as usize;
+    let mut packet = vec![0; len];
+    reader.read_exact(&mut packet).await?;
+    Ok(packet)
+}
+
+/// Prepends the length to a packet read by `read_packet`.
+pub fn frame_packet(packet: &[u8]) -> Vec<u8> {
+    let mut framed = Vec::with_capacity(packet.len() + 2);
+    framed.push(packet.len() as u8);
+    framed.push((packet.len() >> 8) as u8);
+    framed.extend_from_slice(packet);
+    framed
+}
+
+/// Takes the complete packets, length prefix included, off the front of a
+/// buffer of bytes read from flicd. An incomplete packet stays behind.
+pub fn split_packets(buffer: &mut Vec<u8>) -> Vec<Vec<u8>> {
+    let mut packets = vec![];
+    while buffer.len() >= 2 {
+        let len = buffer[0] as usize | (buffer[1] as usize) << 8;
+        if buffer.len() < len + 2 {
+            break;
+        }
+        packets.push(buffer.drain(..len + 2).collect());
+    }
+    packets
+}
+
+#[derive(Debug, PartialEq, Eq, Hash, Copy, Clone)]
+enum IdKind {
+    Scan,
+    Conn,
+    Ping,
+    ScanWizard,
+    Listener,
+}
+
+fn packet_id(packet: &[u8]) -> Option<u32> {
+    packet
+        .get(1..5)
+        .map(|id| u32::from_le_bytes([id[0], id[1], id[2], id[3]]))
+}
+
+fn with_id(packet: &[u8], id: u32) -> Vec<u8> {
+    let mut packet = packet.to_vec();
+    packet[1..5].copy_from_slice(&id.to_le_bytes());
+    packet
+}
+
+fn packet_bd_addr(packet: &[u8]) -> Option<&[u8]> {
+    packet.get(1..7)
+}
+
+/// Id rewriting and event routing of `FlicProxy`, independent of any IO.
+///
+/// Packets are given and returned without their length prefix. Every id a
+/// client chooses is replaced by a proxy wide unique one before it goes
+/// upstream, and events carrying such an id are only routed back to the
+/// client owning it with the client's id restored. Events without an id are
+/// broadcast, except for the responses to `GetInfo` and `GetButtonInfo`
+/// which go to the client that asked.
+#[derive(Default)]
+pub struct Multiplexer {
+    next_client: usize,
+    clients: Vec<usize>,
+    next_id: HashMap<IdKind, u32>,
+    upstream_ids: HashMap<(IdKind, usize, u32), u32>,
+    owners: HashMap<(IdKind, u32), (usize, u32)>,
+    info_requests: VecDeque<usize>,
+    button_info_requests: VecDeque<usize>,
+    delete_requests: VecDeque<(usize, Vec<u8>)>,
+}
+
+impl Multiplexer {
+    pub fn new() -> Multiplexer {
+        Multiplexer::default()
+    }
+
+    pub fn add_client(&mut self) -> usize {
+        self.next_client += 1;
+        self.clients.push(self.next_client);
+        self.next_client
+    }
+
+    /// Forgets a client and returns the commands releasing what it created
+    /// upstream. Its pending requests stay queued so that their responses
+    /// are discarded instead of reaching the next client in line.
+    pub fn remove_client(&mut self, client: usize) -> Vec<Vec<u8>> {
+        self.clients.retain(|c| *c != client);
+        let owned: Vec<(IdKind, u32, u32)> = self
+            .upstream_ids
+            .iter()
+            .filter(|((_, c, _), _)| *c == client)
+            .map(|((kind, _, id), upstream)| (*kind, *id, *upstream))
+            .collect();
+        let mut commands = vec![];
+        for (kind, id, upstream) in owned {
+            self.upstream_ids.remove(&(kind, client, id));
+            self.owners.remove(&(kind, upstream));
+            let opcode = match kind {
+                IdKind::Scan => 2,
+                IdKind::Conn => 4,
+                IdKind::ScanWizard => 10,
+                IdKind::Listener => 13,
+                IdKind::Ping => continue,
+            };
+            let mut command = vec![opcode];
+            command.extend_from_slice(&upstream.to_le_bytes());
+            commands.push(command);
+        }
+        commands
+    }
+
+    fn create(&mut self, kind: IdKind, client: usize, id: u32) -> u32 {
+        if let Some(upstream) = self.upstream_ids.get(&(kind, client, id)) {
+            return *upstream;
+        }
+        let next_id = self.next_id.entry(kind).or_insert(0);
+        *next_id += 1;
+        let upstream = *next_id;
+        self.upstream_ids.insert((kind, client, id), upstream);
+        self.owners.insert((kind, upstream), (client, id));
+        upstream
+    }
+
+    fn release(&mut self, kind: IdKind, upstream: u32) {
+        if let Some((client, id)) = self.owners.remove(&(kind, upstream)) {
+            self.upstream_ids.remove(&(kind, client, id));
+        }
+    }
+
+    /// Rewrites a command of a client, `None` if it must not be forwarded.
+    pub fn command(&mut self, client: usize, packet: &[u8]) -> Option<Vec<u8>> {
+        let opcode = *packet.first()?;
+        let created = match opcode {
+            1 => Some(IdKind::Scan),
+            3 => Some(IdKind::Conn),
+            7 => Some(IdKind::Ping),
+            9 => Some(IdKind::ScanWizard),
+            12 => Some(IdKind::Listener),
+            _ => None,
+        };
+        let referenced = match opcode {
+            2 => Some(IdKind::Scan),
+            4 | 6 => Some(IdKind::Conn),
+            10 => Some(IdKind::ScanWizard),
+            13 => Some(IdKind::Listener),
+            _ => None,
+        };
+        if let Some(kind) = created {
+            let upstream = self.create(kind, client, packet_id(packet)?);
+            return Some(with_id(packet, upstream));
+        }
+        if let Some(kind) = referenced {
+            let upstream = *self.upstream_ids.get(&(kind, client, packet_id(packet)?))?;
+            match opcode {
+                // no event confirms these
+                2 | 13 => self.release(kind, upstream),
+                _ => {}
+            }
+            return Some(with_id(packet, upstream));
+        }
+        match opcode {
+            0 => self.info_requests.push_back(client),
+            8 => self.button_info_requests.push_back(client),
+            11 => self
+                .delete_requests
+                .push_back((client, packet_bd_addr(packet)?.to_vec())),
+            _ => {}
+        }
+        Some(packet.to_vec())
+    }
+
+    /// Routes an event from flicd to the clients it concerns.
+    pub fn event(&mut self, packet: &[u8]) -> Vec<(usize, Vec<u8>)> {
+        let opcode = match packet.first() {
+            Some(opcode) => *opcode,
+            None => return vec![],
+        };
+        let kind = match opcode {
+            0 => Some(IdKind::Scan),
+            1..=7 => Some(IdKind::Conn),
+            13 => Some(IdKind::Ping),
+            15..=18 => Some(IdKind::ScanWizard),
+            20 => Some(IdKind::Listener),
+            _ => None,
+        };
+        if let Some(kind) = kind {
+            let upstream = match packet_id(packet) {
+                Some(upstream) => upstream,
+                None => return vec![],
+            };
+            let (client, id) = match self.owners.get(&(kind, upstream)) {
+                Some(owner) => *owner,
+                None => return vec![],
+            };
+            match opcode {
+                3 | 13 | 18 => self.release(kind, upstream),
+                _ => {}
+            }
+            return vec![(client, with_id(packet, id))];
+        }
+        let requester = match opcode {
+            9 => self.info_requests.pop_front(),
+            14 => self.button_info_requests.pop_front(),
+            _ => None,
+        };
+        if let Some(client) = requester {
+            if !self.clients.contains(&client) {
+                return vec![];
+            }
+            return vec![(client, packet.to_vec())];
+        }
+        if opcode == 19 && packet.get(7) == Some(&1) {
+            let bd_addr = packet_bd_addr(packet).map(|b| b.to_vec());
+            let deleter = self
+                .delete_requests
+                .iter()
+                .position(|(_, b)| Some(b) == bd_addr.as_ref())
+                .and_then(|idx| self.delete_requests.remove(idx))
+                .map(|(client, _)| client);
+            return self
+                .clients
+                .iter()
+                .map(|client| {
+                    let mut packet = packet.to_vec();
+                    packet[7] = (Some(*client) == deleter) as u8;
+                    (*client, packet)
+                })
+                .collect();
+        }
+        self.clients
+            .iter()
+            .map(|client| (*client, packet.to_vec()))
+            .collect()
+    }
+}
+
+/// Shares one flicd connection between many clients speaking the flicd
+/// protocol.
+pub struct FlicProxy {
+    upstream: String,
+}
+
+impl FlicProxy {
+    pub fn new(upstream: &str) -> FlicProxy {
+        FlicProxy {
+            upstream: upstream.to_string(),
+        }
+    }
+
+    /// Accepts clients on `listen` until the connection to flicd is lost.
+    pub async fn run(&self, listen: &str) -> io::Result<()> {
+        let (mut upstream_reader, mut upstream_writer) =
+            TcpStream::connect(&self.upstream[..]).await?.into_split();
+        let mut listener = TcpListener::bind(listen).await?;
+        let mux = Arc::new(Mutex::new(Multiplexer::new()));
+        let clients: Arc<Mutex<HashMap<usize, UnboundedSender<Vec<u8>>>>> =
+            Arc::new(Mutex::new(HashMap::new()));
+
+        let (upstream, mut commands) = unbounded_channel::<Vec<u8>>();
+        tokio::spawn(async move {
+            while let Some(packet) = commands.recv().await {
+                if upstream_writer
+                    .write_all(&frame_packet(&packet))
+                    .await
+                    .is_err()
+                {
+                    return;
+                }
+            }
+        });
+
+        let routes = {
+            let mux = mux.clone();
+            let clients = clients.clone();
+            tokio::spawn(async move {
+                loop {
+                    let packet = match read_packet(&mut upstream_reader).await {
+                        Ok(packet) => packet,
+                        Err(err) => return err,
+                    };
+                    let routed = mux.lock().unwrap().event(&packet);
+                    let clients = clients.lock().unwrap();
+                    for (client, packet) in routed {
+                        if let Some(sender) = clients.get(&client) {
+                            let _ = sender.send(packet);
+                        }
+                    }
+                }
+            })
+        };
+
+        let accept = async {
+            loop {
+                let (stream, addr) = match listener.accept().await {
+                    Ok(conn) => conn,
+                    Err(err) => return Err::<(), _>(err),
+                };
+                let (mut reader, mut writer) = stream.into_split();
+                let client = mux.lock().unwrap().add_client();
+                info!(client, %addr, "client connected");
+                let (sender, mut events) = unbounded_channel::<Vec<u8>>();
+                clients.lock().unwrap().insert(client, sender);
+                tokio::spawn(async move {
+                    while let Some(packet) = events.recv().await {
+                        if writer.write_all(&frame_packet(&packet)).await.is_err() {
+                            return;
+                        }
+                    }
+                });
+                let mux = mux.clone();
+                let clients = clients.clone();
+                let upstream = upstream.clone();
+                tokio::spawn(async move {
+                    while let Ok(packet) = read_packet(&mut reader).await {
+                        if let Some(packet) = mux.lock().unwrap().command(client, &packet) {
+                            let _ = upstream.send(packet);
+                        }
+                    }
+                    clients.lock().unwrap().remove(&client);
+                    for packet in mux.lock().unwrap().remove_client(client) {
+                        let _ = upstream.send(packet);
+                    }
+                    info!(client, "client disconnected");
+                });
+            }
+        };
+
+        tokio::select! {
+            result = accept => result,
+            err = routes => Err(match err {
+                Ok(err) => err,
+                Err(err) => io::Error::other(err),
+            }),
+        }
+    }
+}
@@ -0,0 +1,202 @@
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+use tokio::io::{self, AsyncWriteExt};
+use tokio::net::TcpListener;
+use tracing::warn;
+
+use super::client::*;
+use super::events::stream_mapper::{ByteToEventMapper, EventResult};
+use super::events::*;
+use super::proxy::{read_packet, split_packets};
+
+/// One packet of a recording, a line of its JSON lines file.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RecordedPacket {
+    /// Milliseconds since the recording started.
+    pub t: u64,
+    pub dir: PacketDirection,
+    /// The packet including its length prefix, in hex.
+    pub data: String,
+}
+
+impl RecordedPacket {
+    pub fn new(t: u64, dir: PacketDirection, bytes: &[u8]) -> RecordedPacket {
+        RecordedPacket {
+            t,
+            dir,
+            data: bytes.iter().map(|b| format!("{:02x}", b)).collect(),
+        }
+    }
+
+    /// The raw bytes of the packet.
+    pub fn bytes(&self) -> Vec<u8> {
+        self.data
+            .as_bytes()
+            .chunks(2)
+            .filter(|pair| pair.len() == 2)
+            .filter_map(|pair| std::str::from_utf8(pair).ok())
+            .filter_map(|pair| u8::from_str_radix(pair, 16).ok())
+            .collect()
+    }
+}
+
+struct RecorderState {
+    file: File,
+    started: Instant,
+    inbound: Vec<u8>,
+}
+
+impl RecorderState {
+    fn write(&mut self, dir: PacketDirection, bytes: &[u8]) {
+        let packet = RecordedPacket::new(self.started.elapsed().as_millis() as u64, dir, bytes);
+        if let Ok(line) = serde_json::to_string(&packet) {
+            if let Err(err) = writeln!(self.file, "{}", line) {
+                warn!(error = %err, "recording not written");
+            }
+        }
+    }
+
+    fn record(&mut self, dir: PacketDirection, bytes: &[u8]) {
+        if dir == PacketDirection::Outbound {
+            self.write(dir, bytes);
+            return;
+        }
+        self.inbound.extend_from_slice(bytes);
+        for packet in split_packets(&mut self.inbound) {
+            self.write(dir, &packet);
+        }
+    }
+}
+
+/// Writes every packet a client exchanges with flicd to a JSON lines file.
+///
+/// Register `handler()` with `FlicClient::register_packet_handler`.
+#[derive(Clone)]
+pub struct Recorder {
+    state: Arc<Mutex<RecorderState>>,
+}
+
+impl Recorder {
+    /// Starts a recording, appending to `path` if it exists.
+    pub fn create<P: AsRef<Path>>(path: P) -> io::Result<Recorder> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(Recorder {
+            state: Arc::new(Mutex::new(RecorderState {
+                file,
+                started: Instant::now(),
+                inbound: vec![],
+            })),
+        })
+    }
+
+    pub fn record(&self, dir: PacketDirection, bytes: &[u8]) {
+        self.state.lock().unwrap().record(dir, bytes);
+    }
+
+    pub fn handler(&self) -> PacketClosureMutex {
+        let recorder = self.clone();
+        packet_handler(move |dir, bytes| recorder.record(dir, bytes))
+    }
+}
+
+/// Delay before a packet when replaying at `speed` times the original pace.
+fn delay(previous: u64, t: u64, speed: f64) -> Option<Duration> {
+    if speed <= 0.0 || !speed.is_finite() || t <= previous {
+        return None;
+    }
+    Some(Duration::from_secs_f64(
+        (t - previous) as f64 / 1000.0 / speed,
+    ))
+}
+
+/// A recorded session, for replaying it.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Recording {
+    pub packets: Vec<RecordedPacket>,
+}
+
+impl Recording {
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Recording> {
+        let mut packets = vec![];
+        for line in BufReader::new(File::open(path)?).lines() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            packets.push(serde_json::from_str(&line)?);
+        }
+        Ok(Recording { packets })
+    }
+
+    /// Decodes everything flicd sent.
+    pub fn events(&self) -> Vec<Event> {
+        let mut events = vec![];
+        let mut mapper = ByteToEventMapper::new();
+        for packet in &self.packets {
+            if packet.dir == PacketDirection::Inbound {
+                for b in packet.bytes() {
+                    match mapper.map(b) {
+                        EventResult::Some(Event::NoOp) => {}
+                        EventResult::Some(event) => events.push(event),
+                        _ => {}
+                    }
+                }
+            }
+        }
+        events
+    }
+
+    /// Passes the events flicd sent to `f` with their original timing
+    /// divided by `speed`. A speed of zero or infinity replays without
+    /// delays.
+    pub async fn replay<F: FnMut(&Event)>(&self, speed: f64, mut f: F) {
+        let mut mapper = ByteToEventMapper::new();
+        let mut previous = self.packets.first().map_or(0, |packet| packet.t);
+        for packet in &self.packets {
+            if packet.dir != PacketDirection::Inbound {
+                continue;
+            }
+            if let Some(delay) = delay(previous, packet.t, speed) {
+                tokio::time::delay_for(delay).await;
+            }
+            previous = packet.t;
+            for b in packet.bytes() {
+                match mapper.map(b) {
+                    EventResult::Some(Event::NoOp) => {}
+                    EventResult::Some(event) => f(&event),
+                    _ => {}
+                }
+            }
+        }
+    }
+
+    /// Plays flicd for one client connecting to `listener`.
+    ///
+    /// Recorded inbound packets are sent with their original timing divided
+    /// by `speed`. For every recorded outbound packet the server first waits
+    /// for the client to send a packet, so responses follow their requests.
+    pub async fn serve(&self, mut listener: TcpListener, speed: f64) -> io::Result<()> {
+        let (mut stream, _) = listener.accept().await?;
+        let mut previous = self.packets.first().map_or(0, |packet| packet.t);
+        for packet in &self.packets {
+            match packet.dir {
+                PacketDirection::Outbound => {
+                    read_packet(&mut stream).await?;
+                }
+                PacketDirection::Inbound => {
+                    if let Some(delay) = delay(previous, packet.t, speed) {
+                        tokio::time::delay_for(delay).await;
+                    }
+                    stream.write_all(&packet.bytes()).await?;
+                }
+            }
+            previous = packet.t;
+        }
+        stream.flush().await
+    }
+}
@@ -0,0 +1,570 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime};
+
+use chrono::{Local, NaiveTime};
+use serde::Deserialize;
+use tracing::warn;
+
+use super::client::{event_handler, EventClosureMutex, FlicClient};
+use super::commands::{is_bd_addr, Command};
+use super::enums::*;
+use super::events::*;
+use super::exec::CommandRunner;
+
+// Errors
+
+#[derive(Debug)]
+pub enum RulesError {
+    Io(std::io::Error),
+    Toml(toml::de::Error),
+    Yaml(serde_yaml::Error),
+    UnknownFormat(PathBuf),
+    UnknownButton(String),
+    UnknownClick(String),
+    UnknownConnectionStatus(String),
+    InvalidTime(String),
+}
+
+impl fmt::Display for RulesError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(err) => write!(f, "cannot read rules file: {}", err),
+            Self::Toml(err) => write!(f, "invalid TOML rules: {}", err),
+            Self::Yaml(err) => write!(f, "invalid YAML rules: {}", err),
+            Self::UnknownFormat(path) => {
+                write!(f, "unknown rules file format: {}", path.display())
+            }
+            Self::UnknownButton(name) => write!(f, "unknown button: {}", name),
+            Self::UnknownClick(click) => write!(f, "unknown click type: {}", click),
+            Self::UnknownConnectionStatus(status) => {
+                write!(f, "unknown connection status: {}", status)
+            }
+            Self::InvalidTime(time) => write!(f, "invalid time of day (HH:MM): {}", time),
+        }
+    }
+}
+
+impl std::error::Error for RulesError {}
+
+impl From<std::io::Error> for RulesError {
+    fn from(err: std::io::Error) -> Self {
+        Self::Io(err)
+    }
+}
+impl From<toml::de::Error> for RulesError {
+    fn from(err: toml::de::Error) -> Self {
+        Self::Toml(err)
+    }
+}
+impl From<serde_yaml::Error> for RulesError {
+    fn from(err: serde_yaml::Error) -> Self {
+        Self::Yaml(err)
+    }
+}
+
+// Configuration
+
+/// Contents of a rules file.
+///
+/// ```toml
+/// [buttons]
+/// kitchen = "80:e4:da:76:fa:55"
+///
+/// [[rule]]
+/// button = "kitchen"
+/// click = "double"
+/// action = { exec = "echo hello" }
+/// debounce_ms = 500
+/// when = { after = "07:00", before = "22:00", min_battery = 10 }
+/// ```
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct RulesConfig {
+    #[serde(default)]
+    pub buttons: HashMap<String, String>,
+    #[serde(default, rename = "rule")]
+    pub rules: Vec<RuleConfig>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct RuleConfig {
+    pub button: Option<String>,
+    pub click: String,
+    pub action: Action,
+    #[serde(default)]
+    pub when: Conditions,
+    pub debounce_ms: Option<u64>,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Action {
+    Exec(String),
+}
+
+/// An action of a matching rule together with the click that fired it.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Trigger {
+    pub action: Action,
+    pub bd_addr: String,
+    pub conn_id: u32,
+    pub click_type: ClickType,
+    pub was_queued: bool,
+    pub time_diff: i32,
+}
+
+impl Trigger {
+    /// Environment variables describing the click for executed commands.
+    pub fn env(&self) -> Vec<(String, String)> {
+        vec![
+            ("FLIC_BDADDR".to_string(), self.bd_addr.clone()),
+            ("FLIC_CONN_ID".to_string(), self.conn_id.to_string()),
+            (
+                "FLIC_CLICK_TYPE".to_string(),
+                format!("{:?}", self.click_type),
+            ),
+            ("FLIC_WAS_QUEUED".to_string(), self.was_queued.to_string()),
+            ("FLIC_TIME_DIFF".to_string(), self.time_diff.to_string()),
+        ]
+    }
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct Conditions {
+    pub after: Option<String>,
+    pub before: Option<String>,
+    pub min_battery: Option<i8>,
+    pub max_battery: Option<i8>,
+    pub connection: Option<String>,
+}
+
+impl RulesConfig {
+    pub fn from_toml_str(s: &str) -> Result<RulesConfig, RulesError> {
+        Ok(toml::from_str(s)?)
+    }
+    pub fn from_yaml_str(s: &str) -> Result<RulesConfig, RulesError> {
+        Ok(serde_yaml::from_str(s)?)
+    }
+    pub fn from_file(path: &Path) -> Result<RulesConfig, RulesError> {
+        let content = fs::read_to_string(path)?;
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some("toml") => Self::from_toml_str(&content),
+            Some("yaml") | Some("yml") => Self::from_yaml_str(&content),
+            _ => Err(RulesError::UnknownFormat(path.to_path_buf())),
+        }
+    }
+}
+
+pub fn parse_click_type(click: &str) -> Result<ClickType, RulesError> {
+    match click {
+        "down" => Ok(ClickType::ButtonDown),
+        "up" => Ok(ClickType::ButtonUp),
+        "click" => Ok(ClickType::ButtonClick),
+        "single" => Ok(ClickType::ButtonSingleClick),
+        "double" => Ok(ClickType::ButtonDoubleClick),
+        "hold" => Ok(ClickType::ButtonHold),
+        _ => Err(RulesError::UnknownClick(click.to_string())),
+    }
+}
+
+fn parse_connection_status(status: &str) -> Result<ConnectionStatus, RulesError> {
+    match status {
+        "disconnected" => Ok(ConnectionStatus::Disconnected),
+        "connected" => Ok(ConnectionStatus::Connected),
+        "ready" => Ok(ConnectionStatus::Ready),
+        _ => Err(RulesError::UnknownConnectionStatus(status.to_string())),
+    }
+}
+
+fn parse_time(time: &str) -> Result<NaiveTime, RulesError> {
+    NaiveTime::parse_from_str(time, "%H:%M").map_err(|_| RulesError::InvalidTime(time.to_string()))
+}
+
+// Compiled rules
+
+#[derive(Debug, Clone)]
+struct Rule {
+    bd_addr: Option<String>,
+    click_type: ClickType,
+    action: Action,
+    after: Option<NaiveTime>,
+    before: Option<NaiveTime>,
+    min_battery: Option<i8>,
+    max_battery: Option<i8>,
+    connection: Option<ConnectionStatus>,
+    debounce: Option<Duration>,
+}
+
+impl Rule {
+    fn compile(config: &RuleConfig, buttons: &HashMap<String, String>) -> Result<Rule, RulesError> {
+        let bd_addr = match config.button.as_deref() {
+            None | Some("*") => None,
+            Some(name) => match buttons.get(name) {
+                Some(bd_addr) => Some(bd_addr.to_lowercase()),
+                None if is_bd_addr(name) => Some(name.to_lowercase()),
+                None => return Err(RulesError::UnknownButton(name.to_string())),
+            },
+        };
+        Ok(Rule {
+            bd_addr,
+            click_type: parse_click_type(&config.click)?,
+            action: config.action.clone(),
+            after: config.when.after.as_deref().map(parse_time).transpose()?,
+            before: config.when.before.as_deref().map(parse_time).transpose()?,
+            min_battery: config.when.min_battery,
+            max_battery: config.when.max_battery,
+            connection: config
+                .when
+                .connection
+                .as_deref()
+                .map(parse_connection_status)
+                .transpose()?,
+            debounce: config.debounce_ms.map(Duration::from_millis),
+        })
+    }
+
+    fn is_time_of_day(&self, now: NaiveTime) -> bool {
+        match (self.after, self.before) {
+            (Some(after), Some(before)) if after <= before => now >= after && now < before,
+            (Some(after), Some(before)) => now >= after || now < before,
+            (Some(after), None) => now >= after,
+            (None, Some(before)) => now < before,
+            (None, None) => true,
+        }
+    }
+
+    fn matches(&self, button: &ButtonState, click_type: ClickType, now: NaiveTime) -> bool {
+        if self.click_type != click_type {
+            return false;
+        }
+        if let Some(ref bd_addr) = self.bd_addr {
+            if *bd_addr != button.bd_addr {
+                return false;
+            }
+        }
+        if !self.is_time_of_day(now) {
+            return false;
+        }
+        if let Some(min) = self.min_battery {
+            if button.battery_percentage.is_none_or(|b| b < min) {
+                return false;
+            }
+        }
+        if let Some(max) = self.max_battery {
+            if button.battery_percentage.is_none_or(|b| b > max) {
+                return false;
+            }
+        }
+        if let Some(connection) = self.connection {
+            if button.connection_status != Some(connection) {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+// Engine
+
+#[derive(Debug, Clone)]
+struct ButtonState {
+    bd_addr: String,
+    conn_id: u32,
+    listener_id: u32,
+    connection_status: Option<ConnectionStatus>,
+    battery_percentage: Option<i8>,
+    pinned: bool,
+}
+
+struct EngineState {
+    path: Option<PathBuf>,
+    modified: Option<SystemTime>,
+    rules: Vec<Rule>,
+    buttons: Vec<ButtonState>,
+    next_id: u32,
+    /// When a rule last fired for a button.
+    last_fired: HashMap<(usize, String), Instant>,
+    runner: CommandRunner,
+}
+
+impl EngineState {
+    fn button_by_conn_id(&self, conn_id: u32) -> Option<&ButtonState> {
+        self.buttons.iter().find(|b| b.conn_id == conn_id)
+    }
+
+    /// Replaces the rules and returns the commands needed to follow the
+    /// changed set of buttons.
+    fn apply(&mut self, config: &RulesConfig) -> Result<Vec<Command>, RulesError> {
+        let rules = config
+            .rules
+            .iter()
+            .map(|rule| Rule::compile(rule, &config.buttons))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let mut wanted: Vec<String> = config.buttons.values().map(|b| b.to_lowercase()).collect();
+        for rule in rules.iter() {
+            if let Some(ref bd_addr) = rule.bd_addr {
+                wanted.push(bd_addr.clone());
+            }
+        }
+        wanted.sort();
+        wanted.dedup();
+
+        let mut commands = vec![];
+        self.buttons.retain(|button| {
+            if button.pinned || wanted.contains(&button.bd_addr) {
+                true
+            } else {
+                commands.push(Command::RemoveBatteryStatusListener {
+                    listener_id: button.listener_id,
+                });
+                commands.push(Command::RemoveConnectionChannel {
+                    conn_id: button.conn_id,
+                });
+                false
+            }
+        });
+        for bd_addr in wanted {
+            commands.extend(self.add_button(bd_addr, false));
+        }
+
+        self.rules = rules;
+        self.last_fired.clear();
+        Ok(commands)
+    }
+
+    fn add_button(&mut self, bd_addr: String, pinned: bool) -> Vec<Command> {
+        if let Some(button) = self.buttons.iter_mut().find(|b| b.bd_addr == bd_addr) {
+            button.pinned |= pinned;
+            return vec![];
+        }
+        self.next_id += 1;
+        let button = ButtonState {
+            bd_addr,
+            conn_id: self.next_id,
+            listener_id: self.next_id,
+            connection_status: None,
+            battery_percentage: None,
+            pinned,
+        };
+        let commands = connect_commands(&button);
+        self.buttons.push(button);
+        commands
+    }
+
+    fn handle(&mut self, event: &Event) -> Vec<Trigger> {
+        match *event {
+            Event::CreateConnectionChannelResponse(CreateConnectionChannelResponse {
+                conn_id,
+                connection_status,
+                ..
+            })
+            | Event::ConnectionStatusChanged(ConnectionStatusChanged {
+                conn_id,
+                connection_status,
+                ..
+            }) => {
+                if let Some(button) = self.buttons.iter_mut().find(|b| b.conn_id == conn_id) {
+                    button.connection_status = Some(connection_status);
+                }
+                vec![]
+            }
+            Event::BatteryStatus(BatteryStatus {
+                listener_id,
+                battery_percentage,
+                ..
+            }) => {
+                if let Some(button) = self
+                    .buttons
+                    .iter_mut()
+                    .find(|b| b.listener_id == listener_id)
+                {
+                    button.battery_percentage = Some(battery_percentage).filter(|b| *b >= 0);
+                }
+                vec![]
+            }
+            _ => match event.gesture() {
+                Some(gesture) => self.fire(
+                    gesture.conn_id,
+                    gesture.click_type,
+                    gesture.was_queued,
+                    gesture.time_diff,
+                ),
+                None => vec![],
+            },
+        }
+    }
+
+    fn fire(
+        &mut self,
+        conn_id: u32,
+        click_type: ClickType,
+        was_queued: bool,
+        time_diff: i32,
+    ) -> Vec<Trigger> {
+        let button = match self.button_by_conn_id(conn_id) {
+            Some(button) => button.clone(),
+            None => return vec![],
+        };
+        let now = Local::now().time();
+        let instant = Instant::now();
+
+        let mut triggers = vec![];
+        for (idx, rule) in self.rules.iter().enumerate() {
+            if !rule.matches(&button, click_type, now) {
+                continue;
+            }
+            let key = (idx, button.bd_addr.clone());
+            if let (Some(debounce), Some(last)) = (rule.debounce, self.last_fired.get(&key)) {
+                if instant.duration_since(*last) < debounce {
+                    continue;
+                }
+            }
+            self.last_fired.insert(key, instant);
+            triggers.push(Trigger {
+                action: rule.action.clone(),
+                bd_addr: button.bd_addr.clone(),
+                conn_id,
+                click_type,
+                was_queued,
+                time_diff,
+            });
+        }
+        triggers
+    }
+}
+
+fn connect_commands(button: &ButtonState) -> Vec<Command> {
+    vec![
+        Command::CreateConnectionChannel {
+            conn_id: button.conn_id,
+            bd_addr: button.bd_addr.clone(),
+            latency_mode: LatencyMode::NormalLatency,
+            auto_disconnect_time: 511,
+        },
+        Command::CreateBatteryStatusListener {
+            listener_id: button.listener_id,
+            bd_addr: button.bd_addr.clone(),
+        },
+    ]
+}
+
+/// Maps button gestures to actions as described by a rules file.
+///
+/// The engine owns the connection channels of all buttons it has rules for:
+/// submit `setup_commands()` once the client is connected and register
+/// `handler()` with the client. `watch()` reloads the file when it changes.
+#[derive(Clone)]
+pub struct RuleEngine {
+    state: Arc<Mutex<EngineState>>,
+}
+
+impl RuleEngine {
+    pub fn new(config: &RulesConfig) -> Result<RuleEngine, RulesError> {
+        let mut state = EngineState {
+            path: None,
+            modified: None,
+            rules: vec![],
+            buttons: vec![],
+            next_id: 0,
+            last_fired: HashMap::new(),
+            runner: CommandRunner::default(),
+        };
+        state.apply(config)?;
+        Ok(RuleEngine {
+            state: Arc::new(Mutex::new(state)),
+        })
+    }
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<RuleEngine, RulesError> {
+        let path = path.as_ref();
+        let engine = Self::new(&RulesConfig::from_file(path)?)?;
+        {
+            let mut state = engine.state.lock().unwrap();
+            state.path = Some(path.to_path_buf());
+            state.modified = fs::metadata(path).and_then(|m| m.modified()).ok();
+        }
+        Ok(engine)
+    }
+
+    pub fn with_runner(self, runner: CommandRunner) -> Self {
+        self.state.lock().unwrap().runner = runner;
+        self
+    }
+
+    pub fn setup_commands(&self) -> Vec<Command> {
+        let state = self.state.lock().unwrap();
+        state.buttons.iter().flat_map(connect_commands).collect()
+    }
+
+    /// Starts following a button that is not mentioned in the rules file,
+    /// so that wildcard rules apply to it. Returns the commands to connect it.
+    ///
+    /// Such buttons are kept when the rules file is reloaded.
+    pub fn add_button(&self, bd_addr: &str) -> Vec<Command> {
+        self.state
+            .lock()
+            .unwrap()
+            .add_button(bd_addr.to_lowercase(), true)
+    }
+
+    /// Feeds an event into the engine and returns the actions of all
+    /// matching rules.
+    pub fn handle(&self, event: &Event) -> Vec<Trigger> {
+        self.state.lock().unwrap().handle(event)
+    }
+
+    /// Reloads the rules file if it was modified since the last load and
+    /// returns the commands needed to follow the changed set of buttons.
+    pub fn reload_if_changed(&self) -> Result<Vec<Command>, RulesError> {
+        let mut state = self.state.lock().unwrap();
+        let path = match state.path {
+            Some(ref path) => path.clone(),
+            None => return Ok(vec![]),
+        };
+        let modified = fs::metadata(&path)?.modified().ok();
+        if modified == state.modified {
+            return Ok(vec![]);
+        }
+        // a file that fails to load is retried until it is fixed
+        let commands = state.apply(&RulesConfig::from_file(&path)?)?;
+        state.modified = modified;
+        Ok(commands)
+    }
+
+    pub fn handler(&self) -> EventClosureMutex {
+        let engine = self.clone();
+        event_handler(move |event| {
+            let triggers = engine.handle(event);
+            if triggers.is_empty() {
+                return;
+            }
+            let runner = engine.state.lock().unwrap().runner.clone();
+            for trigger in triggers {
+                let env = trigger.env();
+                match trigger.action {
+                    Action::Exec(cmd) => runner.run(cmd, env),
+                }
+            }
+        })
+    }
+
+    /// Polls the rules file until the client is stopped.
+    ///
+    /// A file that fails to load keeps the previous rules active.
+    pub async fn watch(&self, client: Arc<FlicClient>, interval: Duration) {
+        while client.is_running().await {
+            tokio::time::delay_for(interval).await;
+            match self.reload_if_changed() {
+                Ok(commands) => {
+                    for command in commands {
+                        client.submit(command).await;
+                    }
+                }
+                Err(err) => warn!(error = %err, "rules not reloaded"),
+            }
+        }
+    }
+}
@@ -0,0 +1,165 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use chrono::{Local, NaiveTime};
+
+use super::channels::ChannelMap;
+use super::client::FlicClient;
+use super::commands::Command;
+use super::profile::ConnectionProfile;
+
+#[derive(Debug, Clone)]
+struct Window {
+    bd_addr: Option<String>,
+    after: NaiveTime,
+    before: NaiveTime,
+    profile: ConnectionProfile,
+}
+
+impl Window {
+    fn contains(&self, now: NaiveTime) -> bool {
+        if self.after <= self.before {
+            now >= self.after && now < self.before
+        } else {
+            now >= self.after || now < self.before
+        }
+    }
+}
+
+/// Which profile a button should have at a time of day.
+///
+/// A window for the button wins over a window for all buttons, which wins
+/// over the profile set for the button, which wins over the default.
+/// Windows may wrap around midnight.
+#[derive(Debug, Clone, Default)]
+pub struct ProfileSchedule {
+    default: ConnectionProfile,
+    buttons: HashMap<String, ConnectionProfile>,
+    windows: Vec<Window>,
+}
+
+impl ProfileSchedule {
+    pub fn new(default: ConnectionProfile) -> ProfileSchedule {
+        ProfileSchedule {
+            default,
+            ..ProfileSchedule::default()
+        }
+    }
+
+    /// The profile of a button outside of its windows.
+    pub fn button(mut self, bd_addr: &str, profile: ConnectionProfile) -> Self {
+        self.buttons.insert(bd_addr.to_lowercase(), profile);
+        self
+    }
+
+    /// The profile of all buttons from `after` until `before`.
+    pub fn window(
+        mut self,
+        after: NaiveTime,
+        before: NaiveTime,
+        profile: ConnectionProfile,
+    ) -> Self {
+        self.windows.push(Window {
+            bd_addr: None,
+            after,
+            before,
+            profile,
+        });
+        self
+    }
+
+    /// The profile of one button from `after` until `before`.
+    pub fn button_window(
+        mut self,
+        bd_addr: &str,
+        after: NaiveTime,
+        before: NaiveTime,
+        profile: ConnectionProfile,
+    ) -> Self {
+        self.windows.push(Window {
+            bd_addr: Some(bd_addr.to_lowercase()),
+            after,
+            before,
+            profile,
+        });
+        self
+    }
+
+    pub fn profile_at(&self, bd_addr: &str, now: NaiveTime) -> ConnectionProfile {
+        let bd_addr = bd_addr.to_lowercase();
+        let window = |own: bool| {
+            self.windows
+                .iter()
+                .filter(|w| w.bd_addr.is_some() == own)
+                .filter(|w| w.bd_addr.as_ref().is_none_or(|b| *b == bd_addr))
+                .find(|w| w.contains(now))
+                .map(|w| w.profile)
+        };
+        window(true)
+            .or_else(|| window(false))
+            .or_else(|| self.buttons.get(&bd_addr).copied())
+            .unwrap_or(self.default)
+    }
+}
+
+/// Applies a `ProfileSchedule` to the live connection channels of a client.
+///
+/// Pass the client's channels, then `watch` the client. Channels get a
+/// `ChangeModeParameters` whenever their scheduled profile differs from the
+/// one last applied, new channels on the first check after they are made.
+#[derive(Clone)]
+pub struct ProfileScheduler {
+    schedule: Arc<Mutex<ProfileSchedule>>,
+    applied: Arc<Mutex<HashMap<u32, ConnectionProfile>>>,
+    channels: ChannelMap,
+}
+
+impl ProfileScheduler {
+    pub fn new(schedule: ProfileSchedule) -> ProfileScheduler {
+        ProfileScheduler {
+            schedule: Arc::new(Mutex::new(schedule)),
+            applied: Arc::new(Mutex::new(HashMap::new())),
+            channels: ChannelMap::new(),
+        }
+    }
+
+    pub fn channels(mut self, channels: ChannelMap) -> Self {
+        self.channels = channels;
+        self
+    }
+
+    /// Replaces the schedule; the next check applies it.
+    pub fn set_schedule(&self, schedule: ProfileSchedule) {
+        *self.schedule.lock().unwrap() = schedule;
+    }
+
+    /// The commands that bring all channels to their profile at `now`.
+    pub fn commands_at(&self, now: NaiveTime) -> Vec<Command> {
+        let schedule = self.schedule.lock().unwrap();
+        let mut applied = self.applied.lock().unwrap();
+        let connections = self.channels.connections();
+        applied.retain(|conn_id, _| connections.iter().any(|(id, _)| id == conn_id));
+        let mut commands = vec![];
+        for (conn_id, bd_addr) in connections {
+            let profile = schedule.profile_at(&bd_addr, now);
+            if applied.insert(conn_id, profile) != Some(profile) {
+                commands.push(profile.change_mode(conn_id));
+            }
+        }
+        commands
+    }
+
+    /// Checks the schedule every `interval` until the client is stopped.
+    pub async fn watch(&self, client: Arc<FlicClient>, interval: Duration) {
+        while client.is_running().await {
+            for command in self.commands_at(Local::now().time()) {
+                client.submit(command).await;
+            }
+            tokio::select! {
+                _ = tokio::time::delay_for(interval) => {}
+                _ = client.stopped() => break,
+            }
+        }
+    }
+}
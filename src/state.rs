@@ -0,0 +1,363 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::{Arc, Mutex};
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use super::channels::ChannelMap;
+use super::client::*;
+use super::enums::*;
+use super::events::*;
+
+/// A connection channel as last reported by flicd.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct ChannelState {
+    pub bd_addr: Option<String>,
+    pub connection_status: ConnectionStatus,
+    /// Why the button disconnected the last time, kept after it reconnects.
+    pub last_disconnect_reason: Option<DisconnectReason>,
+}
+
+/// One change of the server state, as passed to the change handlers.
+///
+/// With the `serde` feature it serializes with a `change` tag, e.g.
+/// `{"change": "button_verified", "bd_addr": "80:e4:da:76:fa:55"}`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(tag = "change", rename_all = "snake_case"))]
+pub enum StateChange {
+    ControllerState {
+        state: BluetoothControllerState,
+    },
+    ButtonVerified {
+        bd_addr: String,
+    },
+    ButtonRemoved {
+        bd_addr: String,
+    },
+    ChannelStatus {
+        conn_id: u32,
+        bd_addr: Option<String>,
+        connection_status: ConnectionStatus,
+        disconnect_reason: Option<DisconnectReason>,
+    },
+    ChannelRemoved {
+        conn_id: u32,
+        bd_addr: Option<String>,
+        removed_reason: RemovedReason,
+    },
+    PendingConnections {
+        current: u8,
+        max: u8,
+    },
+    Space {
+        no_space_for_new_connection: bool,
+        max_concurrently_connected_buttons: i16,
+    },
+}
+
+/// What is known about a flicd server at one point in time.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct ServerSnapshot {
+    pub bluetooth_controller_state: Option<BluetoothControllerState>,
+    pub my_bd_addr: Option<String>,
+    pub verified_buttons: BTreeSet<String>,
+    pub channels: BTreeMap<u32, ChannelState>,
+    pub current_pending_connections: u8,
+    pub max_pending_connections: u8,
+    pub no_space_for_new_connection: bool,
+    pub max_concurrently_connected_buttons: i16,
+}
+
+impl ServerSnapshot {
+    fn controller(&mut self, state: BluetoothControllerState, changes: &mut Vec<StateChange>) {
+        if self.bluetooth_controller_state == Some(state) {
+            return;
+        }
+        self.bluetooth_controller_state = Some(state);
+        changes.push(StateChange::ControllerState { state });
+        if state == BluetoothControllerState::Attached {
+            return;
+        }
+        // without a controller no button stays connected, flicd may not say so
+        for (conn_id, channel) in self.channels.iter_mut() {
+            if channel.connection_status != ConnectionStatus::Disconnected {
+                channel.connection_status = ConnectionStatus::Disconnected;
+                changes.push(StateChange::ChannelStatus {
+                    conn_id: *conn_id,
+                    bd_addr: channel.bd_addr.clone(),
+                    connection_status: ConnectionStatus::Disconnected,
+                    disconnect_reason: None,
+                });
+            }
+        }
+    }
+
+    fn space(&mut self, no_space: bool, max: i16, changes: &mut Vec<StateChange>) {
+        if self.no_space_for_new_connection != no_space
+            || self.max_concurrently_connected_buttons != max
+        {
+            self.no_space_for_new_connection = no_space;
+            self.max_concurrently_connected_buttons = max;
+            changes.push(StateChange::Space {
+                no_space_for_new_connection: no_space,
+                max_concurrently_connected_buttons: max,
+            });
+        }
+    }
+
+    fn channel(
+        &mut self,
+        conn_id: u32,
+        bd_addr: Option<String>,
+        status: ConnectionStatus,
+        reason: Option<DisconnectReason>,
+        changes: &mut Vec<StateChange>,
+    ) {
+        let known = self.channels.contains_key(&conn_id);
+        let channel = self.channels.entry(conn_id).or_insert(ChannelState {
+            bd_addr: None,
+            connection_status: status,
+            last_disconnect_reason: None,
+        });
+        let unchanged = known
+            && channel.connection_status == status
+            && (reason.is_none() || channel.last_disconnect_reason == reason)
+            && (bd_addr.is_none() || channel.bd_addr == bd_addr);
+        if bd_addr.is_some() {
+            channel.bd_addr = bd_addr;
+        }
+        if reason.is_some() {
+            channel.last_disconnect_reason = reason;
+        }
+        channel.connection_status = status;
+        if !unchanged {
+            changes.push(StateChange::ChannelStatus {
+                conn_id,
+                bd_addr: channel.bd_addr.clone(),
+                connection_status: status,
+                disconnect_reason: reason,
+            });
+        }
+    }
+
+    /// Applies an event and returns what changed. `bd_addr` is the button
+    /// of the event's channel, if known.
+    pub fn apply(&mut self, event: &Event, bd_addr: Option<String>) -> Vec<StateChange> {
+        let mut changes = vec![];
+        match event {
+            Event::GetInfoResponse(GetInfoResponse {
+                bluetooth_controller_state,
+                my_bd_addr,
+                max_pending_connections,
+                max_concurrently_connected_buttons,
+                current_pending_connections,
+                currently_no_space_for_new_connection,
+                bd_addr_of_verified_buttons,
+                ..
+            }) => {
+                self.controller(*bluetooth_controller_state, &mut changes);
+                self.my_bd_addr = Some(my_bd_addr.clone());
+                let verified: BTreeSet<String> = bd_addr_of_verified_buttons
+                    .iter()
+                    .map(|b| b.to_lowercase())
+                    .collect();
+                for bd_addr in self.verified_buttons.difference(&verified) {
+                    changes.push(StateChange::ButtonRemoved {
+                        bd_addr: bd_addr.clone(),
+                    });
+                }
+                for bd_addr in verified.difference(&self.verified_buttons) {
+                    changes.push(StateChange::ButtonVerified {
+                        bd_addr: bd_addr.clone(),
+                    });
+                }
+                self.verified_buttons = verified;
+                if self.current_pending_connections != *current_pending_connections
+                    || self.max_pending_connections != *max_pending_connections
+                {
+                    self.current_pending_connections = *current_pending_connections;
+                    self.max_pending_connections = *max_pending_connections;
+                    changes.push(StateChange::PendingConnections {
+                        current: *current_pending_connections,
+                        max: *max_pending_connections,
+                    });
+                }
+                self.space(
+                    *currently_no_space_for_new_connection,
+                    *max_concurrently_connected_buttons,
+                    &mut changes,
+                );
+            }
+            Event::BluetoothControllerStateChange(BluetoothControllerStateChange { state }) => {
+                self.controller(*state, &mut changes)
+            }
+            Event::NewVerifiedButton(NewVerifiedButton { bd_addr }) => {
+                let bd_addr = bd_addr.to_lowercase();
+                if self.verified_buttons.insert(bd_addr.clone()) {
+                    changes.push(StateChange::ButtonVerified { bd_addr });
+                }
+            }
+            Event::ButtonDeleted(ButtonDeleted { bd_addr, .. }) => {
+                let bd_addr = bd_addr.to_lowercase();
+                if self.verified_buttons.remove(&bd_addr) {
+                    changes.push(StateChange::ButtonRemoved { bd_addr });
+                }
+            }
+            Event::CreateConnectionChannelResponse(CreateConnectionChannelResponse {
+                conn_id,
+                error: CreateConnectionChannelError::NoError,
+                connection_status,
+            }) => self.channel(*conn_id, bd_addr, *connection_status, None, &mut changes),
+            Event::ConnectionStatusChanged(ConnectionStatusChanged {
+                conn_id,
+                connection_status,
+                disconnect_reason,
+            }) => {
+                let reason = match connection_status {
+                    ConnectionStatus::Disconnected => Some(*disconnect_reason),
+                    _ => None,
+                };
+                self.channel(*conn_id, bd_addr, *connection_status, reason, &mut changes)
+            }
+            Event::ConnectionChannelRemoved(ConnectionChannelRemoved {
+                conn_id,
+                removed_reason,
+            }) => {
+                if let Some(channel) = self.channels.remove(conn_id) {
+                    changes.push(StateChange::ChannelRemoved {
+                        conn_id: *conn_id,
+                        bd_addr: channel.bd_addr,
+                        removed_reason: *removed_reason,
+                    });
+                }
+            }
+            Event::NoSpaceForNewConnection(NoSpaceForNewConnection {
+                max_concurrently_connected_buttons,
+            }) => self.space(
+                true,
+                *max_concurrently_connected_buttons as i16,
+                &mut changes,
+            ),
+            Event::GotSpaceForNewConnection(GotSpaceForNewConnection {
+                max_concurrently_connected_buttons,
+            }) => self.space(
+                false,
+                *max_concurrently_connected_buttons as i16,
+                &mut changes,
+            ),
+            _ => {}
+        }
+        changes
+    }
+
+    /// The channels of a button.
+    pub fn channels_of(&self, bd_addr: &str) -> Vec<(u32, &ChannelState)> {
+        self.channels
+            .iter()
+            .filter(|(_, channel)| {
+                channel
+                    .bd_addr
+                    .as_deref()
+                    .is_some_and(|b| b.eq_ignore_ascii_case(bd_addr))
+            })
+            .map(|(conn_id, channel)| (*conn_id, channel))
+            .collect()
+    }
+}
+
+pub type StateChangeClosure = dyn FnMut(&StateChange) + Sync + Send + 'static;
+pub type StateChangeClosureMutex = Box<StateChangeClosure>;
+
+pub fn state_change_handler<F>(f: F) -> StateChangeClosureMutex
+where
+    F: FnMut(&StateChange) + Sync + Send + 'static,
+{
+    Box::new(f)
+}
+
+/// Server state kept up to date from the events of a client.
+///
+/// Register `handler()` with the client and pass its channels so
+/// connection channels are attributed to buttons. `snapshot()` returns the
+/// current state; change handlers see every change as it happens.
+#[derive(Clone, Default)]
+pub struct ServerState {
+    snapshot: Arc<Mutex<ServerSnapshot>>,
+    handlers: Arc<Mutex<Vec<StateChangeClosureMutex>>>,
+    channels: ChannelMap,
+}
+
+impl ServerState {
+    pub fn new() -> ServerState {
+        ServerState::default()
+    }
+
+    pub fn channels(mut self, channels: ChannelMap) -> Self {
+        self.channels = channels;
+        self
+    }
+
+    pub fn register_change_handler(self, handler: StateChangeClosureMutex) -> Self {
+        self.handlers.lock().unwrap().push(handler);
+        self
+    }
+
+    pub fn handler(&self) -> EventClosureMutex {
+        let state = self.clone();
+        event_handler(move |event| state.apply(event))
+    }
+
+    pub fn apply(&self, event: &Event) {
+        let bd_addr = self.channels.bd_addr_of(event);
+        let changes = self.snapshot.lock().unwrap().apply(event, bd_addr);
+        for handler in self.handlers.lock().unwrap().iter_mut() {
+            for change in &changes {
+                handler(change);
+            }
+        }
+    }
+
+    pub fn snapshot(&self) -> ServerSnapshot {
+        self.snapshot.lock().unwrap().clone()
+    }
+
+    pub fn bluetooth_controller_state(&self) -> Option<BluetoothControllerState> {
+        self.snapshot.lock().unwrap().bluetooth_controller_state
+    }
+
+    /// False while the Bluetooth controller of the server is detached or
+    /// resetting, when no button can be reached.
+    pub fn is_controller_available(&self) -> bool {
+        self.bluetooth_controller_state()
+            .is_none_or(|state| state == BluetoothControllerState::Attached)
+    }
+
+    pub fn is_verified(&self, bd_addr: &str) -> bool {
+        self.snapshot
+            .lock()
+            .unwrap()
+            .verified_buttons
+            .contains(&bd_addr.to_lowercase())
+    }
+
+    /// Status of the channel of a button, the best one if there are several.
+    pub fn connection_status(&self, bd_addr: &str) -> Option<ConnectionStatus> {
+        let snapshot = self.snapshot.lock().unwrap();
+        let channels = snapshot.channels_of(bd_addr);
+        [
+            ConnectionStatus::Ready,
+            ConnectionStatus::Connected,
+            ConnectionStatus::Disconnected,
+        ]
+        .iter()
+        .find(|status| {
+            channels
+                .iter()
+                .any(|(_, c)| c.connection_status == **status)
+        })
+        .copied()
+    }
+}
@@ -0,0 +1,407 @@
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use sha2::Sha256;
+use tokio::io::AsyncWriteExt;
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender};
+use tracing::warn;
+
+use super::channels::ChannelMap;
+use super::client::*;
+use super::enums::ClickType;
+use super::events::*;
+use super::json::{event_name, event_to_json};
+
+/// An HTTP endpoint that receives a POST for selected events.
+///
+/// Without `on_click` or `on_event` every gesture is posted once. The body
+/// defaults to the JSON of the event with the button address added; a
+/// custom body may reference any of its fields as `{{field}}`.
+#[derive(Clone, Debug)]
+pub struct Webhook {
+    url: String,
+    buttons: Vec<String>,
+    click_types: Vec<ClickType>,
+    events: Vec<String>,
+    body: Option<String>,
+    headers: Vec<(String, String)>,
+    secret: Option<String>,
+    retries: u32,
+    backoff: Duration,
+}
+
+impl Webhook {
+    pub fn new(url: &str) -> Webhook {
+        Webhook {
+            url: url.to_string(),
+            buttons: vec![],
+            click_types: vec![],
+            events: vec![],
+            body: None,
+            headers: vec![],
+            secret: None,
+            retries: 3,
+            backoff: Duration::from_secs(1),
+        }
+    }
+
+    /// Restricts the webhook to a button. May be given several times.
+    pub fn for_button(mut self, bd_addr: &str) -> Self {
+        self.buttons.push(bd_addr.to_lowercase());
+        self
+    }
+
+    /// Posts gestures of this click type. May be given several times.
+    pub fn on_click(mut self, click_type: ClickType) -> Self {
+        self.click_types.push(click_type);
+        self
+    }
+
+    /// Posts events of this name, e.g. `connection_status_changed`.
+    pub fn on_event(mut self, name: &str) -> Self {
+        self.events.push(name.to_string());
+        self
+    }
+
+    /// Body template, e.g. `{"button": "{{bd_addr}}", "click": "{{click_type}}"}`.
+    ///
+    /// Strings are escaped to be placed inside JSON strings, other values
+    /// are inserted as JSON.
+    pub fn body(mut self, template: &str) -> Self {
+        self.body = Some(template.to_string());
+        self
+    }
+
+    pub fn header(mut self, name: &str, value: &str) -> Self {
+        self.headers.push((name.to_string(), value.to_string()));
+        self
+    }
+
+    /// Signs every body with HMAC-SHA256, sent as
+    /// `X-Flic-Signature: sha256=<hex>`.
+    pub fn secret(mut self, secret: &str) -> Self {
+        self.secret = Some(secret.to_string());
+        self
+    }
+
+    /// Number of retries after a failed delivery and the delay before the
+    /// first one. The delay doubles with every retry.
+    pub fn retries(mut self, retries: u32, backoff: Duration) -> Self {
+        self.retries = retries;
+        self.backoff = backoff;
+        self
+    }
+
+    fn matches(&self, event: &Event, bd_addr: Option<&str>) -> bool {
+        if !self.buttons.is_empty() {
+            match bd_addr {
+                Some(bd_addr) if self.buttons.iter().any(|b| b.eq_ignore_ascii_case(bd_addr)) => {}
+                _ => return false,
+            }
+        }
+        if self.events.is_empty() && self.click_types.is_empty() {
+            return event.gesture().is_some();
+        }
+        if self.events.iter().any(|name| name == event_name(event)) {
+            return true;
+        }
+        match event.gesture() {
+            Some(gesture) => self.click_types.contains(&gesture.click_type),
+            None => false,
+        }
+    }
+
+    fn delivery(&self, payload: &Value) -> Delivery {
+        let body = match &self.body {
+            Some(template) => render(template, payload),
+            None => payload.to_string(),
+        };
+        let mut headers = vec![];
+        if !self
+            .headers
+            .iter()
+            .any(|(name, _)| name.eq_ignore_ascii_case("content-type"))
+        {
+            headers.push(("Content-Type".to_string(), "application/json".to_string()));
+        }
+        headers.extend(self.headers.iter().cloned());
+        if let Some(secret) = &self.secret {
+            headers.push(("X-Flic-Signature".to_string(), signature(secret, &body)));
+        }
+        Delivery {
+            url: self.url.clone(),
+            headers,
+            body,
+            retries: self.retries,
+            backoff_ms: self.backoff.as_millis() as u64,
+        }
+    }
+}
+
+/// `sha256=<hex>` HMAC of a body, as sent in `X-Flic-Signature`.
+pub fn signature(secret: &str, body: &str) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC takes keys of any size");
+    mac.update(body.as_bytes());
+    let hex: String = mac
+        .finalize()
+        .into_bytes()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect();
+    format!("sha256={}", hex)
+}
+
+fn render(template: &str, payload: &Value) -> String {
+    let mut rendered = String::new();
+    let mut rest = template;
+    while let Some(start) = rest.find("{{") {
+        rendered.push_str(&rest[..start]);
+        match rest[start + 2..].find("}}") {
+            Some(end) => {
+                let field = rest[start + 2..start + 2 + end].trim();
+                match &payload[field] {
+                    Value::Null => {}
+                    Value::String(s) => {
+                        let quoted = Value::String(s.clone()).to_string();
+                        rendered.push_str(&quoted[1..quoted.len() - 1]);
+                    }
+                    value => rendered.push_str(&value.to_string()),
+                }
+                rest = &rest[start + 2 + end + 2..];
+            }
+            None => {
+                rendered.push_str(&rest[start..]);
+                rest = "";
+            }
+        }
+    }
+    rendered.push_str(rest);
+    rendered
+}
+
+/// A request to be sent, as stored in the retry queue.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Delivery {
+    pub url: String,
+    pub headers: Vec<(String, String)>,
+    pub body: String,
+    pub retries: u32,
+    pub backoff_ms: u64,
+}
+
+#[derive(Clone)]
+struct Sender {
+    http: reqwest::Client,
+    queue: Option<PathBuf>,
+    queue_lock: Arc<tokio::sync::Mutex<()>>,
+}
+
+impl Sender {
+    async fn post(&self, delivery: &Delivery) -> Result<(), String> {
+        let mut request = self.http.post(&delivery.url).body(delivery.body.clone());
+        for (name, value) in &delivery.headers {
+            request = request.header(name.as_str(), value.as_str());
+        }
+        match request.send().await {
+            Ok(response) if response.status().is_success() => Ok(()),
+            Ok(response) => Err(format!("status {}", response.status())),
+            Err(err) => Err(err.to_string()),
+        }
+    }
+
+    async fn deliver(&self, delivery: Delivery) {
+        let mut backoff = Duration::from_millis(delivery.backoff_ms);
+        let mut attempt = 0;
+        loop {
+            match self.post(&delivery).await {
+                Ok(()) => return,
+                Err(err) if attempt < delivery.retries => {
+                    warn!(url = %delivery.url, error = %err, "webhook failed, retrying");
+                    tokio::time::delay_for(backoff).await;
+                    backoff *= 2;
+                    attempt += 1;
+                }
+                Err(err) => {
+                    warn!(url = %delivery.url, error = %err, "webhook failed");
+                    self.enqueue(&delivery).await;
+                    return;
+                }
+            }
+        }
+    }
+
+    async fn enqueue(&self, delivery: &Delivery) {
+        let path = match &self.queue {
+            Some(path) => path,
+            None => return,
+        };
+        let _lock = self.queue_lock.lock().await;
+        let line = match serde_json::to_string(delivery) {
+            Ok(line) => line + "\n",
+            Err(_) => return,
+        };
+        let file = tokio::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .await;
+        let result = match file {
+            Ok(mut file) => file.write_all(line.as_bytes()).await,
+            Err(err) => Err(err),
+        };
+        if let Err(err) = result {
+            warn!(path = %path.display(), error = %err, "webhook queue not written");
+        }
+    }
+
+    async fn flush(&self) -> usize {
+        let path = match &self.queue {
+            Some(path) => path,
+            None => return 0,
+        };
+        let _lock = self.queue_lock.lock().await;
+        let content = match tokio::fs::read_to_string(path).await {
+            Ok(content) => content,
+            Err(_) => return 0,
+        };
+        let mut pending = String::new();
+        let mut delivered = 0;
+        for line in content.lines() {
+            match serde_json::from_str::<Delivery>(line) {
+                Ok(delivery) => match self.post(&delivery).await {
+                    Ok(()) => delivered += 1,
+                    Err(_) => {
+                        pending.push_str(line);
+                        pending.push('\n');
+                    }
+                },
+                Err(err) => warn!(error = %err, "webhook queue entry dropped"),
+            }
+        }
+        let result = if pending.is_empty() {
+            tokio::fs::remove_file(path).await
+        } else {
+            tokio::fs::write(path, pending).await
+        };
+        if let Err(err) = result {
+            warn!(path = %path.display(), error = %err, "webhook queue not written");
+        }
+        delivered
+    }
+}
+
+/// Posts events to webhooks.
+///
+/// Failed deliveries are retried with backoff. Once the retries of a
+/// delivery are used up it is appended to the queue file given to
+/// `persist_to`, which `run` flushes periodically and which survives
+/// restarts.
+#[derive(Clone)]
+pub struct WebhookDispatcher {
+    hooks: Arc<Vec<Webhook>>,
+    channels: ChannelMap,
+    flush_interval: Duration,
+    sender: Sender,
+    deliveries: UnboundedSender<Delivery>,
+    receiver: Arc<Mutex<Option<UnboundedReceiver<Delivery>>>>,
+}
+
+impl WebhookDispatcher {
+    pub fn new(hooks: Vec<Webhook>) -> WebhookDispatcher {
+        let (deliveries, receiver) = unbounded_channel();
+        WebhookDispatcher {
+            hooks: Arc::new(hooks),
+            channels: ChannelMap::new(),
+            flush_interval: Duration::from_secs(30),
+            sender: Sender {
+                http: reqwest::Client::builder()
+                    .timeout(Duration::from_secs(10))
+                    .build()
+                    .unwrap_or_default(),
+                queue: None,
+                queue_lock: Arc::new(tokio::sync::Mutex::new(())),
+            },
+            deliveries,
+            receiver: Arc::new(Mutex::new(Some(receiver))),
+        }
+    }
+
+    /// Keeps undeliverable requests in a JSON lines file.
+    pub fn persist_to<P: AsRef<Path>>(mut self, path: P) -> Self {
+        self.sender.queue = Some(path.as_ref().to_path_buf());
+        self
+    }
+
+    /// How often `run` retries the queued deliveries.
+    pub fn flush_interval(mut self, interval: Duration) -> Self {
+        self.flush_interval = interval;
+        self
+    }
+
+    /// Resolves the buttons of click events, usually `client.channels()`.
+    pub fn channels(mut self, channels: ChannelMap) -> Self {
+        self.channels = channels;
+        self
+    }
+
+    /// Returns the requests to send for an event.
+    pub fn deliveries(&self, event: &Event) -> Vec<Delivery> {
+        let bd_addr = self.channels.bd_addr_of(event);
+        let mut payload = event_to_json(event);
+        if let Some(bd_addr) = &bd_addr {
+            payload["bd_addr"] = json!(bd_addr);
+        }
+        self.hooks
+            .iter()
+            .filter(|hook| hook.matches(event, bd_addr.as_deref()))
+            .map(|hook| hook.delivery(&payload))
+            .collect()
+    }
+
+    pub fn handler(&self) -> EventClosureMutex {
+        let dispatcher = self.clone();
+        event_handler(move |event| {
+            for delivery in dispatcher.deliveries(event) {
+                let _ = dispatcher.deliveries.send(delivery);
+            }
+        })
+    }
+
+    /// Retries the queued deliveries once and returns how many went through.
+    pub async fn flush_queue(&self) -> usize {
+        self.sender.flush().await
+    }
+
+    /// Sends the deliveries of the handler and flushes the queue
+    /// periodically.
+    ///
+    /// Can only be run once per dispatcher.
+    pub async fn run(&self) {
+        let mut receiver = match self.receiver.lock().unwrap().take() {
+            Some(receiver) => receiver,
+            None => return,
+        };
+        let sender = self.sender.clone();
+        let flush_interval = self.flush_interval;
+        let (stop, mut stopped) = tokio::sync::oneshot::channel::<()>();
+        tokio::spawn(async move {
+            loop {
+                sender.flush().await;
+                tokio::select! {
+                    _ = tokio::time::delay_for(flush_interval) => {}
+                    _ = &mut stopped => break,
+                }
+            }
+        });
+        while let Some(delivery) = receiver.recv().await {
+            let sender = self.sender.clone();
+            tokio::spawn(async move { sender.deliver(delivery).await });
+        }
+        drop(stop);
+    }
+}
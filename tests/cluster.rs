@@ -0,0 +1,227 @@
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use tokio::net::TcpListener;
+use tokio::prelude::*;
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender};
+use tokio::time::delay_for;
+
+use flicbtn::*;
+
+const KITCHEN: &str = "80:e4:da:76:fa:55";
+const HALL: &str = "80:e4:da:76:fa:56";
+const SHARED: &str = "80:e4:da:76:fa:57";
+
+fn bd_addr_bytes(bd_addr: &str) -> Vec<u8> {
+    bd_addr
+        .split(':')
+        .rev()
+        .map(|part| u8::from_str_radix(part, 16).unwrap())
+        .collect()
+}
+
+fn event(opcode: u8, body: Vec<u8>) -> Vec<u8> {
+    let mut packet = vec![opcode];
+    packet.extend(body);
+    frame_packet(&packet)
+}
+
+fn button(opcode: u8, click_type: ClickType) -> Vec<u8> {
+    let mut body = 1u32.to_le_bytes().to_vec();
+    body.extend(&[click_type.into(), 0]);
+    body.extend(&0i32.to_le_bytes());
+    event(opcode, body)
+}
+
+/// A flicd with some verified buttons. Reports every command it receives
+/// and sends the events it is given.
+async fn fake_flicd(
+    port: u16,
+    verified: &'static [&'static str],
+) -> (UnboundedReceiver<Command>, UnboundedSender<Vec<u8>>) {
+    let mut listener = TcpListener::bind(("127.0.0.1", port)).await.unwrap();
+    let (commands, received) = unbounded_channel();
+    let (events, mut to_send) = unbounded_channel::<Vec<u8>>();
+    let answers = events.clone();
+    tokio::spawn(async move {
+        let (stream, _) = listener.accept().await.unwrap();
+        let (mut reader, mut writer) = tokio::io::split(stream);
+        tokio::spawn(async move {
+            while let Some(event) = to_send.recv().await {
+                writer.write_all(&event).await.unwrap();
+            }
+        });
+        while let Ok(packet) = read_packet(&mut reader).await {
+            let command = Command::from_packet(&packet).unwrap();
+            if command == Command::GetInfo {
+                let mut body = vec![2];
+                body.extend(bd_addr_bytes("00:00:00:00:00:01"));
+                body.extend(&[0, 2, 10, 0, 0, 0]);
+                body.extend(&(verified.len() as u16).to_le_bytes());
+                for bd_addr in verified {
+                    body.extend(bd_addr_bytes(bd_addr));
+                }
+                let _ = answers.send(event(9, body));
+            }
+            let _ = commands.send(command);
+        }
+    });
+    (received, events)
+}
+
+fn received(commands: &mut UnboundedReceiver<Command>) -> Vec<Command> {
+    let mut received = vec![];
+    while let Ok(command) = commands.try_recv() {
+        received.push(command);
+    }
+    received
+}
+
+async fn listen(cluster: FlicCluster) -> Arc<FlicCluster> {
+    let cluster = Arc::new(cluster);
+    let listener = cluster.clone();
+    tokio::spawn(async move { listener.listen().await });
+    delay_for(Duration::from_millis(100)).await;
+    cluster
+}
+
+fn connect(bd_addr: &str) -> Command {
+    Command::CreateConnectionChannel {
+        conn_id: 1,
+        bd_addr: bd_addr.to_string(),
+        latency_mode: LatencyMode::NormalLatency,
+        auto_disconnect_time: 511,
+    }
+}
+
+#[tokio::test]
+async fn routes_commands_to_owning_server() {
+    let (mut kitchen, _) = fake_flicd(18891, &[KITCHEN, SHARED]).await;
+    let (mut hall, _) = fake_flicd(18892, &[HALL, SHARED]).await;
+    let cluster = FlicCluster::new(&["127.0.0.1:18891", "127.0.0.1:18892"])
+        .await
+        .unwrap();
+    let cluster = listen(cluster).await;
+    assert_eq!(received(&mut kitchen), vec![Command::GetInfo]);
+    assert_eq!(received(&mut hall), vec![Command::GetInfo]);
+    assert_eq!(cluster.servers_of(KITCHEN), vec!["127.0.0.1:18891"]);
+    assert_eq!(
+        cluster.servers_of(&SHARED.to_uppercase()),
+        vec!["127.0.0.1:18891", "127.0.0.1:18892"]
+    );
+
+    cluster.submit(connect(KITCHEN)).await;
+    cluster
+        .submit(Command::RemoveConnectionChannel { conn_id: 1 })
+        .await;
+    let button_info = Command::GetButtonInfo {
+        bd_addr: HALL.to_string(),
+    };
+    cluster.submit(button_info.clone()).await;
+    delay_for(Duration::from_millis(100)).await;
+    assert_eq!(
+        received(&mut kitchen),
+        vec![
+            connect(KITCHEN),
+            Command::RemoveConnectionChannel { conn_id: 1 }
+        ]
+    );
+    assert_eq!(received(&mut hall), vec![button_info]);
+
+    // shared and unknown buttons go everywhere
+    cluster.submit(connect(SHARED)).await;
+    let unknown = Command::ForceDisconnect {
+        bd_addr: "80:e4:da:76:fa:58".to_string(),
+    };
+    cluster.submit(unknown.clone()).await;
+    delay_for(Duration::from_millis(100)).await;
+    assert_eq!(
+        received(&mut kitchen),
+        vec![connect(SHARED), unknown.clone()]
+    );
+    assert_eq!(received(&mut hall), vec![connect(SHARED), unknown]);
+}
+
+#[tokio::test]
+async fn delivers_clicks_once() {
+    let (_, kitchen) = fake_flicd(18893, &[SHARED]).await;
+    let (_, hall) = fake_flicd(18894, &[SHARED]).await;
+    let clicks = Arc::new(Mutex::new(vec![]));
+    let handler = {
+        let clicks = clicks.clone();
+        cluster_handler(move |server, event| {
+            if let Some(button) = event.button() {
+                let server = server.to_string();
+                clicks
+                    .lock()
+                    .unwrap()
+                    .push((server, button.kind, button.click_type));
+            }
+        })
+    };
+    let cluster = FlicCluster::new(&["127.0.0.1:18893", "127.0.0.1:18894"])
+        .await
+        .unwrap()
+        .dedup_window(Duration::from_millis(300))
+        .register_event_handler(handler)
+        .await;
+    let cluster = listen(cluster).await;
+    cluster.submit(connect(SHARED)).await;
+    delay_for(Duration::from_millis(100)).await;
+
+    let press = [
+        button(4, ClickType::ButtonDown),
+        button(4, ClickType::ButtonUp),
+        button(5, ClickType::ButtonClick),
+        button(7, ClickType::ButtonSingleClick),
+    ];
+    for event in press.iter() {
+        kitchen.send(event.clone()).unwrap();
+    }
+    delay_for(Duration::from_millis(50)).await;
+    for event in press.iter() {
+        hall.send(event.clone()).unwrap();
+    }
+    delay_for(Duration::from_millis(50)).await;
+    let kitchen_press = vec![
+        (
+            "127.0.0.1:18893".to_string(),
+            ButtonEventKind::UpOrDown,
+            ClickType::ButtonDown,
+        ),
+        (
+            "127.0.0.1:18893".to_string(),
+            ButtonEventKind::UpOrDown,
+            ClickType::ButtonUp,
+        ),
+        (
+            "127.0.0.1:18893".to_string(),
+            ButtonEventKind::ClickOrHold,
+            ClickType::ButtonClick,
+        ),
+        (
+            "127.0.0.1:18893".to_string(),
+            ButtonEventKind::SingleOrDoubleClickOrHold,
+            ClickType::ButtonSingleClick,
+        ),
+    ];
+    assert_eq!(*clicks.lock().unwrap(), kitchen_press);
+
+    // the same server clicking again isn't a duplicate
+    kitchen.send(press[2].clone()).unwrap();
+    delay_for(Duration::from_millis(50)).await;
+    assert_eq!(clicks.lock().unwrap().len(), 5);
+
+    // nor is a click after the window
+    delay_for(Duration::from_millis(300)).await;
+    hall.send(press[2].clone()).unwrap();
+    delay_for(Duration::from_millis(50)).await;
+    assert_eq!(
+        clicks.lock().unwrap().last().unwrap(),
+        &(
+            "127.0.0.1:18894".to_string(),
+            ButtonEventKind::ClickOrHold,
+            ClickType::ButtonClick,
+        )
+    );
+}
@@ -0,0 +1,81 @@
+use flicbtn::*;
+
+const BUTTON: &str = "80:e4:da:76:fa:55";
+
+fn every_command() -> Vec<Command> {
+    vec![
+        Command::GetInfo,
+        Command::CreateScanner { scan_id: 1 },
+        Command::RemoveScanner { scan_id: 2 },
+        Command::CreateConnectionChannel {
+            conn_id: 3,
+            bd_addr: BUTTON.to_string(),
+            latency_mode: LatencyMode::LowLatency,
+            auto_disconnect_time: 300,
+        },
+        Command::RemoveConnectionChannel { conn_id: 4 },
+        Command::ForceDisconnect {
+            bd_addr: BUTTON.to_string(),
+        },
+        Command::ChangeModeParameters {
+            conn_id: 5,
+            latency_mode: LatencyMode::HighLatency,
+            auto_disconnect_time: -1,
+        },
+        Command::Ping {
+            ping_id: 0x0102_0304,
+        },
+        Command::GetButtonInfo {
+            bd_addr: BUTTON.to_string(),
+        },
+        Command::CreateScanWizard { scan_wizard_id: 6 },
+        Command::CancelScanWizard { scan_wizard_id: 7 },
+        Command::DeleteButton {
+            bd_addr: BUTTON.to_string(),
+        },
+        Command::CreateBatteryStatusListener {
+            listener_id: 8,
+            bd_addr: BUTTON.to_string(),
+        },
+        Command::RemoveBatteryStatusListener { listener_id: 9 },
+    ]
+}
+
+#[test]
+fn every_command_round_trips() {
+    let mut mapper = CommandToByteMapper::new();
+    let commands = every_command();
+    let opcodes: Vec<u8> = commands.iter().map(Command::opcode).collect();
+    assert_eq!(opcodes, (0..=13).collect::<Vec<u8>>());
+
+    for command in commands {
+        let bytes: Vec<u8> = mapper.map(command.clone()).collect();
+        let len = bytes[0] as usize | (bytes[1] as usize) << 8;
+        assert_eq!(len, bytes.len() - 2, "{:?}", command);
+        assert_eq!(bytes[2], command.opcode(), "{:?}", command);
+        assert_eq!(Command::from_packet(&bytes[2..]), Some(command));
+    }
+}
+
+#[test]
+fn rejects_truncated_and_unknown_packets() {
+    let mut mapper = CommandToByteMapper::new();
+    for command in every_command().into_iter().skip(1) {
+        let bytes: Vec<u8> = mapper.map(command.clone()).collect();
+        let truncated = &bytes[2..bytes.len() - 1];
+        assert_eq!(Command::from_packet(truncated), None, "{:?}", command);
+    }
+    assert_eq!(Command::from_packet(&[]), None);
+    assert_eq!(Command::from_packet(&[14, 0, 0, 0, 0]), None);
+}
+
+#[test]
+fn recognizes_bd_addrs() {
+    assert!(is_bd_addr(BUTTON));
+    assert!(is_bd_addr(&BUTTON.to_uppercase()));
+    assert!(!is_bd_addr("kitchen"));
+    assert!(!is_bd_addr("80:e4:da:76:fa"));
+    assert!(!is_bd_addr("80:e4:da:76:fa:55:00"));
+    assert!(!is_bd_addr("80:e4:da:76:fa:5"));
+    assert!(!is_bd_addr("80:e4:da:76:fa:zz"));
+}
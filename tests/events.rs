@@ -0,0 +1,151 @@
+use flicbtn::*;
+
+fn decode(bytes: &[u8]) -> Vec<Event> {
+    let mut mapper = ByteToEventMapper::new();
+    bytes
+        .iter()
+        .filter_map(|b| match mapper.map(*b) {
+            EventResult::Some(event) => Some(event),
+            _ => None,
+        })
+        .collect()
+}
+
+fn button_packet(opcode: u8, click_type: u8) -> Vec<u8> {
+    vec![11, 0, opcode, 7, 0, 0, 0, click_type, 0, 0, 0, 0, 0]
+}
+
+#[test]
+fn decodes_every_button_opcode_into_one_variant() {
+    let mut bytes = button_packet(4, ClickType::ButtonDown.into());
+    bytes.extend(button_packet(5, ClickType::ButtonClick.into()));
+    bytes.extend(button_packet(6, ClickType::ButtonDoubleClick.into()));
+    bytes.extend(button_packet(7, ClickType::ButtonDoubleClick.into()));
+    let events = decode(&bytes);
+
+    let kinds: Vec<ButtonEventKind> = events
+        .iter()
+        .filter_map(|e| e.button().map(|b| b.kind))
+        .collect();
+    assert_eq!(
+        kinds,
+        vec![
+            ButtonEventKind::UpOrDown,
+            ButtonEventKind::ClickOrHold,
+            ButtonEventKind::SingleOrDoubleClick,
+            ButtonEventKind::SingleOrDoubleClickOrHold,
+        ]
+    );
+    assert_eq!(
+        events.iter().map(Event::name).collect::<Vec<_>>(),
+        vec![
+            "button_up_or_down",
+            "button_click_or_hold",
+            "button_single_or_double_click",
+            "button_single_or_double_click_or_hold",
+        ]
+    );
+    assert!(events.iter().all(|e| e.conn_id() == Some(7)));
+    assert_eq!(
+        events.iter().map(Event::is_click).collect::<Vec<_>>(),
+        vec![false, true, true, true]
+    );
+    // the double click counts once
+    assert_eq!(events.iter().filter_map(Event::gesture).count(), 3);
+}
+
+#[test]
+fn helpers_read_ids_and_addresses() {
+    let deleted = Event::ButtonDeleted(ButtonDeleted {
+        bd_addr: "80:e4:da:76:fa:55".to_string(),
+        deleted_by_this_client: true,
+    });
+    assert_eq!(deleted.bd_addr(), Some("80:e4:da:76:fa:55"));
+    assert_eq!(deleted.conn_id(), None);
+
+    let removed = Event::ConnectionChannelRemoved(ConnectionChannelRemoved {
+        conn_id: 3,
+        removed_reason: RemovedReason::RemovedByThisClient,
+    });
+    assert_eq!(removed.conn_id(), Some(3));
+    assert_eq!(removed.bd_addr(), None);
+    assert!(!removed.is_click());
+    assert_eq!(
+        Event::PingResponse(PingResponse { ping_id: 1 }).button(),
+        None
+    );
+}
+
+#[test]
+fn keeps_decoding_after_unknown_opcodes_and_values() {
+    // an opcode from a newer flicd, then a ping response with an appended
+    // field, then a removal for a reason this version does not know
+    let mut bytes = vec![4, 0, 42, 1, 2, 3];
+    bytes.extend(vec![7, 0, 13, 1, 0, 0, 0, 0xaa, 0xbb]);
+    bytes.extend(vec![6, 0, 3, 2, 0, 0, 0, 99]);
+    assert_eq!(
+        decode(&bytes),
+        vec![
+            Event::Unknown {
+                opcode: 42,
+                payload: vec![1, 2, 3],
+            },
+            Event::PingResponse(PingResponse { ping_id: 1 }),
+            Event::ConnectionChannelRemoved(ConnectionChannelRemoved {
+                conn_id: 2,
+                removed_reason: RemovedReason::Other(99),
+            }),
+        ]
+    );
+    assert_eq!(u8::from(RemovedReason::Other(99)), 99);
+    assert_eq!(
+        RemovedReason::from(1),
+        RemovedReason::ForceDisconnectedByThisClient
+    );
+}
+
+fn padded(s: &str) -> Vec<u8> {
+    let mut bytes = vec![s.len() as u8];
+    bytes.extend(s.as_bytes());
+    bytes.resize(17, 0);
+    bytes
+}
+
+fn packet(opcode: u8, payload: Vec<u8>) -> Vec<u8> {
+    let len = payload.len() as u16 + 1;
+    let mut bytes = vec![len as u8, (len >> 8) as u8, opcode];
+    bytes.extend(payload);
+    bytes
+}
+
+#[test]
+fn decodes_button_info_of_old_and_new_servers() {
+    let mut payload = vec![0x55, 0xfa, 0x76, 0xda, 0xe4, 0x80];
+    payload.extend(1..=16);
+    payload.extend(padded("black"));
+    payload.extend(padded("AB12-C34567"));
+    let old = packet(14, payload.clone());
+    payload.push(2);
+    payload.extend(&9u32.to_le_bytes());
+    let new = packet(14, payload);
+
+    let events = decode(&[old, new].concat());
+    let infos: Vec<&GetButtonInfoResponse> = events
+        .iter()
+        .filter_map(|e| match e {
+            Event::GetButtonInfoResponse(info) => Some(info),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(infos.len(), 2);
+    for info in &infos {
+        assert_eq!(info.bd_addr, "80:e4:da:76:fa:55");
+        assert_eq!(info.color.as_deref(), Some("black"));
+        assert_eq!(info.serial_number.as_deref(), Some("AB12-C34567"));
+    }
+    assert_eq!(infos[0].flic_version, None);
+    assert_eq!(infos[0].model(), None);
+    assert_eq!(infos[1].flic_version, Some(2));
+    assert_eq!(infos[1].firmware_version, Some(9));
+    assert_eq!(infos[1].model(), Some(ButtonModel::Flic2));
+}
@@ -0,0 +1,65 @@
+#![cfg(feature = "rules")]
+
+use std::fs;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use tokio::time::delay_for;
+
+use flicbtn::*;
+
+fn temp_file(name: &str) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("flicbtn-exec-{}-{}", std::process::id(), name));
+    let _ = fs::remove_file(&path);
+    path
+}
+
+fn lines(path: &PathBuf) -> Vec<String> {
+    fs::read_to_string(path)
+        .unwrap_or_default()
+        .lines()
+        .map(str::to_string)
+        .collect()
+}
+
+#[tokio::test]
+async fn passes_environment() {
+    let out = temp_file("env");
+    let runner = CommandRunner::default();
+    runner.run(
+        format!("echo \"$FLIC_BDADDR $FLIC_CLICK_TYPE\" > {}", out.display()),
+        vec![
+            ("FLIC_BDADDR".to_string(), "80:e4:da:76:fa:55".to_string()),
+            ("FLIC_CLICK_TYPE".to_string(), "ButtonHold".to_string()),
+        ],
+    );
+    delay_for(Duration::from_millis(500)).await;
+    assert_eq!(lines(&out), vec!["80:e4:da:76:fa:55 ButtonHold"]);
+    fs::remove_file(&out).unwrap();
+}
+
+#[tokio::test]
+async fn kills_commands_after_timeout() {
+    let out = temp_file("timeout");
+    let runner = CommandRunner::new(4, Duration::from_millis(100));
+    runner.run(format!("sleep 0.5; echo late > {}", out.display()), vec![]);
+    delay_for(Duration::from_millis(1000)).await;
+    assert!(!out.exists());
+}
+
+#[tokio::test]
+async fn limits_concurrent_commands() {
+    let out = temp_file("concurrency");
+    let runner = CommandRunner::new(1, Duration::from_secs(5));
+    for _ in 0..2 {
+        runner.run(
+            format!("echo started >> {}; sleep 0.5", out.display()),
+            vec![],
+        );
+    }
+    delay_for(Duration::from_millis(250)).await;
+    assert_eq!(lines(&out).len(), 1);
+    delay_for(Duration::from_millis(1250)).await;
+    assert_eq!(lines(&out).len(), 2);
+    fs::remove_file(&out).unwrap();
+}
@@ -0,0 +1,162 @@
+#![cfg(feature = "http")]
+
+use std::time::Duration;
+
+use futures::StreamExt;
+use hyper::{Body, Request};
+use serde_json::json;
+use tokio::net::TcpStream;
+use tokio::prelude::*;
+use tokio::time::{delay_for, timeout};
+
+use flicbtn::*;
+
+const KITCHEN: &str = "80:e4:da:76:fa:55";
+const HALL: &str = "80:e4:da:76:fa:56";
+
+fn verified(bd_addr: &str) -> Event {
+    Event::NewVerifiedButton(NewVerifiedButton {
+        bd_addr: bd_addr.to_string(),
+    })
+}
+
+fn click(conn_id: u32) -> Event {
+    Event::Button(ButtonEvent {
+        kind: ButtonEventKind::SingleOrDoubleClickOrHold,
+        conn_id,
+        click_type: ClickType::ButtonSingleClick,
+        was_queued: false,
+        time_diff: 0,
+    })
+}
+
+fn feed() -> EventFeed {
+    let channels = ChannelMap::new();
+    channels.track_command(&Command::CreateConnectionChannel {
+        conn_id: 1,
+        bd_addr: KITCHEN.to_string(),
+        latency_mode: LatencyMode::NormalLatency,
+        auto_disconnect_time: 511,
+    });
+    EventFeed::new().history(3).channels(channels)
+}
+
+#[test]
+fn parses_filters() {
+    assert_eq!(FeedFilter::from_query(None), FeedFilter::default());
+    assert_eq!(
+        FeedFilter::from_query(Some(
+            "bd_addr=80:E4:DA:76:FA:55,,80:e4:da:76:fa:56&event=new_verified_button&replay=5"
+        )),
+        FeedFilter {
+            bd_addrs: vec![KITCHEN.to_string(), HALL.to_string()],
+            events: vec!["new_verified_button".to_string()],
+            replay: 5,
+        }
+    );
+    assert_eq!(FeedFilter::from_query(Some("replay=all")).replay, 0);
+}
+
+#[test]
+fn matches_buttons_and_events() {
+    let kitchen = json!({ "event": "new_verified_button", "bd_addr": KITCHEN });
+    let server = json!({ "event": "ping_response", "ping_id": 1 });
+    assert!(FeedFilter::default().matches(&kitchen));
+    assert!(FeedFilter::default().matches(&server));
+
+    let buttons = FeedFilter::from_query(Some("bd_addr=80:E4:DA:76:FA:55"));
+    assert!(buttons.matches(&kitchen));
+    assert!(!buttons.matches(&server));
+    assert!(!FeedFilter::from_query(Some("bd_addr=80:e4:da:76:fa:56")).matches(&kitchen));
+
+    let events = FeedFilter::from_query(Some("event=ping_response,button_deleted"));
+    assert!(events.matches(&server));
+    assert!(!events.matches(&kitchen));
+    let both = FeedFilter::from_query(Some("event=ping_response&bd_addr=80:e4:da:76:fa:55"));
+    assert!(!both.matches(&server));
+}
+
+async fn next_message(body: &mut Body) -> String {
+    let chunk = timeout(Duration::from_secs(5), body.next())
+        .await
+        .expect("no message sent")
+        .unwrap()
+        .unwrap();
+    String::from_utf8(chunk.to_vec()).unwrap()
+}
+
+#[tokio::test]
+async fn replays_last_matching_events() {
+    let feed = feed();
+    let mut handler = feed.handler();
+    for event in [verified(HALL), click(1), verified(KITCHEN), verified(HALL)].iter() {
+        handler(event);
+    }
+
+    // three events are kept, two of them of the kitchen button
+    let request = Request::get(format!("/events?bd_addr={}&replay=5", KITCHEN))
+        .body(Body::empty())
+        .unwrap();
+    let response = feed.respond(request);
+    assert_eq!(response.headers()["content-type"], "text/event-stream");
+    let mut body = response.into_body();
+    assert_eq!(
+        next_message(&mut body).await,
+        format!(
+            "event: button_single_or_double_click_or_hold\ndata: {}\n\n",
+            json!({
+                "event": "button_single_or_double_click_or_hold",
+                "conn_id": 1,
+                "click_type": "ButtonSingleClick",
+                "was_queued": false,
+                "time_diff": 0,
+                "bd_addr": KITCHEN,
+            })
+        )
+    );
+    assert!(next_message(&mut body)
+        .await
+        .starts_with("event: new_verified_button\n"));
+
+    let request = Request::get("/events?replay=1")
+        .body(Body::empty())
+        .unwrap();
+    let mut body = feed.respond(request).into_body();
+    assert!(next_message(&mut body).await.contains(HALL));
+}
+
+#[tokio::test]
+async fn streams_server_sent_events() {
+    let feed = feed();
+    let server = feed.clone();
+    tokio::spawn(async move { server.serve(([127, 0, 0, 1], 18881).into()).await });
+    delay_for(Duration::from_millis(100)).await;
+
+    let mut stream = TcpStream::connect("127.0.0.1:18881").await.unwrap();
+    stream
+        .write_all(b"GET /events?event=new_verified_button HTTP/1.1\r\nHost: localhost\r\n\r\n")
+        .await
+        .unwrap();
+    let mut received = String::new();
+    let mut buf = [0; 1024];
+    let n = stream.read(&mut buf).await.unwrap();
+    received.push_str(&String::from_utf8_lossy(&buf[..n]));
+    assert!(received.starts_with("HTTP/1.1 200 OK\r\n"), "{}", received);
+    assert!(received.contains("content-type: text/event-stream"));
+
+    feed.publish(&click(1));
+    feed.publish(&verified(HALL));
+    timeout(Duration::from_secs(5), async {
+        while !received.contains("data: ") {
+            let n = stream.read(&mut buf).await.unwrap();
+            received.push_str(&String::from_utf8_lossy(&buf[..n]));
+        }
+    })
+    .await
+    .expect("no event streamed");
+    assert!(received.contains(&format!(
+        "event: new_verified_button\ndata: {}\n\n",
+        json!({ "event": "new_verified_button", "bd_addr": HALL })
+    )));
+    assert!(!received.contains("button_single_or_double_click_or_hold"));
+}
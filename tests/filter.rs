@@ -0,0 +1,93 @@
+use std::time::{Duration, Instant};
+
+use flicbtn::*;
+
+const BUTTON: &str = "80:e4:da:76:fa:55";
+
+fn button(kind: ButtonEventKind, click_type: ClickType, was_queued: bool) -> Event {
+    Event::Button(ButtonEvent {
+        kind,
+        conn_id: 1,
+        click_type,
+        was_queued,
+        time_diff: 0,
+    })
+}
+
+/// The button events flicd sends for one double click.
+fn double_click() -> Vec<Event> {
+    vec![
+        button(ButtonEventKind::UpOrDown, ClickType::ButtonDown, false),
+        button(ButtonEventKind::UpOrDown, ClickType::ButtonUp, false),
+        button(ButtonEventKind::UpOrDown, ClickType::ButtonDown, false),
+        button(ButtonEventKind::UpOrDown, ClickType::ButtonUp, false),
+        button(ButtonEventKind::ClickOrHold, ClickType::ButtonClick, false),
+        button(
+            ButtonEventKind::SingleOrDoubleClick,
+            ClickType::ButtonDoubleClick,
+            false,
+        ),
+        button(
+            ButtonEventKind::SingleOrDoubleClickOrHold,
+            ClickType::ButtonDoubleClick,
+            false,
+        ),
+    ]
+}
+
+#[test]
+fn selects_gestures_of_a_button() {
+    let filter = EventFilter::new()
+        .for_button(BUTTON)
+        .of_type(ClickType::ButtonDoubleClick);
+    let passed = double_click()
+        .iter()
+        .filter(|e| filter.matches(e, Some(BUTTON)))
+        .count();
+    assert_eq!(passed, 1);
+    assert!(!filter.matches(&double_click()[6], Some("80:e4:da:76:fa:56")));
+    assert!(!filter.matches(&double_click()[6], None));
+
+    let clicks = EventFilter::new().clicks_only();
+    let types: Vec<ClickType> = double_click()
+        .iter()
+        .filter(|e| clicks.matches(e, None))
+        .filter_map(|e| e.button().map(|b| b.click_type))
+        .collect();
+    assert_eq!(
+        types,
+        vec![ClickType::ButtonClick, ClickType::ButtonDoubleClick]
+    );
+
+    let fresh = EventFilter::new().exclude_queued();
+    assert!(!fresh.matches(
+        &button(ButtonEventKind::UpOrDown, ClickType::ButtonDown, true),
+        None
+    ));
+    assert!(fresh.matches(&double_click()[0], None));
+    assert!(fresh.matches(&Event::PingResponse(PingResponse { ping_id: 1 }), None));
+}
+
+#[test]
+fn debounces_and_throttles_bursts() {
+    let click = button(ButtonEventKind::ClickOrHold, ClickType::ButtonClick, false);
+    let start = Instant::now();
+    let at = |ms: u64| start + Duration::from_millis(ms);
+    let times = [0, 100, 200, 300, 600, 700];
+
+    let mut debounced = EventFilter::new().debounce(Duration::from_millis(250));
+    let passed: Vec<u64> = times
+        .iter()
+        .copied()
+        .filter(|ms| debounced.accept(&click, None, at(*ms)))
+        .collect();
+    assert_eq!(passed, vec![0, 600]);
+
+    let mut throttled = EventFilter::new().throttle(Duration::from_millis(250));
+    let passed: Vec<u64> = times
+        .iter()
+        .copied()
+        .filter(|ms| throttled.accept(&click, None, at(*ms)))
+        .collect();
+    assert_eq!(passed, vec![0, 300, 600]);
+}
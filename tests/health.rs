@@ -0,0 +1,81 @@
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use tokio::io::AsyncWriteExt;
+use tokio::net::TcpListener;
+use tokio::time::{delay_for, timeout};
+
+use flicbtn::*;
+
+/// A flicd answering pings, or swallowing them when `answer` is false.
+async fn server(addr: &str, answer: bool) {
+    let mut listener = TcpListener::bind(addr).await.unwrap();
+    tokio::spawn(async move {
+        let (mut stream, _) = listener.accept().await.unwrap();
+        while let Ok(packet) = read_packet(&mut stream).await {
+            if answer && packet[0] == 7 {
+                let mut response = vec![13];
+                response.extend_from_slice(&packet[1..5]);
+                stream.write_all(&frame_packet(&response)).await.unwrap();
+            }
+        }
+    });
+}
+
+#[tokio::test]
+async fn measures_round_trips() {
+    server("127.0.0.1:18861", true).await;
+    let client = FlicClient::new("127.0.0.1:18861")
+        .await
+        .unwrap()
+        .heartbeat(Duration::from_millis(50), 5);
+    let client = Arc::new(client);
+    let listener = {
+        let client = client.clone();
+        tokio::spawn(async move { client.listen().await })
+    };
+    delay_for(Duration::from_millis(400)).await;
+
+    let health = client.health();
+    assert!(health.alive, "{:?}", health);
+    assert!(health.pings_sent >= 3, "{:?}", health);
+    assert!(health.missed <= 1, "{:?}", health);
+    assert!(health.last_rtt.is_some() && health.avg_rtt.is_some());
+    client.stop().await;
+    listener.await.unwrap();
+}
+
+#[tokio::test]
+async fn declares_silent_server_dead() {
+    server("127.0.0.1:18862", false).await;
+    let reported = Arc::new(Mutex::new(vec![]));
+    let client = {
+        let reported = reported.clone();
+        FlicClient::new("127.0.0.1:18862")
+            .await
+            .unwrap()
+            .register_health_handler(health_handler(move |health| {
+                reported.lock().unwrap().push(health.clone());
+            }))
+            .await
+            .heartbeat(Duration::from_millis(20), 3)
+    };
+
+    // listen returns once the server is dead
+    timeout(Duration::from_secs(2), client.listen())
+        .await
+        .unwrap();
+    let health = client.health();
+    assert!(!health.alive);
+    assert_eq!(health.missed, 3);
+    assert_eq!(health.last_rtt, None);
+    assert_eq!(*reported.lock().unwrap(), vec![health]);
+}
+
+#[tokio::test]
+async fn healthy_without_heartbeat() {
+    server("127.0.0.1:18863", false).await;
+    let client = FlicClient::new("127.0.0.1:18863").await.unwrap();
+    assert_eq!(client.health(), Health::default());
+    assert!(client.health().alive);
+}
@@ -0,0 +1,122 @@
+#![cfg(feature = "mqtt")]
+
+use serde_json::{json, Value};
+
+use flicbtn::homeassistant::{discovery, removal};
+use flicbtn::*;
+
+const BUTTON: &str = "80:e4:da:76:fa:55";
+
+fn info() -> GetButtonInfoResponse {
+    GetButtonInfoResponse {
+        bd_addr: BUTTON.to_string(),
+        uuid: "0102030405060708090a0b0c0d0e0f10".to_string(),
+        color: Some("black".to_string()),
+        serial_number: Some("AB12-C34567".to_string()),
+        flic_version: Some(2),
+        firmware_version: Some(9),
+    }
+}
+
+fn topics(publications: &[Publication]) -> Vec<&str> {
+    publications.iter().map(|p| &p.topic[..]).collect()
+}
+
+const TOPICS: [&str; 8] = [
+    "homeassistant/device_automation/flic_80e4da76fa55/down/config",
+    "homeassistant/device_automation/flic_80e4da76fa55/up/config",
+    "homeassistant/device_automation/flic_80e4da76fa55/click/config",
+    "homeassistant/device_automation/flic_80e4da76fa55/single/config",
+    "homeassistant/device_automation/flic_80e4da76fa55/double/config",
+    "homeassistant/device_automation/flic_80e4da76fa55/hold/config",
+    "homeassistant/sensor/flic_80e4da76fa55/battery/config",
+    "homeassistant/binary_sensor/flic_80e4da76fa55/connectivity/config",
+];
+
+#[test]
+fn announces_triggers_and_sensors() {
+    let publications = discovery("homeassistant", "flic", &info());
+    assert_eq!(topics(&publications), TOPICS);
+    assert!(publications.iter().all(|p| p.retain));
+    let configs: Vec<Value> = publications
+        .iter()
+        .map(|p| serde_json::from_slice(&p.payload).unwrap())
+        .collect();
+
+    let device = json!({
+        "identifiers": ["flic_80e4da76fa55"],
+        "connections": [["bluetooth", BUTTON]],
+        "name": "Flic AB12-C34567",
+        "manufacturer": "Shortcut Labs",
+        "model": "Flic 2 (black)",
+        "sw_version": "9",
+        "serial_number": "AB12-C34567",
+    });
+    assert!(configs.iter().all(|config| config["device"] == device));
+
+    assert_eq!(
+        configs[4],
+        json!({
+            "automation_type": "trigger",
+            "topic": "flic/80:e4:da:76:fa:55/action",
+            "payload": "double",
+            "type": "button_double_press",
+            "subtype": "button_1",
+            "device": device,
+        })
+    );
+
+    let battery = &configs[6];
+    assert_eq!(battery["unique_id"], "flic_80e4da76fa55_battery");
+    assert_eq!(battery["name"], "Flic AB12-C34567 Battery");
+    assert_eq!(battery["state_topic"], "flic/80:e4:da:76:fa:55/battery");
+    assert_eq!(battery["availability_mode"], "all");
+    assert_eq!(battery["availability"][0]["topic"], "flic/server/status");
+    assert_eq!(
+        battery["availability"][1]["topic"],
+        "flic/80:e4:da:76:fa:55/battery"
+    );
+
+    let connectivity = &configs[7];
+    assert_eq!(connectivity["unique_id"], "flic_80e4da76fa55_connectivity");
+    assert_eq!(
+        connectivity["state_topic"],
+        "flic/80:e4:da:76:fa:55/connection"
+    );
+    assert_eq!(
+        connectivity["availability"][0]["topic"],
+        "flic/server/status"
+    );
+}
+
+#[test]
+fn names_buttons_without_info() {
+    let info = GetButtonInfoResponse {
+        color: None,
+        serial_number: None,
+        flic_version: None,
+        firmware_version: None,
+        ..info()
+    };
+    let publications = discovery("homeassistant", "flic", &info);
+    let config: Value = serde_json::from_slice(&publications[0].payload).unwrap();
+    assert_eq!(
+        config["device"],
+        json!({
+            "identifiers": ["flic_80e4da76fa55"],
+            "connections": [["bluetooth", BUTTON]],
+            "name": "Flic 80:e4:da:76:fa:55",
+            "manufacturer": "Shortcut Labs",
+            "model": "Flic",
+        })
+    );
+}
+
+#[test]
+fn removes_every_config() {
+    let publications = removal("homeassistant", BUTTON);
+    assert_eq!(topics(&publications), TOPICS);
+    assert!(publications
+        .iter()
+        .all(|p| p.retain && p.payload.is_empty()));
+}
@@ -0,0 +1,247 @@
+#![cfg(feature = "http")]
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use hyper::{Body, Method, Request, StatusCode};
+use serde_json::{json, Value};
+use tokio::net::TcpListener;
+use tokio::prelude::*;
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver};
+
+use flicbtn::*;
+
+const VERIFIED: &str = "80:e4:da:76:fa:55";
+/// A button flicd never answers for.
+const SILENT: &str = "80:e4:da:76:fa:56";
+
+fn bd_addr_bytes(bd_addr: &str) -> Vec<u8> {
+    bd_addr
+        .split(':')
+        .rev()
+        .map(|part| u8::from_str_radix(part, 16).unwrap())
+        .collect()
+}
+
+fn padded(s: &str) -> Vec<u8> {
+    let mut bytes = vec![s.len() as u8];
+    bytes.extend(s.as_bytes());
+    bytes.resize(17, 0);
+    bytes
+}
+
+fn event(opcode: u8, body: Vec<u8>) -> Vec<u8> {
+    let mut packet = vec![opcode];
+    packet.extend(body);
+    frame_packet(&packet)
+}
+
+/// The events flicd sends for a command.
+fn answer(command: &Command) -> Vec<Vec<u8>> {
+    match command {
+        Command::GetInfo => {
+            let mut body = vec![2];
+            body.extend(bd_addr_bytes("00:00:00:00:00:01"));
+            body.extend(&[0, 2, 10, 0, 0, 0, 1, 0]);
+            body.extend(bd_addr_bytes(VERIFIED));
+            vec![event(9, body)]
+        }
+        Command::GetButtonInfo { bd_addr } if bd_addr == VERIFIED => {
+            let mut body = bd_addr_bytes(VERIFIED);
+            body.extend(1..=16);
+            body.extend(padded("black"));
+            body.extend(padded("AB12-C34567"));
+            body.push(2);
+            body.extend(&9u32.to_le_bytes());
+            vec![event(14, body)]
+        }
+        Command::CreateBatteryStatusListener { listener_id, .. } => {
+            let mut body = listener_id.to_le_bytes().to_vec();
+            body.push(80);
+            body.extend(&0u64.to_le_bytes());
+            vec![event(20, body)]
+        }
+        Command::CreateConnectionChannel { conn_id, .. } => {
+            let mut body = conn_id.to_le_bytes().to_vec();
+            body.extend(&[0, 1]);
+            vec![event(1, body)]
+        }
+        Command::DeleteButton { bd_addr } if bd_addr == VERIFIED => {
+            let mut body = bd_addr_bytes(VERIFIED);
+            body.push(1);
+            vec![event(19, body)]
+        }
+        Command::CreateScanWizard { scan_wizard_id } => {
+            let mut found = scan_wizard_id.to_le_bytes().to_vec();
+            found.extend(bd_addr_bytes(VERIFIED));
+            found.extend(&[2, b'F', b'2']);
+            let mut completed = scan_wizard_id.to_le_bytes().to_vec();
+            completed.push(0);
+            vec![event(16, found), event(18, completed)]
+        }
+        _ => vec![],
+    }
+}
+
+/// Serves one client and reports every command it receives.
+async fn fake_flicd(port: u16) -> UnboundedReceiver<Command> {
+    let mut listener = TcpListener::bind(("127.0.0.1", port)).await.unwrap();
+    let (tx, rx) = unbounded_channel();
+    tokio::spawn(async move {
+        let (mut stream, _) = listener.accept().await.unwrap();
+        while let Ok(packet) = read_packet(&mut stream).await {
+            let command = Command::from_packet(&packet).unwrap();
+            let events = answer(&command);
+            let _ = tx.send(command);
+            for event in events {
+                stream.write_all(&event).await.unwrap();
+            }
+        }
+    });
+    rx
+}
+
+/// An API in front of a fake flicd.
+async fn api(port: u16) -> (HttpApi, UnboundedReceiver<Command>) {
+    let commands = fake_flicd(port).await;
+    let api = HttpApi::new().timeout(Duration::from_millis(300));
+    let client = FlicClient::new(&format!("127.0.0.1:{}", port))
+        .await
+        .unwrap()
+        .register_event_handler(api.handler())
+        .await;
+    let client = Arc::new(client);
+    let listener = client.clone();
+    tokio::spawn(async move { listener.listen().await });
+    let server = api.clone();
+    tokio::spawn(async move { server.serve(client, ([127, 0, 0, 1], 0).into()).await });
+    (api, commands)
+}
+
+async fn call(api: &HttpApi, method: Method, uri: &str) -> (StatusCode, Value) {
+    let request = Request::builder()
+        .method(method)
+        .uri(uri)
+        .body(Body::empty())
+        .unwrap();
+    let response = api.respond(request).await;
+    let status = response.status();
+    let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+    let value = serde_json::from_slice(&body).unwrap_or(Value::Null);
+    (status, value)
+}
+
+fn received(commands: &mut UnboundedReceiver<Command>) -> Vec<Command> {
+    let mut received = vec![];
+    while let Ok(command) = commands.try_recv() {
+        received.push(command);
+    }
+    received
+}
+
+#[tokio::test]
+async fn reports_server_and_buttons() {
+    let (api, _) = api(18871).await;
+
+    let (status, info) = call(&api, Method::GET, "/info").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(info["bluetooth_controller_state"], "Attached");
+    assert_eq!(info["bd_addr_of_verified_buttons"], json!([VERIFIED]));
+
+    let (status, buttons) = call(&api, Method::GET, "/buttons").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(buttons[0]["bd_addr"], VERIFIED);
+    assert_eq!(buttons[0]["color"], "black");
+    assert_eq!(buttons[0]["serial_number"], "AB12-C34567");
+
+    let (status, button) = call(&api, Method::GET, &format!("/buttons/{}", VERIFIED)).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(button["flic_version"], 2);
+    assert_eq!(button["battery_percentage"], 80);
+    assert_eq!(button["conn_id"], Value::Null);
+}
+
+#[tokio::test]
+async fn connects_with_profile() {
+    let (api, mut commands) = api(18872).await;
+    let connect = format!("/buttons/{}/connect", VERIFIED);
+
+    for query in [
+        "?profile=turbo",
+        "?latency=fast",
+        "?auto_disconnect_time=600",
+        "?auto_disconnect_time=soon",
+    ]
+    .iter()
+    {
+        let (status, body) = call(&api, Method::POST, &format!("{}{}", connect, query)).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST, "{}", query);
+        assert!(body["error"].is_string());
+    }
+    assert!(received(&mut commands).is_empty());
+
+    let uri = format!("{}?profile=battery-saver&latency=low", connect);
+    let (status, button) = call(&api, Method::POST, &uri).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(button["connection_status"], "Connected");
+    let conn_id = button["conn_id"].as_u64().unwrap() as u32;
+    assert_eq!(
+        received(&mut commands),
+        vec![Command::CreateConnectionChannel {
+            conn_id,
+            bd_addr: VERIFIED.to_string(),
+            latency_mode: LatencyMode::LowLatency,
+            auto_disconnect_time: 300,
+        }]
+    );
+
+    // a connected button changes its mode instead
+    let uri = format!("{}?auto_disconnect_time=511", connect);
+    let (status, _) = call(&api, Method::POST, &uri).await;
+    assert_eq!(status, StatusCode::OK);
+    tokio::time::delay_for(Duration::from_millis(100)).await;
+    assert_eq!(
+        received(&mut commands),
+        vec![Command::ChangeModeParameters {
+            conn_id,
+            latency_mode: LatencyMode::NormalLatency,
+            auto_disconnect_time: 511,
+        }]
+    );
+}
+
+#[tokio::test]
+async fn deletes_and_runs_scan_wizard() {
+    let (api, _) = api(18873).await;
+
+    let (status, body) = call(&api, Method::DELETE, &format!("/buttons/{}", VERIFIED)).await;
+    assert_eq!(status, StatusCode::NO_CONTENT);
+    assert_eq!(body, Value::Null);
+
+    let (status, result) = call(&api, Method::POST, "/scan-wizard").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(
+        result,
+        json!({ "result": "WizardSuccess", "bd_addr": VERIFIED, "name": "F2" })
+    );
+}
+
+#[tokio::test]
+async fn rejects_bad_requests() {
+    let (api, _) = api(18874).await;
+
+    let (status, _) = call(&api, Method::GET, "/buttons/kitchen").await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    let (status, _) = call(&api, Method::POST, "/buttons/80:e4:da:76:fa/connect").await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    let (status, _) = call(&api, Method::GET, "/lights").await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    let (status, _) = call(&api, Method::PUT, "/info").await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    let (status, body) = call(&api, Method::GET, &format!("/buttons/{}", SILENT)).await;
+    assert_eq!(status, StatusCode::GATEWAY_TIMEOUT);
+    assert_eq!(body["error"], "flicd did not respond");
+    let (status, _) = call(&api, Method::DELETE, &format!("/buttons/{}", SILENT)).await;
+    assert_eq!(status, StatusCode::GATEWAY_TIMEOUT);
+}
@@ -0,0 +1,153 @@
+#![cfg(feature = "metrics")]
+
+use flicbtn::*;
+
+const BUTTON: &str = "80:e4:da:76:fa:55";
+
+fn status(conn_id: u32, connection_status: ConnectionStatus) -> Event {
+    Event::ConnectionStatusChanged(ConnectionStatusChanged {
+        conn_id,
+        connection_status,
+        disconnect_reason: DisconnectReason::Unspecified,
+    })
+}
+
+#[test]
+fn counts_button_events() {
+    let channels = ChannelMap::new();
+    channels.track_command(&Command::CreateConnectionChannel {
+        conn_id: 1,
+        bd_addr: BUTTON.to_string(),
+        latency_mode: LatencyMode::NormalLatency,
+        auto_disconnect_time: 511,
+    });
+    channels.track_command(&Command::CreateBatteryStatusListener {
+        listener_id: 2,
+        bd_addr: BUTTON.to_string(),
+    });
+    let mut mapper = ByteToEventMapper::new();
+    let metrics = Metrics::new()
+        .channels(channels)
+        .corrupt_packets(mapper.corrupt_packets());
+    let mut handler = metrics.handler();
+    // a button event cut short
+    for b in &[3, 0, 4, 1, 0] {
+        mapper.map(*b);
+    }
+
+    handler(&status(1, ConnectionStatus::Ready));
+    handler(&status(1, ConnectionStatus::Disconnected));
+    handler(&status(1, ConnectionStatus::Connected));
+    for _ in 0..2 {
+        // a click is reported on every event kind, but counted once
+        handler(&Event::Button(ButtonEvent {
+            kind: ButtonEventKind::SingleOrDoubleClickOrHold,
+            conn_id: 1,
+            click_type: ClickType::ButtonSingleClick,
+            was_queued: false,
+            time_diff: 0,
+        }));
+        handler(&Event::Button(ButtonEvent {
+            kind: ButtonEventKind::SingleOrDoubleClick,
+            conn_id: 1,
+            click_type: ClickType::ButtonSingleClick,
+            was_queued: false,
+            time_diff: 0,
+        }));
+    }
+    handler(&Event::BatteryStatus(BatteryStatus {
+        listener_id: 2,
+        battery_percentage: 87,
+        timestamp: 1600000000,
+    }));
+    handler(&Event::GetInfoResponse(GetInfoResponse {
+        bluetooth_controller_state: BluetoothControllerState::Attached,
+        my_bd_addr: "00:00:00:00:00:00".to_string(),
+        my_bd_addr_type: BdAddrType::PublicBdAddrType,
+        max_pending_connections: 2,
+        max_concurrently_connected_buttons: 10,
+        current_pending_connections: 1,
+        currently_no_space_for_new_connection: false,
+        bd_addr_of_verified_buttons: vec![BUTTON.to_string()],
+    }));
+
+    let text = metrics.render();
+    for line in &[
+        "flic_clicks_total{bd_addr=\"80:e4:da:76:fa:55\",click_type=\"ButtonSingleClick\"} 2",
+        "flic_connection_status{bd_addr=\"80:e4:da:76:fa:55\",status=\"Connected\"} 1",
+        "flic_connection_status{bd_addr=\"80:e4:da:76:fa:55\",status=\"Ready\"} 0",
+        "flic_battery_percent{bd_addr=\"80:e4:da:76:fa:55\"} 87",
+        "flic_reconnects_total{bd_addr=\"80:e4:da:76:fa:55\"} 1",
+        "flic_corrupt_packets_total 1",
+        "flic_pending_connections 1",
+        "flic_max_pending_connections 2",
+    ] {
+        assert!(
+            text.lines().any(|l| l == *line),
+            "{} missing in\n{}",
+            line,
+            text
+        );
+    }
+
+    handler(&Event::ConnectionChannelRemoved(ConnectionChannelRemoved {
+        conn_id: 1,
+        removed_reason: RemovedReason::RemovedByThisClient,
+    }));
+    assert!(!metrics.render().contains("flic_connection_status{"));
+}
+
+#[test]
+fn times_pings() {
+    let metrics = Metrics::new();
+    let mut packets = metrics.packet_handler();
+    let mut handler = metrics.handler();
+    let bytes: Vec<u8> = CommandToByteMapper::new()
+        .map(Command::Ping { ping_id: 7 })
+        .collect();
+    packets(PacketDirection::Outbound, &bytes);
+    handler(&Event::PingResponse(PingResponse { ping_id: 7 }));
+    // an unknown ping is not observed
+    handler(&Event::PingResponse(PingResponse { ping_id: 8 }));
+
+    let text = metrics.render();
+    assert!(
+        text.contains("flic_ping_seconds_bucket{le=\"1\"} 1\n"),
+        "{}",
+        text
+    );
+    assert!(
+        text.contains("flic_ping_seconds_bucket{le=\"+Inf\"} 1\n"),
+        "{}",
+        text
+    );
+    assert!(text.contains("flic_ping_seconds_count 1\n"), "{}", text);
+}
+
+#[test]
+fn skips_unknown_battery_level() {
+    let channels = ChannelMap::new();
+    channels.track_command(&Command::CreateBatteryStatusListener {
+        listener_id: 2,
+        bd_addr: BUTTON.to_string(),
+    });
+    let metrics = Metrics::new().channels(channels);
+    let mut handler = metrics.handler();
+    let battery = |battery_percentage| {
+        Event::BatteryStatus(BatteryStatus {
+            listener_id: 2,
+            battery_percentage,
+            timestamp: 1600000000,
+        })
+    };
+
+    handler(&battery(-1));
+    assert!(!metrics.render().contains("flic_battery_percent{"));
+    handler(&battery(87));
+    assert!(metrics
+        .render()
+        .contains("flic_battery_percent{bd_addr=\"80:e4:da:76:fa:55\"} 87\n"));
+    // a level that became unknown isn't reported as the last one
+    handler(&battery(-1));
+    assert!(!metrics.render().contains("flic_battery_percent{"));
+}
@@ -0,0 +1,238 @@
+#![cfg(feature = "mqtt")]
+
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use rumqttc::{AsyncClient, Event as MqttEvent, EventLoop, MqttOptions, Packet, QoS};
+use serde_json::{json, Value};
+use tokio::net::TcpListener;
+use tokio::prelude::*;
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver};
+use tokio::time::timeout;
+
+use flicbtn::*;
+
+const BROKER_PORT: u16 = 18831;
+const FLICD_ADDR: &str = "127.0.0.1:18832";
+const BUTTON: &str = "80:e4:da:76:fa:55";
+
+fn start_broker() {
+    let config: librumqttd::Config = serde_json::from_value(json!({
+        "servers": [{
+            "port": BROKER_PORT,
+            "connection_timeout_ms": 1000,
+            "next_connection_delay_ms": 0,
+            "max_client_id_len": 64,
+            "max_connections": 10,
+            "throttle_delay_ms": 0,
+            "max_payload_size": 4096,
+            "max_inflight_count": 100,
+            "max_inflight_size": 102400,
+            "ca_path": null,
+            "cert_path": null,
+            "key_path": null,
+            "username": null,
+            "password": null,
+        }],
+        "router": {
+            "id": 0,
+            "dir": std::env::temp_dir().join("flicbtn-mqtt-test"),
+            "max_segment_size": 10240,
+            "max_segment_count": 10,
+            "max_connections": 10,
+            "mesh": null,
+        },
+    }))
+    .unwrap();
+    thread::spawn(move || {
+        let _ = librumqttd::Broker::new(config).start();
+    });
+}
+
+fn packet(opcode: u8, body: &[u8]) -> Vec<u8> {
+    let len = body.len() + 1;
+    let mut packet = vec![len as u8, (len >> 8) as u8, opcode];
+    packet.extend_from_slice(body);
+    packet
+}
+
+fn bd_addr_bytes() -> Vec<u8> {
+    vec![0x55, 0xfa, 0x76, 0xda, 0xe4, 0x80]
+}
+
+/// Serves one client and reports the opcode of every command it receives.
+async fn fake_flicd(
+    mut listener: TcpListener,
+) -> (UnboundedReceiver<u8>, tokio::net::tcp::OwnedWriteHalf) {
+    let (stream, _) = listener.accept().await.unwrap();
+    let (mut reader, writer) = stream.into_split();
+    let (tx, rx) = unbounded_channel();
+    tokio::spawn(async move {
+        loop {
+            let lower = match reader.read_u8().await {
+                Ok(b) => b as usize,
+                Err(_) => return,
+            };
+            let upper = reader.read_u8().await.unwrap() as usize;
+            let mut body = vec![0; (upper << 8) + lower];
+            reader.read_exact(&mut body).await.unwrap();
+            let _ = tx.send(body[0]);
+        }
+    });
+    (rx, writer)
+}
+
+async fn expect_opcode(rx: &mut UnboundedReceiver<u8>, opcode: u8) {
+    timeout(Duration::from_secs(5), async {
+        while let Some(received) = rx.recv().await {
+            if received == opcode {
+                return;
+            }
+        }
+        panic!("flicd connection closed");
+    })
+    .await
+    .expect("command not received");
+}
+
+async fn expect_publish(eventloop: &mut EventLoop, topic: &str) -> Vec<u8> {
+    timeout(Duration::from_secs(5), async {
+        loop {
+            if let MqttEvent::Incoming(Packet::Publish(publish)) = eventloop.poll().await.unwrap() {
+                if publish.topic == topic {
+                    return publish.payload.to_vec();
+                }
+            }
+        }
+    })
+    .await
+    .expect("message not published")
+}
+
+#[tokio::test]
+async fn bridges_events_and_commands() {
+    start_broker();
+    tokio::time::delay_for(Duration::from_millis(500)).await;
+
+    let (observer, mut observer_loop) =
+        AsyncClient::new(MqttOptions::new("observer", "127.0.0.1", BROKER_PORT), 16);
+    observer
+        .subscribe("flic/#", QoS::AtLeastOnce)
+        .await
+        .unwrap();
+    timeout(Duration::from_secs(5), async {
+        while let Ok(event) = observer_loop.poll().await {
+            if let MqttEvent::Incoming(Packet::SubAck(_)) = event {
+                return;
+            }
+        }
+    })
+    .await
+    .expect("observer not subscribed");
+
+    let listener = TcpListener::bind(FLICD_ADDR).await.unwrap();
+    let bridge = MqttBridge::new("flic");
+    let client = FlicClient::new(FLICD_ADDR)
+        .await
+        .unwrap()
+        .register_event_handler(bridge.handler())
+        .await;
+    let client = Arc::new(client);
+    let (mut commands, mut flicd) = fake_flicd(listener).await;
+    {
+        let client = client.clone();
+        tokio::spawn(async move { client.listen().await });
+    }
+    {
+        let client = client.clone();
+        let options = MqttOptions::new("bridge", "127.0.0.1", BROKER_PORT);
+        tokio::spawn(async move { bridge.run(client, options).await });
+    }
+
+    expect_opcode(&mut commands, 0).await;
+    assert_eq!(
+        expect_publish(&mut observer_loop, "flic/server/status").await,
+        b"online".to_vec()
+    );
+
+    let mut info = vec![2];
+    info.extend(vec![0; 6]);
+    info.extend(vec![0, 2, 10, 0, 0, 0, 1, 0]);
+    info.extend(bd_addr_bytes());
+    flicd.write_all(&packet(9, &info)).await.unwrap();
+    let info: Value =
+        serde_json::from_slice(&expect_publish(&mut observer_loop, "flic/server/info").await)
+            .unwrap();
+    assert_eq!(info["bd_addr_of_verified_buttons"], json!([BUTTON]));
+    expect_opcode(&mut commands, 3).await;
+
+    flicd
+        .write_all(&packet(7, &[1, 0, 0, 0, 4, 1, 5, 0, 0, 0]))
+        .await
+        .unwrap();
+    let click: Value = serde_json::from_slice(
+        &expect_publish(&mut observer_loop, &format!("flic/{}/click", BUTTON)).await,
+    )
+    .unwrap();
+    assert_eq!(click["bd_addr"], json!(BUTTON));
+    assert_eq!(click["click_type"], json!("ButtonDoubleClick"));
+    assert_eq!(click["was_queued"], json!(true));
+
+    observer
+        .publish("flic/cmd/delete", QoS::AtLeastOnce, false, BUTTON)
+        .await
+        .unwrap();
+    tokio::spawn(async move { while observer_loop.poll().await.is_ok() {} });
+    expect_opcode(&mut commands, 11).await;
+
+    client.stop().await;
+}
+
+#[test]
+fn rejects_malformed_addresses() {
+    let bridge = MqttBridge::new("flic");
+    for topic in ["connect", "disconnect", "button-info", "delete"].iter() {
+        let topic = format!("flic/cmd/{}", topic);
+        assert!(bridge.handle_message(&topic, b"kitchen").is_empty());
+        assert!(bridge
+            .handle_message(&topic, b"80:e4:da:76:fa:55:00")
+            .is_empty());
+        assert!(bridge
+            .handle_message(&topic, br#"{"bd_addr": "80:e4:da:76:fa"}"#)
+            .is_empty());
+    }
+    assert_eq!(
+        bridge.handle_message("flic/cmd/delete", b"80:e4:da:76:fa:55"),
+        vec![Command::DeleteButton {
+            bd_addr: "80:e4:da:76:fa:55".to_string()
+        }]
+    );
+}
+
+#[test]
+fn lowercases_addresses() {
+    let bridge = MqttBridge::new("flic");
+    let upper = BUTTON.to_uppercase();
+    for topic in ["connect", "disconnect", "button-info", "delete"].iter() {
+        let topic = format!("flic/cmd/{}", topic);
+        let commands = bridge.handle_message(&topic, upper.as_bytes());
+        assert!(!commands.is_empty(), "{}", topic);
+        for command in commands {
+            let bd_addr = match command {
+                Command::CreateConnectionChannel { bd_addr, .. }
+                | Command::CreateBatteryStatusListener { bd_addr, .. }
+                | Command::GetButtonInfo { bd_addr }
+                | Command::DeleteButton { bd_addr } => bd_addr,
+                _ => continue,
+            };
+            assert_eq!(bd_addr, BUTTON, "{}", topic);
+        }
+    }
+    assert_eq!(
+        bridge.handle_message("flic/cmd/delete", upper.as_bytes()),
+        vec![Command::DeleteButton {
+            bd_addr: BUTTON.to_string()
+        }]
+    );
+}
@@ -0,0 +1,122 @@
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use tokio::net::TcpListener;
+use tokio::time::timeout;
+
+use flicbtn::*;
+
+fn controller(state: BluetoothControllerState) -> Event {
+    Event::BluetoothControllerStateChange(BluetoothControllerStateChange { state })
+}
+
+#[test]
+fn pauses_and_restores_scanners_and_wizards() {
+    let outages = Arc::new(Mutex::new(vec![]));
+    let guard = {
+        let outages = outages.clone();
+        OutageGuard::new().register_outage_handler(outage_handler(move |outage| {
+            outages.lock().unwrap().push(outage.clone())
+        }))
+    };
+    guard.handle_command(Command::CreateScanner { scan_id: 1 });
+    guard.handle_command(Command::CreateScanner { scan_id: 2 });
+    guard.handle_command(Command::RemoveScanner { scan_id: 2 });
+    guard.handle_command(Command::CreateScanWizard { scan_wizard_id: 7 });
+    assert_eq!(
+        guard.handle_event(&controller(BluetoothControllerState::Attached)),
+        vec![]
+    );
+
+    let paused = guard.handle_event(&controller(BluetoothControllerState::Detached));
+    assert_eq!(
+        paused,
+        vec![
+            Command::RemoveScanner { scan_id: 1 },
+            Command::CancelScanWizard { scan_wizard_id: 7 },
+        ]
+    );
+    assert_eq!(guard.outage(), Some(BluetoothControllerState::Detached));
+    // the guard's own commands and the failed wizard keep them paused
+    for command in paused {
+        guard.handle_command(command);
+    }
+    guard.handle_event(&Event::ScanWizardCompleted(ScanWizardCompleted {
+        scan_wizard_id: 7,
+        result: ScanWizardResult::WizardBluetoothUnavailable,
+    }));
+    assert_eq!(
+        guard.handle_event(&controller(BluetoothControllerState::Resetting)),
+        vec![]
+    );
+
+    assert_eq!(
+        guard.handle_event(&controller(BluetoothControllerState::Attached)),
+        vec![
+            Command::CreateScanner { scan_id: 1 },
+            Command::CreateScanWizard { scan_wizard_id: 7 },
+        ]
+    );
+    assert_eq!(guard.outage(), None);
+    assert_eq!(
+        *outages.lock().unwrap(),
+        vec![
+            Outage::Started {
+                state: BluetoothControllerState::Detached,
+                scanners: vec![1],
+                scan_wizards: vec![7],
+            },
+            Outage::Ended {
+                scanners: vec![1],
+                scan_wizards: vec![7],
+            },
+        ]
+    );
+}
+
+#[test]
+fn forgets_scanners_removed_during_an_outage() {
+    let guard = OutageGuard::new();
+    guard.handle_command(Command::CreateScanner { scan_id: 1 });
+    guard.handle_command(Command::CreateScanner { scan_id: 2 });
+    guard.handle_event(&controller(BluetoothControllerState::Resetting));
+    guard.handle_command(Command::RemoveScanner { scan_id: 1 });
+    guard.handle_command(Command::RemoveScanner { scan_id: 2 });
+    // removed by the client while paused
+    guard.handle_command(Command::RemoveScanner { scan_id: 2 });
+    assert_eq!(
+        guard.handle_event(&controller(BluetoothControllerState::Attached)),
+        vec![Command::CreateScanner { scan_id: 1 }]
+    );
+}
+
+#[tokio::test]
+async fn runs_until_client_stops() {
+    let mut listener = TcpListener::bind("127.0.0.1:18895").await.unwrap();
+    let client = Arc::new(FlicClient::new("127.0.0.1:18895").await.unwrap());
+    let (mut flicd, _) = listener.accept().await.unwrap();
+    let guard = OutageGuard::new();
+    let run = {
+        let guard = guard.clone();
+        let client = client.clone();
+        tokio::spawn(async move { guard.run(client).await })
+    };
+
+    guard.handle_command(Command::CreateScanner { scan_id: 1 });
+    let mut handler = guard.handler();
+    handler(&controller(BluetoothControllerState::Detached));
+    let packet = timeout(Duration::from_secs(5), read_packet(&mut flicd))
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(
+        Command::from_packet(&packet),
+        Some(Command::RemoveScanner { scan_id: 1 })
+    );
+
+    client.stop().await;
+    timeout(Duration::from_secs(1), run)
+        .await
+        .expect("run did not return")
+        .unwrap();
+}
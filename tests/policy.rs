@@ -0,0 +1,170 @@
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+
+use tokio::time::delay_for;
+
+use flicbtn::*;
+
+fn button(kind: ButtonEventKind, conn_id: u32, click_type: ClickType, time_diff: i32) -> Event {
+    Event::Button(ButtonEvent {
+        kind,
+        conn_id,
+        click_type,
+        was_queued: time_diff > 0,
+        time_diff,
+    })
+}
+
+/// The button events flicd sends for a single click, queued for `time_diff`
+/// seconds.
+fn single_click(conn_id: u32, time_diff: i32) -> Vec<Event> {
+    vec![
+        button(
+            ButtonEventKind::UpOrDown,
+            conn_id,
+            ClickType::ButtonDown,
+            time_diff,
+        ),
+        button(
+            ButtonEventKind::UpOrDown,
+            conn_id,
+            ClickType::ButtonUp,
+            time_diff,
+        ),
+        button(
+            ButtonEventKind::ClickOrHold,
+            conn_id,
+            ClickType::ButtonClick,
+            time_diff,
+        ),
+        button(
+            ButtonEventKind::SingleOrDoubleClick,
+            conn_id,
+            ClickType::ButtonSingleClick,
+            time_diff,
+        ),
+        button(
+            ButtonEventKind::SingleOrDoubleClickOrHold,
+            conn_id,
+            ClickType::ButtonSingleClick,
+            time_diff,
+        ),
+    ]
+}
+
+/// Runs events through a policy and returns the clicks it passes on.
+fn handle(policy: ClickPolicy, events: &[Event]) -> Arc<Mutex<Vec<Click>>> {
+    let clicks = Arc::new(Mutex::new(vec![]));
+    let mut handler = {
+        let clicks = clicks.clone();
+        policy.handler(move |click: &Click| clicks.lock().unwrap().push(click.clone()))
+    };
+    handler(&Event::PingResponse(PingResponse { ping_id: 1 }));
+    for event in events {
+        handler(event);
+    }
+    clicks
+}
+
+fn click_types(clicks: &Arc<Mutex<Vec<Click>>>) -> Vec<ClickType> {
+    clicks
+        .lock()
+        .unwrap()
+        .iter()
+        .map(|c| c.click_type)
+        .collect()
+}
+
+const GESTURES: [ClickType; 4] = [
+    ClickType::ButtonDown,
+    ClickType::ButtonUp,
+    ClickType::ButtonClick,
+    ClickType::ButtonSingleClick,
+];
+
+#[test]
+fn stale_threshold() {
+    let policy = ClickPolicy::new(Duration::from_secs(5), QueuedClickAction::Drop);
+    let now = SystemTime::now();
+    let click = |time_diff| Click::from_event(&single_click(1, time_diff)[2], now).unwrap();
+    assert!(!policy.is_stale(&click(0), now));
+    assert!(!policy.is_stale(&click(4), now));
+    assert!(policy.is_stale(&click(5), now));
+    assert_eq!(click(5).occurred_at, now - Duration::from_secs(5));
+}
+
+#[test]
+fn delivers_each_gesture_once() {
+    let policy = ClickPolicy::new(Duration::from_secs(5), QueuedClickAction::Drop);
+    let clicks = handle(policy, &single_click(1, 0));
+    assert_eq!(click_types(&clicks), GESTURES);
+    assert!(clicks.lock().unwrap().iter().all(|c| !c.stale));
+}
+
+#[test]
+fn delivers_flags_or_drops_stale_clicks() {
+    let deliver = ClickPolicy::new(Duration::from_secs(5), QueuedClickAction::Deliver);
+    let clicks = handle(deliver, &single_click(1, 10));
+    assert_eq!(click_types(&clicks), GESTURES);
+    assert!(clicks.lock().unwrap().iter().all(|c| !c.stale));
+
+    let flag = ClickPolicy::new(Duration::from_secs(5), QueuedClickAction::Flag);
+    let clicks = handle(flag, &single_click(1, 10));
+    assert_eq!(click_types(&clicks), GESTURES);
+    assert!(clicks.lock().unwrap().iter().all(|c| c.stale));
+
+    let drop = ClickPolicy::new(Duration::from_secs(5), QueuedClickAction::Drop);
+    let mut events = single_click(1, 10);
+    events.extend(single_click(1, 2));
+    let clicks = handle(drop, &events);
+    assert_eq!(click_types(&clicks), GESTURES);
+    assert!(clicks
+        .lock()
+        .unwrap()
+        .iter()
+        .all(|c| c.was_queued && !c.stale));
+}
+
+#[tokio::test]
+async fn delays_stale_clicks() {
+    let delay = QueuedClickAction::Delay(Duration::from_millis(100));
+    let policy = ClickPolicy::new(Duration::from_secs(5), delay);
+    let mut events = single_click(1, 20);
+    events.extend(single_click(1, 10));
+    let clicks = handle(policy, &events);
+    assert!(clicks.lock().unwrap().is_empty());
+    delay_for(Duration::from_millis(300)).await;
+    assert_eq!(click_types(&clicks), [GESTURES, GESTURES].concat());
+    assert!(clicks.lock().unwrap().iter().all(|c| c.stale));
+}
+
+#[tokio::test]
+async fn coalesces_delayed_clicks() {
+    let delay = QueuedClickAction::Delay(Duration::from_millis(100));
+    let policy = ClickPolicy::new(Duration::from_secs(5), delay).coalesce(true);
+    let mut events = single_click(1, 20);
+    events.extend(single_click(2, 20));
+    events.extend(single_click(1, 10));
+    let clicks = handle(policy, &events);
+    delay_for(Duration::from_millis(300)).await;
+
+    let clicks = clicks.lock().unwrap();
+    // the last of each gesture per connection
+    let kept: Vec<(u32, ClickType, i32)> = clicks
+        .iter()
+        .map(|c| (c.conn_id, c.click_type, c.event.button().unwrap().time_diff))
+        .collect();
+    assert_eq!(
+        kept,
+        vec![
+            (2, ClickType::ButtonDown, 20),
+            (2, ClickType::ButtonUp, 20),
+            (2, ClickType::ButtonClick, 20),
+            (2, ClickType::ButtonSingleClick, 20),
+            (1, ClickType::ButtonDown, 10),
+            (1, ClickType::ButtonUp, 10),
+            (1, ClickType::ButtonClick, 10),
+            (1, ClickType::ButtonSingleClick, 10),
+        ]
+    );
+}
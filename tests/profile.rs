@@ -0,0 +1,139 @@
+use std::time::Duration;
+
+use flicbtn::*;
+
+const BUTTON: &str = "80:e4:da:76:fa:55";
+
+#[test]
+fn encodes_auto_disconnect() {
+    assert_eq!(AutoDisconnect::from_wire(511), Ok(AutoDisconnect::Never));
+    assert_eq!(
+        AutoDisconnect::from_wire(60),
+        Ok(AutoDisconnect::After(Duration::from_secs(60)))
+    );
+    assert_eq!(
+        AutoDisconnect::from_wire(512),
+        Err(ProfileError::InvalidAutoDisconnectTime(512))
+    );
+    assert_eq!(
+        AutoDisconnect::from_wire(-1),
+        Err(ProfileError::InvalidAutoDisconnectTime(-1))
+    );
+    assert_eq!(AutoDisconnect::Never.to_wire(), Ok(511));
+    assert_eq!(
+        AutoDisconnect::After(Duration::from_secs(510)).to_wire(),
+        Ok(510)
+    );
+    let too_long = Duration::from_secs(511);
+    assert_eq!(
+        ConnectionProfile::new(LatencyMode::LowLatency, AutoDisconnect::After(too_long)),
+        Err(ProfileError::InvalidAutoDisconnect(too_long))
+    );
+}
+
+#[test]
+fn builds_commands_of_named_profiles() {
+    for name in ConnectionProfile::NAMES.iter() {
+        assert!(name.parse::<ConnectionProfile>().is_ok());
+    }
+    assert_eq!(
+        "turbo".parse::<ConnectionProfile>(),
+        Err(ProfileError::UnknownProfile("turbo".to_string()))
+    );
+
+    let profile = ConnectionProfile::battery_saver();
+    let create = profile.create_channel(3, BUTTON);
+    assert_eq!(
+        create,
+        Command::CreateConnectionChannel {
+            conn_id: 3,
+            bd_addr: BUTTON.to_string(),
+            latency_mode: LatencyMode::HighLatency,
+            auto_disconnect_time: 300,
+        }
+    );
+    assert_eq!(ConnectionProfile::of_command(&create), Some(Ok(profile)));
+    assert_eq!(
+        ConnectionProfile::responsive().change_mode(3),
+        Command::ChangeModeParameters {
+            conn_id: 3,
+            latency_mode: LatencyMode::LowLatency,
+            auto_disconnect_time: 511,
+        }
+    );
+    assert_eq!(ConnectionProfile::of_command(&Command::GetInfo), None);
+}
+
+#[cfg(feature = "schedule")]
+#[test]
+fn switches_live_channels_on_schedule() {
+    use chrono::NaiveTime;
+
+    let time = |h| NaiveTime::from_hms_opt(h, 0, 0).unwrap();
+    let other = "80:e4:da:70:00:01";
+    let schedule = ProfileSchedule::new(ConnectionProfile::battery_saver())
+        .window(time(9), time(17), ConnectionProfile::responsive())
+        .button(other, ConnectionProfile::default())
+        .button_window(other, time(22), time(6), ConnectionProfile::battery_saver());
+    assert_eq!(
+        schedule.profile_at(BUTTON, time(8)),
+        ConnectionProfile::battery_saver()
+    );
+    assert_eq!(
+        schedule.profile_at(other, time(12)),
+        ConnectionProfile::responsive()
+    );
+    assert_eq!(
+        schedule.profile_at(other, time(18)),
+        ConnectionProfile::default()
+    );
+    assert_eq!(
+        schedule.profile_at(other, time(3)),
+        ConnectionProfile::battery_saver()
+    );
+
+    let channels = ChannelMap::new();
+    channels.track_command(&ConnectionProfile::default().create_channel(1, BUTTON));
+    let scheduler = ProfileScheduler::new(schedule).channels(channels.clone());
+    assert_eq!(
+        scheduler.commands_at(time(8)),
+        vec![ConnectionProfile::battery_saver().change_mode(1)]
+    );
+    assert_eq!(scheduler.commands_at(time(8)), vec![]);
+    channels.track_command(&ConnectionProfile::default().create_channel(2, other));
+    assert_eq!(
+        scheduler.commands_at(time(10)),
+        vec![
+            ConnectionProfile::responsive().change_mode(1),
+            ConnectionProfile::responsive().change_mode(2),
+        ]
+    );
+    channels.track_command(&Command::RemoveConnectionChannel { conn_id: 2 });
+    assert_eq!(scheduler.commands_at(time(10)), vec![]);
+}
+
+#[cfg(feature = "schedule")]
+#[tokio::test]
+async fn watches_until_client_stops() {
+    use std::sync::Arc;
+
+    use tokio::net::TcpListener;
+    use tokio::time::{delay_for, timeout};
+
+    let mut listener = TcpListener::bind("127.0.0.1:18896").await.unwrap();
+    let client = Arc::new(FlicClient::new("127.0.0.1:18896").await.unwrap());
+    let _flicd = listener.accept().await.unwrap();
+    let scheduler = ProfileScheduler::new(ProfileSchedule::new(ConnectionProfile::default()));
+    let watch = {
+        let client = client.clone();
+        tokio::spawn(async move { scheduler.watch(client, Duration::from_secs(60)).await })
+    };
+
+    // stopped while waiting for the next check
+    delay_for(Duration::from_millis(100)).await;
+    client.stop().await;
+    timeout(Duration::from_secs(1), watch)
+        .await
+        .expect("watch did not return")
+        .unwrap();
+}
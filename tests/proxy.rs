@@ -0,0 +1,232 @@
+use flicbtn::*;
+
+const BUTTON: [u8; 6] = [0x55, 0xfa, 0x76, 0xda, 0xe4, 0x80];
+
+/// A packet with an id following the opcode.
+fn with_id(opcode: u8, id: u32) -> Vec<u8> {
+    let mut packet = vec![opcode];
+    packet.extend_from_slice(&id.to_le_bytes());
+    packet
+}
+
+fn with_bd_addr(opcode: u8, rest: &[u8]) -> Vec<u8> {
+    let mut packet = vec![opcode];
+    packet.extend_from_slice(&BUTTON);
+    packet.extend_from_slice(rest);
+    packet
+}
+
+/// The commands creating something, with the events answering them.
+const CREATED: [(u8, u8); 5] = [
+    // scanner, advertisement packet
+    (1, 0),
+    // connection channel, button up or down
+    (3, 4),
+    // ping, ping response
+    (7, 13),
+    // scan wizard, found private button
+    (9, 15),
+    // battery status listener, battery status
+    (12, 20),
+];
+
+#[test]
+fn namespaces_ids() {
+    let mut mux = Multiplexer::new();
+    let a = mux.add_client();
+    let b = mux.add_client();
+    for (opcode, _) in CREATED.iter() {
+        assert_eq!(
+            mux.command(a, &with_id(*opcode, 7)),
+            Some(with_id(*opcode, 1))
+        );
+        assert_eq!(
+            mux.command(b, &with_id(*opcode, 7)),
+            Some(with_id(*opcode, 2))
+        );
+        assert_eq!(
+            mux.command(b, &with_id(*opcode, 8)),
+            Some(with_id(*opcode, 3))
+        );
+        // creating again keeps the id
+        assert_eq!(
+            mux.command(a, &with_id(*opcode, 7)),
+            Some(with_id(*opcode, 1))
+        );
+    }
+
+    // ids of other clients can't be referenced
+    assert_eq!(mux.command(a, &with_id(6, 8)), None);
+    let mut change = with_id(6, 8);
+    change.extend_from_slice(&[1, 0, 0]);
+    let mut upstream = with_id(6, 3);
+    upstream.extend_from_slice(&[1, 0, 0]);
+    assert_eq!(mux.command(b, &change), Some(upstream));
+}
+
+#[test]
+fn routes_events_to_owner() {
+    let mut mux = Multiplexer::new();
+    let a = mux.add_client();
+    let b = mux.add_client();
+    for (opcode, event) in CREATED.iter() {
+        mux.command(a, &with_id(*opcode, 7));
+        mux.command(b, &with_id(*opcode, 7));
+        let mut packet = with_id(*event, 2);
+        packet.extend_from_slice(&[1, 2, 3]);
+        let mut restored = with_id(*event, 7);
+        restored.extend_from_slice(&[1, 2, 3]);
+        assert_eq!(mux.event(&packet), vec![(b, restored)]);
+        assert_eq!(mux.event(&with_id(*event, 3)), vec![]);
+    }
+
+    // events without an id go to everyone
+    let verified = with_bd_addr(12, &[]);
+    assert_eq!(
+        mux.event(&verified),
+        vec![(a, verified.clone()), (b, verified)]
+    );
+}
+
+#[test]
+fn releases_ids() {
+    let mut mux = Multiplexer::new();
+    let a = mux.add_client();
+    // connection channel removed, ping response, scan wizard completed
+    for (opcode, event) in [(3, 3), (7, 13), (9, 18)].iter() {
+        mux.command(a, &with_id(*opcode, 7));
+        assert_eq!(
+            mux.event(&with_id(*event, 1)),
+            vec![(a, with_id(*event, 7))]
+        );
+        assert_eq!(mux.event(&with_id(*event, 1)), vec![]);
+        // a new id is handed out for the client's id
+        assert_eq!(
+            mux.command(a, &with_id(*opcode, 7)),
+            Some(with_id(*opcode, 2))
+        );
+    }
+    assert_eq!(mux.command(a, &with_id(4, 1)), None);
+    assert_eq!(mux.command(a, &with_id(4, 7)), Some(with_id(4, 2)));
+}
+
+#[test]
+fn routes_info_responses_in_order() {
+    let mut mux = Multiplexer::new();
+    let a = mux.add_client();
+    let b = mux.add_client();
+    let c = mux.add_client();
+
+    mux.command(b, &[0]);
+    mux.command(a, &[0]);
+    let info = vec![9, 2];
+    assert_eq!(mux.event(&info), vec![(b, info.clone())]);
+    assert_eq!(mux.event(&info), vec![(a, info.clone())]);
+    // unrequested responses are broadcast
+    assert_eq!(mux.event(&info).len(), 3);
+
+    mux.command(c, &with_bd_addr(8, &[]));
+    mux.command(a, &with_bd_addr(8, &[]));
+    let button_info = with_bd_addr(14, &[1, 2, 3]);
+    assert_eq!(mux.event(&button_info), vec![(c, button_info.clone())]);
+    assert_eq!(mux.event(&button_info), vec![(a, button_info.clone())]);
+}
+
+#[test]
+fn tells_deleting_client() {
+    let mut mux = Multiplexer::new();
+    let a = mux.add_client();
+    let b = mux.add_client();
+
+    assert_eq!(
+        mux.command(b, &with_bd_addr(11, &[])),
+        Some(with_bd_addr(11, &[]))
+    );
+    assert_eq!(
+        mux.event(&with_bd_addr(19, &[1])),
+        vec![(a, with_bd_addr(19, &[0])), (b, with_bd_addr(19, &[1]))]
+    );
+    // the request was answered, a later deletion came from elsewhere
+    assert_eq!(
+        mux.event(&with_bd_addr(19, &[1])),
+        vec![(a, with_bd_addr(19, &[0])), (b, with_bd_addr(19, &[0]))]
+    );
+    assert_eq!(
+        mux.event(&with_bd_addr(19, &[0])),
+        vec![(a, with_bd_addr(19, &[0])), (b, with_bd_addr(19, &[0]))]
+    );
+}
+
+#[test]
+fn cleans_up_removed_client() {
+    let mut mux = Multiplexer::new();
+    let a = mux.add_client();
+    let b = mux.add_client();
+    mux.command(b, &with_id(3, 1));
+    for (opcode, _) in CREATED.iter() {
+        mux.command(a, &with_id(*opcode, 7));
+    }
+    mux.command(a, &[0]);
+    mux.command(b, &[0]);
+
+    let mut commands = mux.remove_client(a);
+    commands.sort();
+    // remove scanner, connection channel, scan wizard and listener, pings
+    // need no cleanup
+    assert_eq!(
+        commands,
+        vec![with_id(2, 1), with_id(4, 2), with_id(10, 1), with_id(13, 1)]
+    );
+    assert_eq!(mux.remove_client(a), Vec::<Vec<u8>>::new());
+
+    // nothing is routed to the removed client any more
+    assert_eq!(mux.event(&with_id(4, 2)), vec![]);
+    // the response to its request is dropped rather than handed to b
+    assert_eq!(mux.event(&[9, 2]), vec![]);
+    assert_eq!(mux.event(&[9, 2]), vec![(b, vec![9, 2])]);
+    assert_eq!(mux.event(&with_id(4, 1)), vec![(b, with_id(4, 1))]);
+}
+
+#[test]
+fn discards_responses_to_departed_client() {
+    let mut mux = Multiplexer::new();
+    let a = mux.add_client();
+    let b = mux.add_client();
+    mux.command(a, &with_bd_addr(8, &[]));
+    mux.command(b, &with_bd_addr(8, &[]));
+    mux.command(a, &with_bd_addr(11, &[]));
+    mux.command(b, &with_bd_addr(11, &[]));
+    mux.remove_client(a);
+
+    let button_info = with_bd_addr(14, &[1, 2, 3]);
+    assert_eq!(mux.event(&button_info), vec![]);
+    assert_eq!(mux.event(&button_info), vec![(b, button_info.clone())]);
+
+    // a asked to delete first, so b didn't delete the button
+    assert_eq!(
+        mux.event(&with_bd_addr(19, &[1])),
+        vec![(b, with_bd_addr(19, &[0]))]
+    );
+    assert_eq!(
+        mux.event(&with_bd_addr(19, &[1])),
+        vec![(b, with_bd_addr(19, &[1]))]
+    );
+}
+
+#[test]
+fn splits_framed_packets() {
+    let mut buffer = frame_packet(&[9, 2]);
+    buffer.extend(frame_packet(&[13, 1, 0, 0, 0]));
+    buffer.extend(&[3, 0, 4]);
+    assert_eq!(
+        split_packets(&mut buffer),
+        vec![frame_packet(&[9, 2]), frame_packet(&[13, 1, 0, 0, 0])]
+    );
+    assert_eq!(buffer, vec![3, 0, 4]);
+
+    buffer.push(1);
+    assert!(split_packets(&mut buffer).is_empty());
+    buffer.push(0);
+    assert_eq!(split_packets(&mut buffer), vec![vec![3, 0, 4, 1, 0]]);
+    assert!(buffer.is_empty());
+}
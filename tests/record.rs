@@ -0,0 +1,149 @@
+#![cfg(feature = "record")]
+
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use tokio::net::TcpListener;
+use tokio::sync::mpsc::unbounded_channel;
+use tokio::time::timeout;
+
+use flicbtn::*;
+
+const BUTTON: &str = "80:e4:da:76:fa:55";
+const SESSION: &str = "tests/recordings/session.jsonl";
+
+fn session_events() -> Vec<Event> {
+    vec![
+        Event::GetInfoResponse(GetInfoResponse {
+            bluetooth_controller_state: BluetoothControllerState::Attached,
+            my_bd_addr: "00:00:00:00:00:00".to_string(),
+            my_bd_addr_type: BdAddrType::PublicBdAddrType,
+            max_pending_connections: 2,
+            max_concurrently_connected_buttons: 10,
+            current_pending_connections: 0,
+            currently_no_space_for_new_connection: false,
+            bd_addr_of_verified_buttons: vec![BUTTON.to_string()],
+        }),
+        Event::CreateConnectionChannelResponse(CreateConnectionChannelResponse {
+            conn_id: 1,
+            error: CreateConnectionChannelError::NoError,
+            connection_status: ConnectionStatus::Connected,
+        }),
+        Event::ConnectionStatusChanged(ConnectionStatusChanged {
+            conn_id: 1,
+            connection_status: ConnectionStatus::Ready,
+            disconnect_reason: DisconnectReason::Unspecified,
+        }),
+        Event::Button(ButtonEvent {
+            kind: ButtonEventKind::SingleOrDoubleClickOrHold,
+            conn_id: 1,
+            click_type: ClickType::ButtonDoubleClick,
+            was_queued: true,
+            time_diff: 2,
+        }),
+    ]
+}
+
+#[test]
+fn decodes_recording() {
+    let recording = Recording::load(SESSION).unwrap();
+    assert_eq!(recording.packets.len(), 6);
+    assert_eq!(recording.events(), session_events());
+}
+
+#[test]
+fn decodes_packet_bytes() {
+    let packet = RecordedPacket::new(0, PacketDirection::Inbound, &[0x02, 0x00, 0x0d, 0xff]);
+    assert_eq!(packet.data, "02000dff");
+    assert_eq!(packet.bytes(), vec![0x02, 0x00, 0x0d, 0xff]);
+
+    let malformed = |data: &str| RecordedPacket {
+        data: data.to_string(),
+        ..packet.clone()
+    };
+    assert_eq!(malformed("02000").bytes(), vec![0x02, 0x00]);
+    assert!(malformed("0é").bytes().is_empty());
+    assert_eq!(malformed("02zz0d").bytes(), vec![0x02, 0x0d]);
+}
+
+#[tokio::test]
+async fn replays_accelerated() {
+    let recording = Recording::load(SESSION).unwrap();
+    let mut events = vec![];
+    let started = Instant::now();
+    recording
+        .replay(10.0, |event| events.push(event.clone()))
+        .await;
+    let elapsed = started.elapsed();
+    assert!(elapsed >= Duration::from_millis(140), "{:?}", elapsed);
+    assert!(elapsed < Duration::from_millis(1000), "{:?}", elapsed);
+    assert_eq!(events, session_events());
+}
+
+#[tokio::test]
+async fn serves_and_records_session() {
+    let recording = Recording::load(SESSION).unwrap();
+    let listener = TcpListener::bind("127.0.0.1:18851").await.unwrap();
+    let server = {
+        let recording = recording.clone();
+        tokio::spawn(async move { recording.serve(listener, 0.0).await })
+    };
+
+    let path = std::env::temp_dir().join("flicbtn-record-test.jsonl");
+    let _ = std::fs::remove_file(&path);
+    let recorder = Recorder::create(&path).unwrap();
+    let (tx, mut rx) = unbounded_channel();
+    let client = FlicClient::new("127.0.0.1:18851")
+        .await
+        .unwrap()
+        .register_event_handler(event_handler(move |event| {
+            let _ = tx.send(event.clone());
+        }))
+        .await
+        .register_packet_handler(recorder.handler())
+        .await;
+    let client = Arc::new(client);
+    let listener = {
+        let client = client.clone();
+        tokio::spawn(async move { client.listen().await })
+    };
+
+    client.submit(Command::GetInfo).await;
+    let mut events = vec![];
+    events.push(
+        timeout(Duration::from_secs(5), rx.recv())
+            .await
+            .unwrap()
+            .unwrap(),
+    );
+    client
+        .submit(Command::CreateConnectionChannel {
+            conn_id: 1,
+            bd_addr: BUTTON.to_string(),
+            latency_mode: LatencyMode::NormalLatency,
+            auto_disconnect_time: 511,
+        })
+        .await;
+    for _ in 0..3 {
+        events.push(
+            timeout(Duration::from_secs(5), rx.recv())
+                .await
+                .unwrap()
+                .unwrap(),
+        );
+    }
+    assert_eq!(events, session_events());
+    server.await.unwrap().unwrap();
+    client.stop().await;
+    listener.await.unwrap();
+
+    let recorded = Recording::load(&path).unwrap();
+    let packets = |recording: &Recording| -> Vec<(PacketDirection, String)> {
+        recording
+            .packets
+            .iter()
+            .map(|packet| (packet.dir, packet.data.clone()))
+            .collect()
+    };
+    assert_eq!(packets(&recorded), packets(&recording));
+}
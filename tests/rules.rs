@@ -0,0 +1,315 @@
+#![cfg(feature = "rules")]
+
+use std::fs;
+use std::time::Duration;
+
+use chrono::Local;
+
+use flicbtn::*;
+
+const KITCHEN: &str = "80:e4:da:76:fa:55";
+const HALL: &str = "80:e4:da:76:fa:56";
+
+/// A gesture on a connection channel of the engine. Buttons get channels
+/// 1, 2, .. in the order of their addresses.
+fn gesture(conn_id: u32, click_type: ClickType) -> Event {
+    let kind = match click_type {
+        ClickType::ButtonClick => ButtonEventKind::ClickOrHold,
+        _ => ButtonEventKind::SingleOrDoubleClickOrHold,
+    };
+    Event::Button(ButtonEvent {
+        kind,
+        conn_id,
+        click_type,
+        was_queued: false,
+        time_diff: 0,
+    })
+}
+
+fn engine(toml: &str) -> RuleEngine {
+    RuleEngine::new(&RulesConfig::from_toml_str(toml).unwrap()).unwrap()
+}
+
+fn fired(engine: &RuleEngine, event: &Event) -> Vec<String> {
+    engine
+        .handle(event)
+        .into_iter()
+        .map(|trigger| match trigger.action {
+            Action::Exec(cmd) => cmd,
+        })
+        .collect()
+}
+
+#[test]
+fn parses_toml_and_yaml() {
+    let toml = RulesConfig::from_toml_str(
+        r#"
+        [buttons]
+        kitchen = "80:e4:da:76:fa:55"
+
+        [[rule]]
+        button = "kitchen"
+        click = "double"
+        action = { exec = "echo hello" }
+        debounce_ms = 500
+        when = { after = "07:00", before = "22:00", min_battery = 10, connection = "ready" }
+        "#,
+    )
+    .unwrap();
+    let yaml = RulesConfig::from_yaml_str(
+        r#"
+buttons:
+  kitchen: "80:e4:da:76:fa:55"
+rule:
+  - button: kitchen
+    click: double
+    action:
+      exec: echo hello
+    debounce_ms: 500
+    when:
+      after: "07:00"
+      before: "22:00"
+      min_battery: 10
+      connection: ready
+"#,
+    )
+    .unwrap();
+    for config in [toml, yaml].iter() {
+        assert_eq!(config.buttons["kitchen"], KITCHEN);
+        let rule = &config.rules[0];
+        assert_eq!(rule.button.as_deref(), Some("kitchen"));
+        assert_eq!(rule.click, "double");
+        assert_eq!(rule.action, Action::Exec("echo hello".to_string()));
+        assert_eq!(rule.debounce_ms, Some(500));
+        assert_eq!(rule.when.after.as_deref(), Some("07:00"));
+        assert_eq!(rule.when.before.as_deref(), Some("22:00"));
+        assert_eq!(rule.when.min_battery, Some(10));
+        assert_eq!(rule.when.connection.as_deref(), Some("ready"));
+    }
+
+    let invalid = |toml: &str| RuleEngine::new(&RulesConfig::from_toml_str(toml).unwrap());
+    assert!(matches!(
+        invalid(
+            r#"[[rule]]
+            button = "attic"
+            click = "click"
+            action = { exec = "true" }"#
+        ),
+        Err(RulesError::UnknownButton(_))
+    ));
+    assert!(matches!(
+        invalid(
+            r#"[[rule]]
+            click = "triple"
+            action = { exec = "true" }"#
+        ),
+        Err(RulesError::UnknownClick(_))
+    ));
+    assert!(matches!(
+        invalid(
+            r#"[[rule]]
+            click = "click"
+            action = { exec = "true" }
+            when = { after = "7 o'clock" }"#
+        ),
+        Err(RulesError::InvalidTime(_))
+    ));
+}
+
+#[test]
+fn matches_click_and_button() {
+    let engine = engine(&format!(
+        r#"
+        [buttons]
+        kitchen = "{}"
+        hall = "{}"
+
+        [[rule]]
+        button = "kitchen"
+        click = "double"
+        action = {{ exec = "kitchen" }}
+
+        [[rule]]
+        click = "click"
+        action = {{ exec = "any" }}
+        "#,
+        KITCHEN, HALL
+    ));
+    assert_eq!(engine.setup_commands().len(), 4);
+
+    assert_eq!(
+        fired(&engine, &gesture(1, ClickType::ButtonDoubleClick)),
+        vec!["kitchen"]
+    );
+    assert!(fired(&engine, &gesture(2, ClickType::ButtonDoubleClick)).is_empty());
+    assert!(fired(&engine, &gesture(1, ClickType::ButtonHold)).is_empty());
+    assert_eq!(
+        fired(&engine, &gesture(2, ClickType::ButtonClick)),
+        vec!["any"]
+    );
+    // a channel the engine did not create
+    assert!(fired(&engine, &gesture(9, ClickType::ButtonClick)).is_empty());
+
+    let trigger = &engine.handle(&gesture(2, ClickType::ButtonClick))[0];
+    assert_eq!(trigger.bd_addr, HALL);
+    assert!(trigger
+        .env()
+        .contains(&("FLIC_CLICK_TYPE".to_string(), "ButtonClick".to_string())));
+}
+
+#[test]
+fn matches_time_of_day() {
+    let hour = |offset: i64| {
+        (Local::now() + chrono::Duration::hours(offset))
+            .format("%H:%M")
+            .to_string()
+    };
+    let engine = engine(&format!(
+        r#"
+        [[rule]]
+        button = "{button}"
+        click = "click"
+        action = {{ exec = "now" }}
+        when = {{ after = "{}", before = "{}" }}
+
+        [[rule]]
+        button = "{button}"
+        click = "click"
+        action = {{ exec = "later" }}
+        when = {{ after = "{}", before = "{}" }}
+        "#,
+        hour(-1),
+        hour(1),
+        hour(2),
+        hour(3),
+        button = KITCHEN,
+    ));
+    assert_eq!(
+        fired(&engine, &gesture(1, ClickType::ButtonClick)),
+        vec!["now"]
+    );
+}
+
+#[test]
+fn matches_battery_and_connection() {
+    let engine = engine(&format!(
+        r#"
+        [[rule]]
+        button = "{button}"
+        click = "click"
+        action = {{ exec = "charged" }}
+        when = {{ min_battery = 50 }}
+
+        [[rule]]
+        button = "{button}"
+        click = "click"
+        action = {{ exec = "low" }}
+        when = {{ max_battery = 20 }}
+
+        [[rule]]
+        button = "{button}"
+        click = "click"
+        action = {{ exec = "ready" }}
+        when = {{ connection = "ready" }}
+        "#,
+        button = KITCHEN,
+    ));
+    let click = gesture(1, ClickType::ButtonClick);
+    // nothing is known about the button yet
+    assert!(fired(&engine, &click).is_empty());
+
+    let battery = |battery_percentage| {
+        Event::BatteryStatus(BatteryStatus {
+            listener_id: 1,
+            battery_percentage,
+            timestamp: 0,
+        })
+    };
+    engine.handle(&battery(80));
+    assert_eq!(fired(&engine, &click), vec!["charged"]);
+    engine.handle(&battery(10));
+    assert_eq!(fired(&engine, &click), vec!["low"]);
+    // -1 means unknown
+    engine.handle(&battery(-1));
+    assert!(fired(&engine, &click).is_empty());
+
+    engine.handle(&Event::ConnectionStatusChanged(ConnectionStatusChanged {
+        conn_id: 1,
+        connection_status: ConnectionStatus::Ready,
+        disconnect_reason: DisconnectReason::Unspecified,
+    }));
+    assert_eq!(fired(&engine, &click), vec!["ready"]);
+}
+
+#[test]
+fn debounces_per_button() {
+    let engine = engine(&format!(
+        r#"
+        [buttons]
+        kitchen = "{}"
+        hall = "{}"
+
+        [[rule]]
+        click = "click"
+        action = {{ exec = "any" }}
+        debounce_ms = 60000
+        "#,
+        KITCHEN, HALL
+    ));
+    let click = gesture(1, ClickType::ButtonClick);
+    assert_eq!(fired(&engine, &click), vec!["any"]);
+    assert!(fired(&engine, &click).is_empty());
+    assert_eq!(
+        fired(&engine, &gesture(2, ClickType::ButtonClick)),
+        vec!["any"]
+    );
+}
+
+#[test]
+fn reloads_changed_file() {
+    let path = std::env::temp_dir().join(format!("flicbtn-rules-{}.toml", std::process::id()));
+    let rules = |button: &str| {
+        format!(
+            "[[rule]]\nbutton = \"{}\"\nclick = \"click\"\naction = {{ exec = \"true\" }}\n",
+            button
+        )
+    };
+    fs::write(&path, rules(KITCHEN)).unwrap();
+    let engine = RuleEngine::from_file(&path).unwrap();
+    assert!(engine.reload_if_changed().unwrap().is_empty());
+
+    std::thread::sleep(Duration::from_millis(20));
+    fs::write(&path, rules(HALL)).unwrap();
+    let commands = engine.reload_if_changed().unwrap();
+    assert_eq!(
+        commands,
+        vec![
+            Command::RemoveBatteryStatusListener { listener_id: 1 },
+            Command::RemoveConnectionChannel { conn_id: 1 },
+            Command::CreateConnectionChannel {
+                conn_id: 2,
+                bd_addr: HALL.to_string(),
+                latency_mode: LatencyMode::NormalLatency,
+                auto_disconnect_time: 511,
+            },
+            Command::CreateBatteryStatusListener {
+                listener_id: 2,
+                bd_addr: HALL.to_string(),
+            },
+        ]
+    );
+    assert!(engine.reload_if_changed().unwrap().is_empty());
+
+    // a broken file keeps the rules
+    std::thread::sleep(Duration::from_millis(20));
+    fs::write(
+        &path,
+        "[[rule]]\nclick = \"triple\"\naction = { exec = \"true\" }\n",
+    )
+    .unwrap();
+    assert!(engine.reload_if_changed().is_err());
+    assert_eq!(engine.handle(&gesture(2, ClickType::ButtonClick)).len(), 1);
+    // and is tried again until it loads
+    assert!(engine.reload_if_changed().is_err());
+    fs::remove_file(&path).unwrap();
+}
@@ -0,0 +1,346 @@
+#![cfg(feature = "serde")]
+
+use std::fmt::Debug;
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use flicbtn::*;
+
+const BUTTON: &str = "80:e4:da:76:fa:55";
+
+/// Checks the JSON of a value and that it deserializes back to the value.
+fn snapshot<T>(value: T, json: &str)
+where
+    T: Serialize + DeserializeOwned + PartialEq + Debug,
+{
+    assert_eq!(serde_json::to_string(&value).unwrap(), json);
+    assert_eq!(serde_json::from_str::<T>(json).unwrap(), value);
+}
+
+#[test]
+fn enums() {
+    snapshot(ClickType::ButtonSingleClick, r#""ButtonSingleClick""#);
+    snapshot(ConnectionStatus::Ready, r#""Ready""#);
+    snapshot(
+        CreateConnectionChannelError::MaxPendingConnectionsReached,
+        r#""MaxPendingConnectionsReached""#,
+    );
+    snapshot(DisconnectReason::TimedOut, r#""TimedOut""#);
+    snapshot(
+        RemovedReason::DeletedByThisClient,
+        r#""DeletedByThisClient""#,
+    );
+    snapshot(BdAddrType::PublicBdAddrType, r#""PublicBdAddrType""#);
+    snapshot(LatencyMode::LowLatency, r#""LowLatency""#);
+    snapshot(ScanWizardResult::WizardSuccess, r#""WizardSuccess""#);
+    snapshot(BluetoothControllerState::Attached, r#""Attached""#);
+}
+
+#[test]
+fn commands() {
+    snapshot(Command::GetInfo, r#"{"command":"get_info"}"#);
+    snapshot(
+        Command::CreateScanner { scan_id: 1 },
+        r#"{"command":"create_scanner","scan_id":1}"#,
+    );
+    snapshot(
+        Command::RemoveScanner { scan_id: 1 },
+        r#"{"command":"remove_scanner","scan_id":1}"#,
+    );
+    snapshot(
+        Command::CreateConnectionChannel {
+            conn_id: 2,
+            bd_addr: BUTTON.to_string(),
+            latency_mode: LatencyMode::NormalLatency,
+            auto_disconnect_time: 511,
+        },
+        r#"{"command":"create_connection_channel","conn_id":2,"bd_addr":"80:e4:da:76:fa:55","latency_mode":"NormalLatency","auto_disconnect_time":511}"#,
+    );
+    snapshot(
+        Command::RemoveConnectionChannel { conn_id: 2 },
+        r#"{"command":"remove_connection_channel","conn_id":2}"#,
+    );
+    snapshot(
+        Command::ForceDisconnect {
+            bd_addr: BUTTON.to_string(),
+        },
+        r#"{"command":"force_disconnect","bd_addr":"80:e4:da:76:fa:55"}"#,
+    );
+    snapshot(
+        Command::ChangeModeParameters {
+            conn_id: 2,
+            latency_mode: LatencyMode::LowLatency,
+            auto_disconnect_time: 60,
+        },
+        r#"{"command":"change_mode_parameters","conn_id":2,"latency_mode":"LowLatency","auto_disconnect_time":60}"#,
+    );
+    snapshot(
+        Command::Ping { ping_id: 3 },
+        r#"{"command":"ping","ping_id":3}"#,
+    );
+    snapshot(
+        Command::GetButtonInfo {
+            bd_addr: BUTTON.to_string(),
+        },
+        r#"{"command":"get_button_info","bd_addr":"80:e4:da:76:fa:55"}"#,
+    );
+    snapshot(
+        Command::CreateScanWizard { scan_wizard_id: 4 },
+        r#"{"command":"create_scan_wizard","scan_wizard_id":4}"#,
+    );
+    snapshot(
+        Command::CancelScanWizard { scan_wizard_id: 4 },
+        r#"{"command":"cancel_scan_wizard","scan_wizard_id":4}"#,
+    );
+    snapshot(
+        Command::DeleteButton {
+            bd_addr: BUTTON.to_string(),
+        },
+        r#"{"command":"delete_button","bd_addr":"80:e4:da:76:fa:55"}"#,
+    );
+    snapshot(
+        Command::CreateBatteryStatusListener {
+            listener_id: 5,
+            bd_addr: BUTTON.to_string(),
+        },
+        r#"{"command":"create_battery_status_listener","listener_id":5,"bd_addr":"80:e4:da:76:fa:55"}"#,
+    );
+    snapshot(
+        Command::RemoveBatteryStatusListener { listener_id: 5 },
+        r#"{"command":"remove_battery_status_listener","listener_id":5}"#,
+    );
+}
+
+#[test]
+fn connection_events() {
+    snapshot(Event::NoOp, r#"{"event":"no_op"}"#);
+    snapshot(Event::CorruptEvent, r#"{"event":"corrupt_event"}"#);
+    snapshot(
+        Event::AdvertisementPacket(AdvertisementPacket {
+            scan_id: 1,
+            bd_addr: BUTTON.to_string(),
+            name: "F2".to_string(),
+            rssi: 200,
+            is_private: false,
+            already_verified: true,
+            already_connected_to_this_device: false,
+            already_connected_to_other_device: false,
+        }),
+        r#"{"event":"advertisement_packet","scan_id":1,"bd_addr":"80:e4:da:76:fa:55","name":"F2","rssi":200,"is_private":false,"already_verified":true,"already_connected_to_this_device":false,"already_connected_to_other_device":false}"#,
+    );
+    snapshot(
+        Event::CreateConnectionChannelResponse(CreateConnectionChannelResponse {
+            conn_id: 2,
+            error: CreateConnectionChannelError::NoError,
+            connection_status: ConnectionStatus::Disconnected,
+        }),
+        r#"{"event":"create_connection_channel_response","conn_id":2,"error":"NoError","connection_status":"Disconnected"}"#,
+    );
+    snapshot(
+        Event::ConnectionStatusChanged(ConnectionStatusChanged {
+            conn_id: 2,
+            connection_status: ConnectionStatus::Ready,
+            disconnect_reason: DisconnectReason::Unspecified,
+        }),
+        r#"{"event":"connection_status_changed","conn_id":2,"connection_status":"Ready","disconnect_reason":"Unspecified"}"#,
+    );
+    snapshot(
+        Event::ConnectionChannelRemoved(ConnectionChannelRemoved {
+            conn_id: 2,
+            removed_reason: RemovedReason::RemovedByThisClient,
+        }),
+        r#"{"event":"connection_channel_removed","conn_id":2,"removed_reason":"RemovedByThisClient"}"#,
+    );
+    snapshot(
+        Event::NoSpaceForNewConnection(NoSpaceForNewConnection {
+            max_concurrently_connected_buttons: 10,
+        }),
+        r#"{"event":"no_space_for_new_connection","max_concurrently_connected_buttons":10}"#,
+    );
+    snapshot(
+        Event::GotSpaceForNewConnection(GotSpaceForNewConnection {
+            max_concurrently_connected_buttons: 10,
+        }),
+        r#"{"event":"got_space_for_new_connection","max_concurrently_connected_buttons":10}"#,
+    );
+}
+
+#[test]
+fn button_events() {
+    snapshot(
+        Event::Button(ButtonEvent {
+            kind: ButtonEventKind::UpOrDown,
+            conn_id: 2,
+            click_type: ClickType::ButtonDown,
+            was_queued: false,
+            time_diff: 0,
+        }),
+        r#"{"event":"button_up_or_down","conn_id":2,"click_type":"ButtonDown","was_queued":false,"time_diff":0}"#,
+    );
+    snapshot(
+        Event::Button(ButtonEvent {
+            kind: ButtonEventKind::ClickOrHold,
+            conn_id: 2,
+            click_type: ClickType::ButtonClick,
+            was_queued: true,
+            time_diff: 3,
+        }),
+        r#"{"event":"button_click_or_hold","conn_id":2,"click_type":"ButtonClick","was_queued":true,"time_diff":3}"#,
+    );
+    snapshot(
+        Event::Button(ButtonEvent {
+            kind: ButtonEventKind::SingleOrDoubleClick,
+            conn_id: 2,
+            click_type: ClickType::ButtonDoubleClick,
+            was_queued: false,
+            time_diff: 0,
+        }),
+        r#"{"event":"button_single_or_double_click","conn_id":2,"click_type":"ButtonDoubleClick","was_queued":false,"time_diff":0}"#,
+    );
+    snapshot(
+        Event::Button(ButtonEvent {
+            kind: ButtonEventKind::SingleOrDoubleClickOrHold,
+            conn_id: 2,
+            click_type: ClickType::ButtonHold,
+            was_queued: false,
+            time_diff: 0,
+        }),
+        r#"{"event":"button_single_or_double_click_or_hold","conn_id":2,"click_type":"ButtonHold","was_queued":false,"time_diff":0}"#,
+    );
+    snapshot(
+        Event::BatteryStatus(BatteryStatus {
+            listener_id: 5,
+            battery_percentage: 87,
+            timestamp: 1600000000,
+        }),
+        r#"{"event":"battery_status","listener_id":5,"battery_percentage":87,"timestamp":1600000000}"#,
+    );
+}
+
+#[test]
+fn server_events() {
+    snapshot(
+        Event::NewVerifiedButton(NewVerifiedButton {
+            bd_addr: BUTTON.to_string(),
+        }),
+        r#"{"event":"new_verified_button","bd_addr":"80:e4:da:76:fa:55"}"#,
+    );
+    snapshot(
+        Event::GetInfoResponse(GetInfoResponse {
+            bluetooth_controller_state: BluetoothControllerState::Attached,
+            my_bd_addr: "00:1a:7d:da:71:13".to_string(),
+            my_bd_addr_type: BdAddrType::PublicBdAddrType,
+            max_pending_connections: 2,
+            max_concurrently_connected_buttons: -1,
+            current_pending_connections: 0,
+            currently_no_space_for_new_connection: false,
+            bd_addr_of_verified_buttons: vec![BUTTON.to_string()],
+        }),
+        r#"{"event":"get_info_response","bluetooth_controller_state":"Attached","my_bd_addr":"00:1a:7d:da:71:13","my_bd_addr_type":"PublicBdAddrType","max_pending_connections":2,"max_concurrently_connected_buttons":-1,"current_pending_connections":0,"currently_no_space_for_new_connection":false,"bd_addr_of_verified_buttons":["80:e4:da:76:fa:55"]}"#,
+    );
+    snapshot(
+        Event::BluetoothControllerStateChange(BluetoothControllerStateChange {
+            state: BluetoothControllerState::Detached,
+        }),
+        r#"{"event":"bluetooth_controller_state_change","state":"Detached"}"#,
+    );
+    snapshot(
+        Event::PingResponse(PingResponse { ping_id: 3 }),
+        r#"{"event":"ping_response","ping_id":3}"#,
+    );
+    snapshot(
+        Event::GetButtonInfoResponse(GetButtonInfoResponse {
+            bd_addr: BUTTON.to_string(),
+            uuid: "0102030405060708090a0b0c0d0e0f10".to_string(),
+            color: Some("black".to_string()),
+            serial_number: None,
+            flic_version: Some(2),
+            firmware_version: Some(9),
+        }),
+        r#"{"event":"get_button_info_response","bd_addr":"80:e4:da:76:fa:55","uuid":"0102030405060708090a0b0c0d0e0f10","color":"black","serial_number":null,"flic_version":2,"firmware_version":9}"#,
+    );
+    snapshot(
+        Event::ButtonDeleted(ButtonDeleted {
+            bd_addr: BUTTON.to_string(),
+            deleted_by_this_client: true,
+        }),
+        r#"{"event":"button_deleted","bd_addr":"80:e4:da:76:fa:55","deleted_by_this_client":true}"#,
+    );
+}
+
+#[test]
+fn scan_wizard_events() {
+    snapshot(
+        Event::ScanWizardFoundPrivateButton(ScanWizardFoundPrivateButton { scan_wizard_id: 4 }),
+        r#"{"event":"scan_wizard_found_private_button","scan_wizard_id":4}"#,
+    );
+    snapshot(
+        Event::ScanWizardFoundPublicButton(ScanWizardFoundPublicButton {
+            scan_wizard_id: 4,
+            bd_addr: BUTTON.to_string(),
+            name: "F2".to_string(),
+        }),
+        r#"{"event":"scan_wizard_found_public_button","scan_wizard_id":4,"bd_addr":"80:e4:da:76:fa:55","name":"F2"}"#,
+    );
+    snapshot(
+        Event::ScanWizardButtonConnected(ScanWizardButtonConnected { scan_wizard_id: 4 }),
+        r#"{"event":"scan_wizard_button_connected","scan_wizard_id":4}"#,
+    );
+    snapshot(
+        Event::ScanWizardCompleted(ScanWizardCompleted {
+            scan_wizard_id: 4,
+            result: ScanWizardResult::WizardFailedTimeout,
+        }),
+        r#"{"event":"scan_wizard_completed","scan_wizard_id":4,"result":"WizardFailedTimeout"}"#,
+    );
+}
+
+#[test]
+fn state_changes() {
+    snapshot(
+        StateChange::ButtonVerified {
+            bd_addr: BUTTON.to_string(),
+        },
+        r#"{"change":"button_verified","bd_addr":"80:e4:da:76:fa:55"}"#,
+    );
+    snapshot(
+        StateChange::ChannelStatus {
+            conn_id: 2,
+            bd_addr: Some(BUTTON.to_string()),
+            connection_status: ConnectionStatus::Disconnected,
+            disconnect_reason: Some(DisconnectReason::TimedOut),
+        },
+        r#"{"change":"channel_status","conn_id":2,"bd_addr":"80:e4:da:76:fa:55","connection_status":"Disconnected","disconnect_reason":"TimedOut"}"#,
+    );
+}
+
+#[test]
+fn outages() {
+    snapshot(
+        Outage::Started {
+            state: BluetoothControllerState::Resetting,
+            scanners: vec![1],
+            scan_wizards: vec![],
+        },
+        r#"{"outage":"started","state":"Resetting","scanners":[1],"scan_wizards":[]}"#,
+    );
+}
+
+#[test]
+fn unknown_events_and_values() {
+    snapshot(
+        Event::Unknown {
+            opcode: 42,
+            payload: vec![1, 2],
+        },
+        r#"{"event":"unknown","opcode":42,"payload":[1,2]}"#,
+    );
+    snapshot(
+        Event::ConnectionChannelRemoved(ConnectionChannelRemoved {
+            conn_id: 2,
+            removed_reason: RemovedReason::Other(99),
+        }),
+        r#"{"event":"connection_channel_removed","conn_id":2,"removed_reason":{"Other":99}}"#,
+    );
+}
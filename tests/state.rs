@@ -0,0 +1,234 @@
+use std::sync::{Arc, Mutex};
+
+use flicbtn::*;
+
+const BUTTON: &str = "80:e4:da:76:fa:55";
+const OTHER: &str = "80:e4:da:70:00:01";
+
+fn info(verified: &[&str], pending: u8) -> Event {
+    Event::GetInfoResponse(GetInfoResponse {
+        bluetooth_controller_state: BluetoothControllerState::Attached,
+        my_bd_addr: "00:1a:7d:da:71:13".to_string(),
+        my_bd_addr_type: BdAddrType::PublicBdAddrType,
+        max_pending_connections: 2,
+        max_concurrently_connected_buttons: -1,
+        current_pending_connections: pending,
+        currently_no_space_for_new_connection: false,
+        bd_addr_of_verified_buttons: verified.iter().map(|b| b.to_string()).collect(),
+    })
+}
+
+fn status(connection_status: ConnectionStatus, disconnect_reason: DisconnectReason) -> Event {
+    Event::ConnectionStatusChanged(ConnectionStatusChanged {
+        conn_id: 1,
+        connection_status,
+        disconnect_reason,
+    })
+}
+
+#[test]
+fn diffs_server_info() {
+    let mut snapshot = ServerSnapshot::default();
+    assert_eq!(
+        snapshot.apply(&info(&[BUTTON], 0), None),
+        vec![
+            StateChange::ControllerState {
+                state: BluetoothControllerState::Attached
+            },
+            StateChange::ButtonVerified {
+                bd_addr: BUTTON.to_string()
+            },
+            StateChange::PendingConnections { current: 0, max: 2 },
+            StateChange::Space {
+                no_space_for_new_connection: false,
+                max_concurrently_connected_buttons: -1
+            },
+        ]
+    );
+    assert_eq!(snapshot.apply(&info(&[BUTTON], 0), None), vec![]);
+    assert_eq!(
+        snapshot.apply(&info(&[OTHER], 1), None),
+        vec![
+            StateChange::ButtonRemoved {
+                bd_addr: BUTTON.to_string()
+            },
+            StateChange::ButtonVerified {
+                bd_addr: OTHER.to_string()
+            },
+            StateChange::PendingConnections { current: 1, max: 2 },
+        ]
+    );
+    assert_eq!(
+        snapshot.apply(
+            &Event::BluetoothControllerStateChange(BluetoothControllerStateChange {
+                state: BluetoothControllerState::Detached
+            }),
+            None
+        ),
+        vec![StateChange::ControllerState {
+            state: BluetoothControllerState::Detached
+        }]
+    );
+    assert_eq!(
+        snapshot.apply(
+            &Event::NoSpaceForNewConnection(NoSpaceForNewConnection {
+                max_concurrently_connected_buttons: 10
+            }),
+            None
+        ),
+        vec![StateChange::Space {
+            no_space_for_new_connection: true,
+            max_concurrently_connected_buttons: 10
+        }]
+    );
+    assert_eq!(snapshot.my_bd_addr.as_deref(), Some("00:1a:7d:da:71:13"));
+    assert!(snapshot.no_space_for_new_connection);
+}
+
+#[test]
+fn tracks_channels_of_a_client() {
+    let channels = ChannelMap::new();
+    channels.track_command(&Command::CreateConnectionChannel {
+        conn_id: 1,
+        bd_addr: BUTTON.to_string(),
+        latency_mode: LatencyMode::NormalLatency,
+        auto_disconnect_time: 511,
+    });
+    let changes = Arc::new(Mutex::new(vec![]));
+    let state = {
+        let changes = changes.clone();
+        ServerState::new()
+            .channels(channels.clone())
+            .register_change_handler(state_change_handler(move |change| {
+                changes.lock().unwrap().push(change.clone())
+            }))
+    };
+    let mut handler = state.handler();
+
+    handler(&Event::CreateConnectionChannelResponse(
+        CreateConnectionChannelResponse {
+            conn_id: 1,
+            error: CreateConnectionChannelError::NoError,
+            connection_status: ConnectionStatus::Disconnected,
+        },
+    ));
+    handler(&status(
+        ConnectionStatus::Ready,
+        DisconnectReason::Unspecified,
+    ));
+    handler(&status(
+        ConnectionStatus::Ready,
+        DisconnectReason::Unspecified,
+    ));
+    assert_eq!(
+        state.connection_status(BUTTON),
+        Some(ConnectionStatus::Ready)
+    );
+    handler(&status(
+        ConnectionStatus::Disconnected,
+        DisconnectReason::TimedOut,
+    ));
+    handler(&status(
+        ConnectionStatus::Connected,
+        DisconnectReason::Unspecified,
+    ));
+
+    let channel = state.snapshot().channels[&1].clone();
+    assert_eq!(channel.bd_addr.as_deref(), Some(BUTTON));
+    assert_eq!(channel.connection_status, ConnectionStatus::Connected);
+    assert_eq!(
+        channel.last_disconnect_reason,
+        Some(DisconnectReason::TimedOut)
+    );
+
+    // the client forgets the channel before the handlers see the removal
+    let removed = Event::ConnectionChannelRemoved(ConnectionChannelRemoved {
+        conn_id: 1,
+        removed_reason: RemovedReason::RemovedByThisClient,
+    });
+    channels.track_event(&removed);
+    handler(&removed);
+    assert_eq!(state.connection_status(BUTTON), None);
+
+    let channel_status = |connection_status, disconnect_reason| StateChange::ChannelStatus {
+        conn_id: 1,
+        bd_addr: Some(BUTTON.to_string()),
+        connection_status,
+        disconnect_reason,
+    };
+    assert_eq!(
+        *changes.lock().unwrap(),
+        vec![
+            channel_status(ConnectionStatus::Disconnected, None),
+            channel_status(ConnectionStatus::Ready, None),
+            channel_status(
+                ConnectionStatus::Disconnected,
+                Some(DisconnectReason::TimedOut)
+            ),
+            channel_status(ConnectionStatus::Connected, None),
+            StateChange::ChannelRemoved {
+                conn_id: 1,
+                bd_addr: Some(BUTTON.to_string()),
+                removed_reason: RemovedReason::RemovedByThisClient,
+            },
+        ]
+    );
+}
+
+#[test]
+fn follows_verified_buttons() {
+    let state = ServerState::new();
+    state.apply(&info(&[BUTTON], 0));
+    state.apply(&Event::NewVerifiedButton(NewVerifiedButton {
+        bd_addr: OTHER.to_uppercase(),
+    }));
+    assert!(state.is_verified(OTHER));
+    state.apply(&Event::ButtonDeleted(ButtonDeleted {
+        bd_addr: BUTTON.to_string(),
+        deleted_by_this_client: false,
+    }));
+    assert!(!state.is_verified(BUTTON));
+    assert_eq!(
+        state.bluetooth_controller_state(),
+        Some(BluetoothControllerState::Attached)
+    );
+}
+
+#[test]
+fn controller_outage_disconnects_channels() {
+    let controller =
+        |state| Event::BluetoothControllerStateChange(BluetoothControllerStateChange { state });
+    let mut snapshot = ServerSnapshot::default();
+    snapshot.apply(&controller(BluetoothControllerState::Attached), None);
+    snapshot.apply(
+        &status(ConnectionStatus::Ready, DisconnectReason::Unspecified),
+        Some(BUTTON.to_string()),
+    );
+    assert_eq!(
+        snapshot.apply(&controller(BluetoothControllerState::Resetting), None),
+        vec![
+            StateChange::ControllerState {
+                state: BluetoothControllerState::Resetting
+            },
+            StateChange::ChannelStatus {
+                conn_id: 1,
+                bd_addr: Some(BUTTON.to_string()),
+                connection_status: ConnectionStatus::Disconnected,
+                disconnect_reason: None,
+            },
+        ]
+    );
+    assert_eq!(
+        snapshot.apply(&controller(BluetoothControllerState::Detached), None),
+        vec![StateChange::ControllerState {
+            state: BluetoothControllerState::Detached
+        }]
+    );
+
+    let state = ServerState::new();
+    assert!(state.is_controller_available());
+    state.apply(&controller(BluetoothControllerState::Detached));
+    assert!(!state.is_controller_available());
+    state.apply(&controller(BluetoothControllerState::Attached));
+    assert!(state.is_controller_available());
+}
@@ -0,0 +1,230 @@
+#![cfg(feature = "webhook")]
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use serde_json::Value;
+use tokio::net::TcpListener;
+use tokio::prelude::*;
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver};
+use tokio::time::timeout;
+
+use flicbtn::*;
+
+const BUTTON: &str = "80:e4:da:76:fa:55";
+
+struct Request {
+    headers: HashMap<String, String>,
+    body: String,
+}
+
+/// A minimal HTTP server answering with the given statuses in turn, then
+/// with 200, and reporting every request it receives.
+async fn stand_in(port: u16, statuses: Vec<u16>) -> UnboundedReceiver<Request> {
+    let mut listener = TcpListener::bind(("127.0.0.1", port)).await.unwrap();
+    let statuses = Arc::new(Mutex::new(statuses));
+    let (tx, rx) = unbounded_channel();
+    tokio::spawn(async move {
+        loop {
+            let (mut stream, _) = match listener.accept().await {
+                Ok(conn) => conn,
+                Err(_) => return,
+            };
+            let mut data = vec![];
+            let mut buf = [0; 1024];
+            let (head, body_start) = loop {
+                let n = stream.read(&mut buf).await.unwrap();
+                if n == 0 {
+                    break (String::new(), 0);
+                }
+                data.extend_from_slice(&buf[..n]);
+                if let Some(pos) = data.windows(4).position(|w| w == b"\r\n\r\n") {
+                    break (String::from_utf8_lossy(&data[..pos]).to_string(), pos + 4);
+                }
+            };
+            let headers: HashMap<String, String> = head
+                .lines()
+                .skip(1)
+                .filter_map(|line| {
+                    let mut parts = line.splitn(2, ':');
+                    Some((
+                        parts.next()?.trim().to_lowercase(),
+                        parts.next()?.trim().to_string(),
+                    ))
+                })
+                .collect();
+            let length: usize = headers
+                .get("content-length")
+                .and_then(|l| l.parse().ok())
+                .unwrap_or(0);
+            while data.len() < body_start + length {
+                let n = stream.read(&mut buf).await.unwrap();
+                data.extend_from_slice(&buf[..n]);
+            }
+            let body = String::from_utf8_lossy(&data[body_start..body_start + length]).to_string();
+            let status = {
+                let mut statuses = statuses.lock().unwrap();
+                if statuses.is_empty() {
+                    200
+                } else {
+                    statuses.remove(0)
+                }
+            };
+            let response = format!(
+                "HTTP/1.1 {} X\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+                status
+            );
+            let _ = stream.write_all(response.as_bytes()).await;
+            let _ = tx.send(Request { headers, body });
+        }
+    });
+    rx
+}
+
+fn channels() -> ChannelMap {
+    let channels = ChannelMap::new();
+    channels.track_command(&Command::CreateConnectionChannel {
+        conn_id: 7,
+        bd_addr: BUTTON.to_string(),
+        latency_mode: LatencyMode::NormalLatency,
+        auto_disconnect_time: 511,
+    });
+    channels
+}
+
+fn click(click_type: ClickType) -> Event {
+    Event::Button(ButtonEvent {
+        kind: ButtonEventKind::SingleOrDoubleClickOrHold,
+        conn_id: 7,
+        click_type,
+        was_queued: false,
+        time_diff: 0,
+    })
+}
+
+async fn next(rx: &mut UnboundedReceiver<Request>) -> Request {
+    timeout(Duration::from_secs(10), rx.recv())
+        .await
+        .expect("no request received")
+        .unwrap()
+}
+
+#[tokio::test]
+async fn posts_templated_signed_body() {
+    let mut requests = stand_in(18841, vec![]).await;
+    let dispatcher = WebhookDispatcher::new(vec![Webhook::new("http://127.0.0.1:18841/hook")
+        .for_button(BUTTON)
+        .on_click(ClickType::ButtonDoubleClick)
+        .body(r#"{"button": "{{bd_addr}}", "click": "{{click_type}}", "queued": {{was_queued}}}"#)
+        .header("X-Token", "abc")
+        .secret("s3cret")])
+    .channels(channels());
+    let mut handler = dispatcher.handler();
+    let runner = dispatcher.clone();
+    tokio::spawn(async move { runner.run().await });
+
+    handler(&click(ClickType::ButtonSingleClick));
+    handler(&Event::Button(ButtonEvent {
+        kind: ButtonEventKind::UpOrDown,
+        conn_id: 7,
+        click_type: ClickType::ButtonDown,
+        was_queued: false,
+        time_diff: 0,
+    }));
+    handler(&click(ClickType::ButtonDoubleClick));
+
+    let request = next(&mut requests).await;
+    let body: Value = serde_json::from_str(&request.body).unwrap();
+    assert_eq!(body["button"], BUTTON);
+    assert_eq!(body["click"], "ButtonDoubleClick");
+    assert_eq!(body["queued"], false);
+    assert_eq!(request.headers["x-token"], "abc");
+    assert_eq!(request.headers["content-type"], "application/json");
+    assert_eq!(
+        request.headers["x-flic-signature"],
+        signature("s3cret", &request.body)
+    );
+    assert!(timeout(Duration::from_millis(300), requests.recv())
+        .await
+        .is_err());
+}
+
+#[tokio::test]
+async fn retries_with_backoff() {
+    let mut requests = stand_in(18842, vec![503, 500]).await;
+    let dispatcher = WebhookDispatcher::new(vec![
+        Webhook::new("http://127.0.0.1:18842/").retries(2, Duration::from_millis(50))
+    ])
+    .channels(channels());
+    let mut handler = dispatcher.handler();
+    let runner = dispatcher.clone();
+    tokio::spawn(async move { runner.run().await });
+
+    handler(&click(ClickType::ButtonHold));
+
+    let mut bodies = vec![];
+    for _ in 0..3 {
+        bodies.push(next(&mut requests).await.body);
+    }
+    assert!(bodies.iter().all(|body| *body == bodies[0]));
+    let body: Value = serde_json::from_str(&bodies[0]).unwrap();
+    assert_eq!(body["event"], "button_single_or_double_click_or_hold");
+    assert_eq!(body["bd_addr"], BUTTON);
+}
+
+#[tokio::test]
+async fn queues_while_endpoint_is_down() {
+    let queue = std::env::temp_dir().join("flicbtn-webhook-test.jsonl");
+    let _ = std::fs::remove_file(&queue);
+    let dispatcher = WebhookDispatcher::new(vec![
+        Webhook::new("http://127.0.0.1:18843/").retries(1, Duration::from_millis(10))
+    ])
+    .channels(channels())
+    .persist_to(&queue)
+    .flush_interval(Duration::from_secs(3600));
+    let mut handler = dispatcher.handler();
+    let runner = dispatcher.clone();
+    tokio::spawn(async move { runner.run().await });
+
+    handler(&click(ClickType::ButtonSingleClick));
+    handler(&click(ClickType::ButtonDoubleClick));
+    timeout(Duration::from_secs(10), async {
+        while std::fs::read_to_string(&queue).map_or(0, |q| q.lines().count()) < 2 {
+            tokio::time::delay_for(Duration::from_millis(20)).await;
+        }
+    })
+    .await
+    .expect("deliveries not queued");
+
+    let mut requests = stand_in(18843, vec![]).await;
+    assert_eq!(dispatcher.flush_queue().await, 2);
+    let mut clicks = vec![];
+    for _ in 0..2 {
+        let body: Value = serde_json::from_str(&next(&mut requests).await.body).unwrap();
+        clicks.push(body["click_type"].as_str().unwrap().to_string());
+    }
+    clicks.sort();
+    assert_eq!(clicks, vec!["ButtonDoubleClick", "ButtonSingleClick"]);
+    assert!(!queue.exists());
+}
+
+#[test]
+fn escapes_template_values() {
+    let dispatcher = WebhookDispatcher::new(vec![Webhook::new("http://127.0.0.1:1/hook")
+        .on_event("advertisement_packet")
+        .body(r#"{"name": "{{name}}", "rssi": {{rssi}}}"#)]);
+    let deliveries = dispatcher.deliveries(&Event::AdvertisementPacket(AdvertisementPacket {
+        scan_id: 1,
+        bd_addr: BUTTON.to_string(),
+        name: "F\"lic\\2\n".to_string(),
+        rssi: 200,
+        is_private: false,
+        already_verified: false,
+        already_connected_to_this_device: false,
+        already_connected_to_other_device: false,
+    }));
+    let body: Value = serde_json::from_str(&deliveries[0].body).unwrap();
+    assert_eq!(body["name"], "F\"lic\\2\n");
+    assert_eq!(body["rssi"], 200);
+}